@@ -0,0 +1,205 @@
+//! A line protocol for driving the game from another program over stdin and
+//! stdout. Every command line gets exactly one line back: a status snapshot,
+//! `ok`, or `error: ` followed by what went wrong.
+//!
+//! ```text
+//! place <basic|knockback|heavy|economy|detector> <x> <y>
+//! wall <x> <y>
+//! wave            start the next wave now
+//! tick [n]        advance n ticks, one by default
+//! status
+//! quit
+//! ```
+
+use crate::game::GameState;
+use crate::tower::TowerKind;
+use crate::wave::Phase;
+use nalgebra::Vector2;
+use std::io::{self, BufRead, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    Place(TowerKind, Vector2<usize>),
+    Wall(Vector2<usize>),
+    StartWave,
+    Tick(u32),
+    Status,
+    Quit,
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    let number = |word: &str| {
+        word.parse::<usize>()
+            .map_err(|_| format!("`{}` is not a number", word))
+    };
+    let ticks = |word: &str| {
+        word.parse::<u32>()
+            .map_err(|_| format!("`{}` is not a number of ticks", word))
+    };
+    let cell = |x: &str, y: &str| Ok::<_, String>(Vector2::new(number(x)?, number(y)?));
+    match words.as_slice() {
+        ["place", kind, x, y] => Ok(Command::Place(kind.parse()?, cell(x, y)?)),
+        ["wall", x, y] => Ok(Command::Wall(cell(x, y)?)),
+        ["wave"] => Ok(Command::StartWave),
+        ["tick"] => Ok(Command::Tick(1)),
+        ["tick", n] => Ok(Command::Tick(ticks(n)?)),
+        ["status"] => Ok(Command::Status),
+        ["quit"] => Ok(Command::Quit),
+        [name, ..] => Err(format!("can't make sense of `{}`", name)),
+        [] => Err("empty command".to_string()),
+    }
+}
+
+/// Carries out `command` and returns the line to answer it with.
+pub fn apply(state: &mut GameState, command: Command) -> String {
+    let done = |result: Result<(), String>| match result {
+        Ok(()) => "ok".to_string(),
+        Err(err) => format!("error: {}", err),
+    };
+    match command {
+        Command::Place(kind, pos) => done(
+            state
+                .try_place_tower(kind, pos)
+                .map_err(|err| err.to_string()),
+        ),
+        Command::Wall(pos) => done(state.try_place_wall(pos).map_err(|err| err.to_string())),
+        Command::StartWave => {
+            state.skip_to_next_wave();
+            done(Ok(()))
+        }
+        Command::Tick(n) => {
+            for _ in 0..n {
+                state.advance();
+            }
+            snapshot(state)
+        }
+        Command::Status | Command::Quit => snapshot(state),
+    }
+}
+
+/// The state of the game on one line of `key=value` pairs.
+pub fn snapshot(state: &GameState) -> String {
+    let phase = match state.phase {
+        Phase::Build { ticks_left } => format!("build:{}", ticks_left),
+        Phase::Combat => "combat".to_string(),
+    };
+    let outcome = match state.outcome() {
+        Some(outcome) => format!("{:?}", outcome).to_lowercase(),
+        None => "playing".to_string(),
+    };
+    format!(
+        "tick={} wave={}/{} phase={} money={} lives={} kills={} enemies={} pending={} towers={} outcome={}",
+        state.tick,
+        (state.wave + 1).min(state.total_waves()),
+        state.total_waves(),
+        phase,
+        state.money,
+        state.lives,
+        state.kills,
+        state.enemies.len(),
+        state.pending.len(),
+        state.towers.len(),
+        outcome
+    )
+}
+
+/// Answers commands read from `input` until it runs out or says `quit`.
+pub fn run<R: BufRead, W: Write>(state: &mut GameState, input: R, mut output: W) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match parse(&line) {
+            Ok(Command::Quit) => break,
+            Ok(command) => apply(state, command),
+            Err(err) => format!("error: {}", err),
+        };
+        writeln!(output, "{}", reply)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+    use crate::map::Map;
+
+    fn game() -> GameState {
+        let map = Map::new("#######\n#^    #\n#     #\n#    $#\n#######").unwrap();
+        GameState::new(map, Difficulty::Normal)
+    }
+
+    #[test]
+    fn command_lines_parse_into_commands() {
+        let cell = Vector2::new;
+        assert_eq!(
+            parse("place heavy 2 3"),
+            Ok(Command::Place(TowerKind::Heavy, cell(2, 3)))
+        );
+        assert_eq!(parse("  wall 4 1 "), Ok(Command::Wall(cell(4, 1))));
+        assert_eq!(parse("wave"), Ok(Command::StartWave));
+        assert_eq!(parse("tick"), Ok(Command::Tick(1)));
+        assert_eq!(parse("tick 12"), Ok(Command::Tick(12)));
+        assert_eq!(parse("status"), Ok(Command::Status));
+        assert_eq!(parse("quit"), Ok(Command::Quit));
+        assert!(parse("").is_err());
+        assert!(parse("dance").is_err());
+        assert!(parse("wall one 1").is_err());
+        assert!(parse("place moat 1 1").is_err());
+        assert!(parse("tick 4294967297").is_err());
+    }
+
+    #[test]
+    fn building_far_off_the_map_is_an_error() {
+        let mut state = game();
+        for line in [
+            "wall 4294967298 2",
+            "place basic 4294967298 2",
+            "place heavy 2 18446744073709551615",
+            "place heavy 18446744073709551615 1",
+        ] {
+            let reply = apply(&mut state, parse(line).unwrap());
+            assert_eq!(reply, "error: that doesn't fit on the map", "{}", line);
+        }
+    }
+
+    #[test]
+    fn applied_commands_change_the_game() {
+        let mut state = game();
+        let money = state.money;
+        let basic = TowerKind::Basic;
+        assert_eq!(
+            apply(&mut state, Command::Place(basic, Vector2::new(3, 2))),
+            "ok"
+        );
+        assert_eq!(state.towers.len(), 1);
+        assert_eq!(state.money, money - basic.cost());
+        let reply = apply(&mut state, Command::Place(basic, Vector2::new(0, 0)));
+        assert!(reply.starts_with("error: "));
+
+        assert_eq!(apply(&mut state, Command::StartWave), "ok");
+        assert_eq!(state.phase, Phase::Combat);
+        let reply = apply(&mut state, Command::Tick(3));
+        assert_eq!(state.tick, 3);
+        assert!(reply.starts_with("tick=3 wave=1/"));
+    }
+
+    #[test]
+    fn every_line_gets_one_reply_until_quit() {
+        let mut state = game();
+        let input = "status\n\nbogus\nwall 2 2\nquit\ntick\n";
+        let mut output = Vec::new();
+        run(&mut state, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let replies: Vec<_> = output.lines().collect();
+        assert_eq!(replies.len(), 3);
+        assert!(replies[0].starts_with("tick=0 "));
+        assert!(replies[1].starts_with("error: "));
+        assert_eq!(replies[2], "ok");
+        assert_eq!(state.tick, 0);
+    }
+}
@@ -0,0 +1,89 @@
+use nalgebra::Vector2;
+
+/// Share of the way to where it is headed the camera covers every frame.
+const SCROLL_RATE: f32 = 0.25;
+
+/// Which part of the map is on screen, for maps too big to show whole.
+/// Sizes are `(width, height)` in cells.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Camera {
+    /// Whether the camera keeps `GameState::focus_point` in view by itself.
+    pub follow: bool,
+    /// The cell at the top left of the view, kept fractional so that the
+    /// camera can scroll smoothly.
+    pos: Vector2<f32>,
+}
+
+impl Camera {
+    /// Moves the camera part of the way towards showing `focus` in the
+    /// middle of a `view` sized window on a `map` sized map.
+    pub fn track(&mut self, focus: Vector2<usize>, view: (usize, usize), map: (usize, usize)) {
+        let target = clamp_view(focus, view, map).map(|c| c as f32);
+        self.pos += (target - self.pos) * SCROLL_RATE;
+    }
+
+    /// The cell at the top left of a `view` sized window on a `map` sized
+    /// map, which never reaches past the map's edges.
+    pub fn origin(&self, view: (usize, usize), map: (usize, usize)) -> Vector2<usize> {
+        let axis =
+            |c: f32, v: usize, m: usize| (c.round().max(0.0) as usize).min(m.saturating_sub(v));
+        Vector2::new(
+            axis(self.pos.x, view.0, map.0),
+            axis(self.pos.y, view.1, map.1),
+        )
+    }
+}
+
+/// The top left cell of a `view` sized window on a `map` sized map, placed
+/// with `center` as near its middle as the edges of the map allow.
+pub fn clamp_view(
+    center: Vector2<usize>,
+    view: (usize, usize),
+    map: (usize, usize),
+) -> Vector2<usize> {
+    let axis = |c: usize, v: usize, m: usize| c.saturating_sub(v / 2).min(m.saturating_sub(v));
+    Vector2::new(axis(center.x, view.0, map.0), axis(center.y, view.1, map.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_center_on_the_focus_but_stop_at_the_edges() {
+        let (view, map) = ((10, 6), (40, 20));
+        assert_eq!(
+            clamp_view(Vector2::new(20, 10), view, map),
+            Vector2::new(15, 7)
+        );
+        assert_eq!(
+            clamp_view(Vector2::new(1, 1), view, map),
+            Vector2::new(0, 0)
+        );
+        assert_eq!(
+            clamp_view(Vector2::new(39, 19), view, map),
+            Vector2::new(30, 14)
+        );
+        // A map smaller than the view is shown from its corner.
+        assert_eq!(
+            clamp_view(Vector2::new(3, 3), view, (5, 5)),
+            Vector2::new(0, 0)
+        );
+    }
+
+    #[test]
+    fn the_camera_scrolls_towards_the_focus_without_leaving_the_map() {
+        let (view, map) = ((10, 6), (40, 20));
+        let mut camera = Camera::default();
+        let corner = Vector2::new(39, 19);
+        let mut last = camera.origin(view, map);
+        for _ in 0..50 {
+            camera.track(corner, view, map);
+            let origin = camera.origin(view, map);
+            assert!(origin.x >= last.x && origin.y >= last.y);
+            assert!(origin.x + view.0 <= map.0 && origin.y + view.1 <= map.1);
+            last = origin;
+        }
+        assert_eq!(last, Vector2::new(30, 14));
+    }
+}
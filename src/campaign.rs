@@ -0,0 +1,111 @@
+use crate::error::GameError;
+use crate::game::GameState;
+use crate::map::Map;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A sequence of maps played one after the other. Each level starts afresh
+/// with no towers and full lives; only the score, the enemies killed so far,
+/// carries over.
+///
+/// A campaign file lists one map path per line, relative to the campaign
+/// file itself; blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Campaign {
+    levels: Vec<PathBuf>,
+    /// Index of the level `next_level` loads.
+    next: usize,
+    pub score: u32,
+}
+
+impl Campaign {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Campaign, GameError> {
+        let path = path.as_ref();
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        Campaign::parse(&fs::read_to_string(path)?, base)
+    }
+
+    /// Reads a campaign whose map paths are relative to `base`.
+    pub fn parse(desc: &str, base: &Path) -> Result<Campaign, GameError> {
+        let levels: Vec<_> = desc
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| base.join(line))
+            .collect();
+        if levels.is_empty() {
+            return Err(GameError::Config(
+                "a campaign needs at least one map".to_string(),
+            ));
+        }
+        Ok(Campaign {
+            levels,
+            next: 0,
+            score: 0,
+        })
+    }
+
+    /// Loads the map of the next level. The first call loads the first
+    /// level; once every level has been played there is none left to load.
+    pub fn next_level(&mut self) -> Result<Map, GameError> {
+        let path = match self.levels.get(self.next) {
+            Some(path) => path,
+            None => {
+                return Err(GameError::InvalidState(
+                    "every level of the campaign has been played".to_string(),
+                ))
+            }
+        };
+        let map = Map::from_file(path)?;
+        self.next += 1;
+        Ok(map)
+    }
+
+    /// Adds the score of a finished level.
+    pub fn record(&mut self, state: &GameState) {
+        self.score += state.kills;
+    }
+
+    /// Whether `next_level` has no more levels to load.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.levels.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+    use std::env;
+
+    #[test]
+    fn a_campaign_plays_its_levels_in_order_and_adds_up_the_score() {
+        let dir = env::temp_dir().join(format!("invaders-campaign-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let second = "#####\n#^ $#\n#####";
+        fs::write(dir.join("one.txt"), crate::MAP).unwrap();
+        fs::write(dir.join("two.txt"), second).unwrap();
+        fs::write(dir.join("campaign"), "# two levels\none.txt\n\ntwo.txt\n").unwrap();
+
+        let mut campaign = Campaign::from_file(dir.join("campaign")).unwrap();
+        let first = campaign.next_level().unwrap();
+        assert_eq!(
+            first.fingerprint(),
+            Map::new(crate::MAP).unwrap().fingerprint()
+        );
+        let mut state = GameState::new(first, Difficulty::Normal);
+        state.kills = 3;
+        campaign.record(&state);
+        assert!(!campaign.is_finished());
+
+        let map = campaign.next_level().unwrap();
+        assert_eq!(map.fingerprint(), Map::new(second).unwrap().fingerprint());
+        let mut state = GameState::new(map, Difficulty::Normal);
+        state.kills = 4;
+        campaign.record(&state);
+        assert_eq!(campaign.score, 7);
+        assert!(campaign.is_finished());
+        assert!(campaign.next_level().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use crate::enemy::EnemyKind;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// How many enemies a wave holds relative to `Normal`.
+    pub fn wave_scale(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.75,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.5,
+        }
+    }
+
+    pub fn wave_count(self) -> usize {
+        match self {
+            Difficulty::Easy => 8,
+            Difficulty::Normal => 10,
+            Difficulty::Hard => 12,
+        }
+    }
+
+    /// Whether towers are kept off the cells enemies walk along, so they
+    /// have to be built beside the path.
+    pub fn no_build_on_path(self) -> bool {
+        self == Difficulty::Hard
+    }
+
+    /// The most towers that can stand at once, unless the economy says
+    /// otherwise. Only hard sets a cap.
+    pub fn max_towers(self) -> Option<u32> {
+        match self {
+            Difficulty::Hard => Some(20),
+            _ => None,
+        }
+    }
+
+    /// Lives given back for every wave cleared, unless the economy says
+    /// otherwise. Only easy heals.
+    pub fn wave_heal(self) -> u32 {
+        match self {
+            Difficulty::Easy => 2,
+            _ => 0,
+        }
+    }
+
+    /// Lives lost when an enemy of `kind` reaches a destination. Easy forgives
+    /// the tougher kinds, hard doubles the cost of every leak.
+    pub fn leak_damage(self, kind: EnemyKind) -> u32 {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal => kind.leak_damage(),
+            Difficulty::Hard => 2 * kind.leak_damage(),
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("unknown difficulty `{}`", s)),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use nalgebra::Vector2;
+
+/// A step on the grid. `y` grows downwards, so `North` is `(0, -1)`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl Direction {
+    pub const CARDINAL: [Direction; 4] = [
+        Direction::South,
+        Direction::East,
+        Direction::West,
+        Direction::North,
+    ];
+
+    pub const ALL: [Direction; 8] = [
+        Direction::South,
+        Direction::East,
+        Direction::North,
+        Direction::West,
+        Direction::SouthEast,
+        Direction::NorthEast,
+        Direction::NorthWest,
+        Direction::SouthWest,
+    ];
+
+    pub const fn to_offset(self) -> Vector2<i32> {
+        match self {
+            Direction::North => Vector2::new(0, -1),
+            Direction::South => Vector2::new(0, 1),
+            Direction::East => Vector2::new(1, 0),
+            Direction::West => Vector2::new(-1, 0),
+            Direction::NorthEast => Vector2::new(1, -1),
+            Direction::NorthWest => Vector2::new(-1, -1),
+            Direction::SouthEast => Vector2::new(1, 1),
+            Direction::SouthWest => Vector2::new(-1, 1),
+        }
+    }
+
+    /// The cardinal direction from `from` that gets closest to pointing at
+    /// `to`, going by whichever axis it is further off along, and east or
+    /// west when that is a tie. `None` if the two are the same cell.
+    pub fn facing(from: Vector2<usize>, to: Vector2<usize>) -> Option<Direction> {
+        let d = to.map(|c| c as i64) - from.map(|c| c as i64);
+        if d.x == 0 && d.y == 0 {
+            None
+        } else if d.x.abs() >= d.y.abs() {
+            Some(if d.x > 0 {
+                Direction::East
+            } else {
+                Direction::West
+            })
+        } else {
+            Some(if d.y > 0 {
+                Direction::South
+            } else {
+                Direction::North
+            })
+        }
+    }
+
+    /// An arrow pointing this way, diagonals rounded off to east or west.
+    pub const fn arrow(self) -> &'static str {
+        match self {
+            Direction::North => "^",
+            Direction::South => "v",
+            Direction::East => ">",
+            Direction::West => "<",
+            Direction::NorthEast | Direction::SouthEast => ">",
+            Direction::NorthWest | Direction::SouthWest => "<",
+        }
+    }
+
+    pub const fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+            Direction::NorthEast => Direction::SouthWest,
+            Direction::NorthWest => Direction::SouthEast,
+            Direction::SouthEast => Direction::NorthWest,
+            Direction::SouthWest => Direction::NorthEast,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opposite_directions_have_opposite_offsets() {
+        for d in Direction::ALL {
+            assert_eq!(d.opposite().to_offset(), -d.to_offset());
+            assert_eq!(d.opposite().opposite(), d);
+        }
+    }
+
+    #[test]
+    fn every_direction_is_one_step_to_a_distinct_neighbor() {
+        for (i, a) in Direction::ALL.iter().enumerate() {
+            let offset = a.to_offset();
+            assert_eq!(offset.x.abs().max(offset.y.abs()), 1);
+            for b in Direction::ALL[i + 1..].iter() {
+                assert_ne!(offset, b.to_offset());
+            }
+        }
+        for d in Direction::CARDINAL {
+            assert_eq!(d.to_offset().abs().sum(), 1);
+        }
+    }
+}
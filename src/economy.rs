@@ -0,0 +1,185 @@
+use crate::error::GameError;
+use std::fs;
+use std::path::Path;
+
+/// Starting resources and reward tuning for a game.
+///
+/// An economy file is a list of `key = value` lines; blank lines and lines
+/// starting with `#` are ignored, and any key that is left out keeps its
+/// default value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Economy {
+    pub starting_money: u32,
+    pub starting_lives: u32,
+    /// When set, lives are replaced by a pool of this much base hp, and an
+    /// enemy that gets through knocks off as much as it has hp left.
+    pub base_hp: Option<u32>,
+    /// Money awarded for clearing the first wave.
+    pub wave_bonus: u32,
+    /// Factor the wave bonus is multiplied by for every wave after the first.
+    pub wave_bonus_growth: f64,
+    /// Factor applied to the bounty of every killed enemy.
+    pub kill_reward_multiplier: f64,
+    /// How many enemies can be on the map before the defense starts to be
+    /// overwhelmed.
+    pub soft_cap: u32,
+    /// Lives lost per tick for every enemy over `soft_cap`. Fractions add
+    /// up from one tick to the next.
+    pub overflow_drain: f64,
+    /// Money paid for every tick of the build phase cut short by starting
+    /// the next wave early.
+    pub rush_bonus: f64,
+    /// When set, the most towers that can stand at once, in place of the
+    /// difficulty's `max_towers`.
+    pub max_towers: Option<u32>,
+    /// When set, lives given back for every wave cleared in place of the
+    /// difficulty's `wave_heal`.
+    pub wave_heal: Option<u32>,
+    /// When set, the most lives healing between waves can bring the game
+    /// back up to, in place of what it started with.
+    pub heal_cap: Option<u32>,
+}
+
+impl Default for Economy {
+    fn default() -> Economy {
+        Economy {
+            starting_money: 100,
+            starting_lives: 20,
+            base_hp: None,
+            wave_bonus: 25,
+            wave_bonus_growth: 1.0,
+            kill_reward_multiplier: 1.0,
+            soft_cap: 40,
+            overflow_drain: 0.05,
+            rush_bonus: 1.0,
+            max_towers: None,
+            wave_heal: None,
+            heal_cap: None,
+        }
+    }
+}
+
+impl Economy {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Economy, GameError> {
+        Economy::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(desc: &str) -> Result<Economy, GameError> {
+        let bad = GameError::Config;
+        let mut economy = Economy::default();
+        for (n, line) in desc.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(bad(format!("line {}: expected `key = value`", n + 1))),
+            };
+            let value: f64 = value
+                .parse()
+                .map_err(|_| bad(format!("line {}: `{}` is not a number", n + 1, value)))?;
+            if !value.is_finite() || value < 0.0 {
+                return Err(bad(format!(
+                    "line {}: `{}` must be non-negative",
+                    n + 1,
+                    key
+                )));
+            }
+            match key {
+                "starting_money" => economy.starting_money = whole(n, key, value)?,
+                "starting_lives" => economy.starting_lives = whole(n, key, value)?,
+                "base_hp" => economy.base_hp = Some(whole(n, key, value)?),
+                "wave_bonus" => economy.wave_bonus = whole(n, key, value)?,
+                "wave_bonus_growth" => economy.wave_bonus_growth = value,
+                "kill_reward_multiplier" => economy.kill_reward_multiplier = value,
+                "soft_cap" => economy.soft_cap = whole(n, key, value)?,
+                "overflow_drain" => economy.overflow_drain = value,
+                "rush_bonus" => economy.rush_bonus = value,
+                "max_towers" => economy.max_towers = Some(whole(n, key, value)?),
+                "wave_heal" => economy.wave_heal = Some(whole(n, key, value)?),
+                "heal_cap" => economy.heal_cap = Some(whole(n, key, value)?),
+                _ => return Err(bad(format!("line {}: unknown key `{}`", n + 1, key))),
+            }
+        }
+        Ok(economy)
+    }
+
+    /// The bonus for clearing wave number `wave`, counting from zero.
+    pub fn wave_bonus(&self, wave: usize) -> u32 {
+        (self.wave_bonus as f64 * self.wave_bonus_growth.powi(wave as i32)).round() as u32
+    }
+
+    /// What the game starts with in lives, or in base hp if it has any.
+    pub fn starting_lives(&self) -> u32 {
+        self.base_hp.unwrap_or(self.starting_lives)
+    }
+
+    /// Lives lost in a tick with `enemies` on the map, as a fraction.
+    pub fn overflow(&self, enemies: usize) -> f64 {
+        enemies.saturating_sub(self.soft_cap as usize) as f64 * self.overflow_drain
+    }
+
+    /// Money for starting a wave with `ticks_left` of the build phase to go.
+    pub fn rush_reward(&self, ticks_left: u32) -> u32 {
+        (ticks_left as f64 * self.rush_bonus).round() as u32
+    }
+
+    pub fn kill_reward(&self, bounty: u32) -> u32 {
+        (bounty as f64 * self.kill_reward_multiplier).round() as u32
+    }
+}
+
+fn whole(n: usize, key: &str, value: f64) -> Result<u32, GameError> {
+    if value.fract() != 0.0 || value > u32::MAX as f64 {
+        return Err(GameError::Config(format!(
+            "line {}: `{}` must be a whole number",
+            n + 1,
+            key
+        )));
+    }
+    Ok(value as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+    use crate::game::GameState;
+    use crate::map::Map;
+    use crate::wave::Phase;
+
+    #[test]
+    fn a_custom_economy_sets_what_a_game_starts_with() {
+        let economy = Economy::parse(
+            "# a lean start\nstarting_money = 40\nstarting_lives = 5\n\nwave_bonus = 10\n",
+        )
+        .unwrap();
+        assert_eq!(economy.starting_money, 40);
+        assert_eq!(economy.kill_reward_multiplier, 1.0);
+        let state = GameState::new(Map::new(crate::MAP).unwrap(), Difficulty::Normal)
+            .with_economy(&economy);
+        assert_eq!((state.money, state.lives), (40, 5));
+    }
+
+    #[test]
+    fn wave_bonuses_grow_as_configured_and_are_paid_on_clearing() {
+        let economy = Economy::parse("wave_bonus = 10\nwave_bonus_growth = 1.5").unwrap();
+        let bonuses: Vec<_> = (0..3).map(|wave| economy.wave_bonus(wave)).collect();
+        assert_eq!(bonuses, vec![10, 15, 23]);
+
+        let mut state = GameState::new(Map::new(crate::MAP).unwrap(), Difficulty::Normal)
+            .with_economy(&economy);
+        state.phase = Phase::Combat;
+        let money = state.money;
+        state.advance();
+        assert_eq!(state.money, money + 10);
+    }
+
+    #[test]
+    fn negative_and_fractional_counts_are_rejected() {
+        assert!(Economy::parse("starting_money = -5").is_err());
+        assert!(Economy::parse("starting_lives = 2.5").is_err());
+        assert!(Economy::parse("kill_reward_multiplier = 0.5").is_ok());
+    }
+}
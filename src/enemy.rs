@@ -0,0 +1,465 @@
+use crate::slots::Handle;
+use nalgebra::Vector2;
+use std::collections::VecDeque;
+
+/// Ticks a breaker spends hammering on a wall before it gives way.
+pub const BREAK_TICKS: u32 = 3;
+/// How many walls a single breaker can knock down over its lifetime.
+pub const MAX_WALLS_BROKEN: u32 = 2;
+/// Ticks a regenerating enemy must go unhurt before it starts to heal.
+pub const REGEN_DELAY: u32 = 2;
+/// How many times a splitter's line can split: its children split again, but
+/// their children don't.
+pub const MAX_SPLITS: u32 = 2;
+/// Children a splitter leaves behind when it is killed.
+pub const SPLIT_CHILDREN: usize = 2;
+/// Length of a cloaked enemy's cycle, in ticks.
+pub const CLOAK_CYCLE: u32 = 6;
+/// Ticks at the end of each cycle a cloaked enemy can't be targeted.
+pub const CLOAK_TICKS: u32 = 3;
+/// Damage a shield soaks up for the allies around it before it is spent.
+pub const SHIELD_POOL: u32 = 20;
+/// How many steps away, counted along the grid, a shield covers allies.
+pub const SHIELD_RANGE: usize = 2;
+/// Hp a rusher knocks off a tower for every tick it spends attacking it.
+pub const RUSH_DAMAGE: u32 = 3;
+/// Cells an enemy's trail reaches back.
+pub const TRAIL_LEN: usize = 3;
+/// Share of its hp below which an enemy panics, when panicking is on.
+pub const PANIC_HP: f32 = 0.25;
+/// What a panicking enemy's speed is multiplied by.
+pub const PANIC_SPEEDUP: f32 = 1.5;
+/// Ticks an evasive enemy remembers a cell it was hurt on.
+pub const DANGER_TICKS: u32 = 20;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EnemyKind {
+    Basic,
+    /// Knocks down an adjacent wall when no destination can be reached.
+    Breaker,
+    /// Heals a little every tick it goes unhurt.
+    Regen,
+    /// Splits into two weaker splitters when killed.
+    Splitter,
+    /// Can't be targeted for part of every `CLOAK_CYCLE` ticks.
+    Cloaked,
+    /// Rebuilds walls that breakers knocked down, as long as that leaves a
+    /// way through.
+    Mason,
+    /// Soaks up damage meant for the allies around it out of a shared pool.
+    Shield,
+    /// Goes for the nearest tower and attacks it until it falls, heading
+    /// for a destination only once there are no towers left to reach.
+    Rusher,
+    /// Moves in any of eight directions, cutting straight across open
+    /// ground along the route the others would take.
+    Glider,
+    /// Steers clear of the cells it was hurt on lately.
+    Evasive,
+    /// Leads the sudden-death wave: slow, very tough and costly to let
+    /// through. Never part of an ordinary wave.
+    Boss,
+}
+
+impl EnemyKind {
+    pub const ALL: [EnemyKind; 11] = [
+        EnemyKind::Basic,
+        EnemyKind::Breaker,
+        EnemyKind::Regen,
+        EnemyKind::Splitter,
+        EnemyKind::Cloaked,
+        EnemyKind::Mason,
+        EnemyKind::Shield,
+        EnemyKind::Rusher,
+        EnemyKind::Glider,
+        EnemyKind::Evasive,
+        EnemyKind::Boss,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EnemyKind::Basic => "basic",
+            EnemyKind::Breaker => "breaker",
+            EnemyKind::Regen => "regen",
+            EnemyKind::Splitter => "splitter",
+            EnemyKind::Cloaked => "cloaked",
+            EnemyKind::Mason => "mason",
+            EnemyKind::Shield => "shield",
+            EnemyKind::Rusher => "rusher",
+            EnemyKind::Glider => "glider",
+            EnemyKind::Evasive => "evasive",
+            EnemyKind::Boss => "boss",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            EnemyKind::Basic => "*",
+            EnemyKind::Breaker => "B",
+            EnemyKind::Regen => "R",
+            EnemyKind::Splitter => "S",
+            EnemyKind::Cloaked => "C",
+            EnemyKind::Mason => "M",
+            EnemyKind::Shield => "O",
+            EnemyKind::Rusher => "X",
+            EnemyKind::Glider => "G",
+            EnemyKind::Evasive => "V",
+            EnemyKind::Boss => "@",
+        }
+    }
+
+    pub fn max_hp(self) -> u32 {
+        match self {
+            EnemyKind::Basic => 10,
+            EnemyKind::Breaker => 20,
+            EnemyKind::Regen => 15,
+            EnemyKind::Splitter => 16,
+            EnemyKind::Cloaked => 12,
+            EnemyKind::Mason => 14,
+            EnemyKind::Shield => 12,
+            EnemyKind::Rusher => 14,
+            EnemyKind::Glider => 10,
+            EnemyKind::Evasive => 12,
+            EnemyKind::Boss => 200,
+        }
+    }
+
+    /// Cells moved per tick, on average.
+    pub fn speed(self) -> f32 {
+        match self {
+            EnemyKind::Breaker | EnemyKind::Boss => 0.5,
+            _ => 1.0,
+        }
+    }
+
+    /// Hp recovered per unhurt tick.
+    pub fn regen(self) -> u32 {
+        match self {
+            EnemyKind::Regen => 1,
+            _ => 0,
+        }
+    }
+
+    /// Index, among the map's destinations in row-major order, of the one
+    /// enemies of this kind head for instead of the nearest. Breakers always
+    /// go for the first, battering their way through if they must.
+    pub fn dest_affinity(self) -> Option<usize> {
+        match self {
+            EnemyKind::Breaker => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Lives lost when an enemy of this kind reaches a destination, before
+    /// the difficulty is taken into account.
+    pub fn leak_damage(self) -> u32 {
+        match self {
+            EnemyKind::Breaker => 3,
+            EnemyKind::Boss => 5,
+            _ => 1,
+        }
+    }
+
+    /// Money paid out when an enemy of this kind is killed.
+    pub fn bounty(self) -> u32 {
+        match self {
+            EnemyKind::Basic => 5,
+            EnemyKind::Breaker => 10,
+            EnemyKind::Regen => 8,
+            EnemyKind::Splitter => 4,
+            EnemyKind::Cloaked => 7,
+            EnemyKind::Mason => 6,
+            EnemyKind::Shield => 9,
+            EnemyKind::Rusher => 8,
+            EnemyKind::Glider => 6,
+            EnemyKind::Evasive => 7,
+            EnemyKind::Boss => 50,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Breaking {
+    pub wall: Vector2<usize>,
+    pub ticks_left: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enemy {
+    pub id: u64,
+    pub pos: Vector2<usize>,
+    /// Where the enemy was at the start of the last tick.
+    pub prev_pos: Vector2<usize>,
+    pub kind: EnemyKind,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub speed: f32,
+    /// Movement carried over from earlier ticks, always below one cell.
+    pub progress: f32,
+    pub ticks_since_hit: u32,
+    pub breaking: Option<Breaking>,
+    pub walls_broken: u32,
+    /// Index of the wave this enemy came in with.
+    pub wave: usize,
+    /// The destination this enemy is after, as an index into the map's
+    /// destinations, or `None` for whichever is nearest.
+    pub target_dest: Option<usize>,
+    /// How many splits this enemy is descended from.
+    pub splits: u32,
+    /// Ticks since the enemy spawned.
+    pub age: u32,
+    /// The cells the enemy was on before, most recent first, at most
+    /// `TRAIL_LEN` of them.
+    pub trail: VecDeque<Vector2<usize>>,
+    /// Damage a shield can still soak up; always zero for other kinds.
+    pub shield_pool: u32,
+    /// The shield covering this enemy this tick, if any.
+    pub shielded_by: Option<Handle>,
+    /// The corners of the straightened route a glider is following, the
+    /// next one first.
+    pub waypoints: Vec<Vector2<usize>>,
+    /// The tick `waypoints` were last worked out on, if ever.
+    pub routed_at: Option<u64>,
+    /// The map generation `waypoints` were worked out against, so that a
+    /// route the map has changed under since is known to be stale.
+    pub routed_generation: Option<u64>,
+    /// The first tick shots can hurt this enemy again after the last one
+    /// that did.
+    pub invuln_until: u64,
+    /// Cells an evasive enemy was hurt on, each with the ticks left before
+    /// it forgets about it.
+    pub danger: Vec<(Vector2<usize>, u32)>,
+    /// Whether a detector has this enemy in range this tick, so that its
+    /// cloak doesn't hide it.
+    pub revealed: bool,
+}
+
+impl Enemy {
+    pub fn new(id: u64, kind: EnemyKind, pos: Vector2<usize>, wave: usize) -> Self {
+        Enemy {
+            id,
+            pos,
+            prev_pos: pos,
+            kind,
+            hp: kind.max_hp(),
+            max_hp: kind.max_hp(),
+            speed: kind.speed(),
+            progress: 0.0,
+            ticks_since_hit: 0,
+            breaking: None,
+            walls_broken: 0,
+            wave,
+            target_dest: kind.dest_affinity(),
+            splits: 0,
+            age: 0,
+            trail: VecDeque::new(),
+            shield_pool: match kind {
+                EnemyKind::Shield => SHIELD_POOL,
+                _ => 0,
+            },
+            shielded_by: None,
+            waypoints: Vec::new(),
+            routed_at: None,
+            routed_generation: None,
+            invuln_until: 0,
+            danger: Vec::new(),
+            revealed: false,
+        }
+    }
+
+    /// Whether towers can't see this enemy right now. It still moves and
+    /// leaks as usual, and shots already on their way still hit it.
+    pub fn is_cloaked(&self) -> bool {
+        self.kind == EnemyKind::Cloaked
+            && self.age % CLOAK_CYCLE >= CLOAK_CYCLE - CLOAK_TICKS
+            && !self.revealed
+    }
+
+    /// Whether killing this enemy leaves children behind.
+    pub fn can_split(&self) -> bool {
+        self.kind == EnemyKind::Splitter && self.splits < MAX_SPLITS
+    }
+
+    /// One of the children a splitter leaves where it died: the same kind,
+    /// with half its maximum hp, heading the same way.
+    pub fn split_child(&self, id: u64) -> Enemy {
+        let mut child = Enemy::new(id, self.kind, self.pos, self.wave);
+        child.max_hp = (self.max_hp / 2).max(1);
+        child.hp = child.max_hp;
+        child.target_dest = self.target_dest;
+        child.splits = self.splits + 1;
+        child
+    }
+
+    /// Moves the enemy to `to`, remembering where it was in its trail.
+    pub fn step_to(&mut self, to: Vector2<usize>) {
+        if to == self.pos {
+            return;
+        }
+        self.trail.push_front(self.pos);
+        self.trail.truncate(TRAIL_LEN);
+        self.pos = to;
+    }
+
+    /// The cell to draw the enemy on when a frame falls `alpha` of the way,
+    /// from 0 to 1, between the last tick and the next. Only the cells it
+    /// walked through on the way from `prev_pos` are ever drawn, so it never
+    /// shows up inside a wall it went round. If the way there has dropped
+    /// out of the trail, it is drawn on one end or the other.
+    pub fn render_pos(&self, alpha: f32) -> Vector2<usize> {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let mut path = match self.trail.iter().position(|&c| c == self.prev_pos) {
+            Some(i) if self.prev_pos != self.pos => self.trail.range(..=i).rev().copied().collect(),
+            _ => vec![self.prev_pos],
+        };
+        path.push(self.pos);
+        path[(alpha * (path.len() - 1) as f32).round() as usize]
+    }
+
+    /// Adds one tick's worth of movement, at `multiplier` times the enemy's
+    /// speed, and returns how many whole cells the enemy gets to move this
+    /// tick, keeping the remainder for later.
+    pub fn take_steps(&mut self, multiplier: f32) -> u32 {
+        self.progress += self.speed * multiplier;
+        let steps = self.progress.floor();
+        self.progress -= steps;
+        steps as u32
+    }
+
+    /// Whether the enemy is down to less than `PANIC_HP` of its hp.
+    pub fn is_desperate(&self) -> bool {
+        (self.hp as f32) < self.max_hp as f32 * PANIC_HP
+    }
+
+    /// Whether shots landing on `tick` do nothing to this enemy.
+    pub fn is_invulnerable(&self, tick: u64) -> bool {
+        tick < self.invuln_until
+    }
+
+    pub fn take_damage(&mut self, damage: u32) {
+        self.hp = self.hp.saturating_sub(damage);
+        self.ticks_since_hit = 0;
+        if self.kind == EnemyKind::Evasive {
+            let pos = self.pos;
+            self.danger.retain(|&(cell, _)| cell != pos);
+            self.danger.push((pos, DANGER_TICKS));
+        }
+    }
+
+    /// Called once per tick: lets the cells an evasive enemy was hurt on
+    /// slip its memory one tick further.
+    pub fn fade_danger(&mut self) {
+        for (_, ticks_left) in self.danger.iter_mut() {
+            *ticks_left -= 1;
+        }
+        self.danger.retain(|&(_, ticks_left)| ticks_left > 0);
+    }
+
+    /// The cells this enemy is keeping away from.
+    pub fn danger_cells(&self) -> Vec<Vector2<usize>> {
+        self.danger.iter().map(|&(cell, _)| cell).collect()
+    }
+
+    /// Called once per tick after combat: heals regenerating enemies that
+    /// have gone unhurt for `REGEN_DELAY` ticks, never past `max_hp`.
+    pub fn regenerate(&mut self) {
+        if self.hp == 0 {
+            return;
+        }
+        if self.ticks_since_hit >= REGEN_DELAY {
+            self.hp = (self.hp + self.kind.regen()).min(self.max_hp);
+        }
+        self.ticks_since_hit = self.ticks_since_hit.saturating_add(1);
+    }
+
+    pub fn can_break_walls(&self) -> bool {
+        self.kind == EnemyKind::Breaker && self.walls_broken < MAX_WALLS_BROKEN
+    }
+
+    /// Whether this enemy covers allies within `SHIELD_RANGE`.
+    pub fn has_shield(&self) -> bool {
+        self.kind == EnemyKind::Shield && self.shield_pool > 0
+    }
+
+    pub fn attacks_towers(&self) -> bool {
+        self.kind == EnemyKind::Rusher
+    }
+
+    pub fn can_repair_walls(&self) -> bool {
+        self.kind == EnemyKind::Mason
+    }
+
+    /// Whether this enemy moves along waypoints instead of cell by cell.
+    pub fn moves_freely(&self) -> bool {
+        self.kind == EnemyKind::Glider
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regenerating_enemies_heal_while_left_alone_up_to_their_max() {
+        let mut enemy = Enemy::new(0, EnemyKind::Regen, Vector2::new(1, 1), 0);
+        let max = enemy.max_hp;
+        enemy.take_damage(4);
+        for _ in 0..REGEN_DELAY {
+            enemy.regenerate();
+        }
+        assert_eq!(enemy.hp, max - 4);
+        enemy.regenerate();
+        assert_eq!(enemy.hp, max - 3);
+        for _ in 0..10 {
+            enemy.regenerate();
+        }
+        assert_eq!(enemy.hp, max);
+    }
+
+    #[test]
+    fn regenerating_enemies_dont_heal_while_being_hit_or_once_dead() {
+        let mut enemy = Enemy::new(0, EnemyKind::Regen, Vector2::new(1, 1), 0);
+        let max = enemy.max_hp;
+        for _ in 0..5 {
+            enemy.take_damage(1);
+            enemy.regenerate();
+        }
+        assert_eq!(enemy.hp, max - 5);
+        enemy.take_damage(max);
+        for _ in 0..10 {
+            enemy.regenerate();
+        }
+        assert_eq!(enemy.hp, 0);
+    }
+
+    #[test]
+    fn the_trail_holds_the_last_cells_visited_newest_first() {
+        let mut enemy = Enemy::new(0, EnemyKind::Basic, Vector2::new(0, 0), 0);
+        for x in 1..=5 {
+            enemy.step_to(Vector2::new(x, 0));
+        }
+        // Standing still leaves the trail as it is.
+        enemy.step_to(Vector2::new(5, 0));
+        assert_eq!(enemy.trail.len(), TRAIL_LEN);
+        let xs: Vec<_> = enemy.trail.iter().map(|c| c.x).collect();
+        assert_eq!(xs, [4, 3, 2]);
+    }
+
+    #[test]
+    fn between_ticks_enemies_are_drawn_on_a_cell_of_the_way_they_walked() {
+        let mut enemy = Enemy::new(0, EnemyKind::Basic, Vector2::new(1, 1), 0);
+        enemy.prev_pos = enemy.pos;
+        enemy.step_to(Vector2::new(2, 1));
+        let ends = [enemy.prev_pos, enemy.pos];
+        assert_eq!(enemy.render_pos(0.0), ends[0]);
+        assert_eq!(enemy.render_pos(1.0), ends[1]);
+        assert!(ends.contains(&enemy.render_pos(0.5)));
+
+        // Round a corner in one tick: the corner is drawn, never the
+        // diagonal shortcut.
+        enemy.prev_pos = enemy.pos;
+        enemy.step_to(Vector2::new(3, 1));
+        enemy.step_to(Vector2::new(3, 2));
+        assert_eq!(enemy.render_pos(0.5), Vector2::new(3, 1));
+    }
+}
@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// What can go wrong loading a game's pieces or driving it.
+#[derive(Debug)]
+pub enum GameError {
+    Io(io::Error),
+    /// A map that could not be read, with why.
+    MapParse(String),
+    /// Something was asked of the game that its current state doesn't allow.
+    InvalidState(String),
+    /// An economy, theme, campaign or high score file that could not be
+    /// read, with why.
+    Config(String),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::Io(err) => write!(f, "{}", err),
+            GameError::MapParse(why) => write!(f, "bad map: {}", why),
+            GameError::InvalidState(why) | GameError::Config(why) => f.write_str(why),
+        }
+    }
+}
+
+impl Error for GameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GameError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GameError {
+    fn from(err: io::Error) -> GameError {
+        GameError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::Campaign;
+    use crate::difficulty::Difficulty;
+    use crate::economy::Economy;
+    use crate::game::GameState;
+    use crate::highscores::HighScores;
+    use crate::map::Map;
+    use crate::theme::Theme;
+    use std::path::Path;
+
+    #[test]
+    fn a_missing_file_is_an_io_error() {
+        let err = Map::from_file("/nonexistent/map.txt").err().unwrap();
+        assert!(matches!(err, GameError::Io(_)));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn a_bad_map_is_a_parse_error() {
+        let err = Map::new("#^?$#").err().unwrap();
+        assert!(matches!(err, GameError::MapParse(_)));
+        assert!(err.to_string().starts_with("bad map: "));
+    }
+
+    #[test]
+    fn asking_too_much_of_the_game_is_an_invalid_state() {
+        let map = Map::new(crate::MAP).unwrap();
+        let mut state = GameState::new(map, Difficulty::Normal);
+        let err = state.set_active_spawns(0, vec![5]).err().unwrap();
+        assert!(matches!(err, GameError::InvalidState(_)));
+        let dir = std::env::temp_dir();
+        let name = format!("ti-level-{}.txt", std::process::id());
+        std::fs::write(dir.join(&name), crate::MAP).unwrap();
+        let mut campaign = Campaign::parse(&name, &dir).unwrap();
+        let first = campaign.next_level();
+        std::fs::remove_file(dir.join(&name)).unwrap();
+        assert!(first.is_ok());
+        assert!(matches!(
+            campaign.next_level(),
+            Err(GameError::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    fn bad_settings_files_are_config_errors() {
+        let config = |result: Result<(), GameError>| match result {
+            Err(GameError::Config(why)) => why,
+            other => panic!("expected a config error, got {:?}", other),
+        };
+        assert_eq!(
+            config(Economy::parse("starting_money = lots").map(drop)),
+            "line 1: `lots` is not a number"
+        );
+        assert_eq!(
+            config(Theme::parse("\nwall = plaid").map(drop)),
+            "line 2: `plaid` is not a color"
+        );
+        assert_eq!(
+            config(Campaign::parse("# nothing\n", Path::new("")).map(drop)),
+            "a campaign needs at least one map"
+        );
+        assert_eq!(
+            config(HighScores::parse("5eed = ten 3").map(drop)),
+            "line 1: expected `fingerprint = score wave`"
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use nalgebra::Vector2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Halves the speed of every enemy that starts a tick on it.
+    Slow,
+}
+
+impl FieldKind {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            FieldKind::Slow => ":",
+        }
+    }
+
+    pub fn cost(self) -> u32 {
+        match self {
+            FieldKind::Slow => 5,
+        }
+    }
+
+    /// Ticks the field lasts once laid down.
+    pub fn ttl(self) -> u32 {
+        match self {
+            FieldKind::Slow => 30,
+        }
+    }
+
+    /// What the speed of an enemy standing on the field is multiplied by.
+    pub fn speed_factor(self) -> f32 {
+        match self {
+            FieldKind::Slow => 0.5,
+        }
+    }
+}
+
+/// A tile the player laid down on open ground, in force until `ttl` runs
+/// out. Fields never get in the way: enemies walk over them as over any
+/// other ground.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Field {
+    pub pos: Vector2<usize>,
+    pub kind: FieldKind,
+    /// Ticks left before it wears off.
+    pub ttl: u32,
+}
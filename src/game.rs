@@ -0,0 +1,3974 @@
+use crate::camera::Camera;
+use crate::date::Date;
+use crate::difficulty::Difficulty;
+use crate::direction::Direction;
+use crate::economy::Economy;
+use crate::enemy::{
+    Breaking, Enemy, EnemyKind, BREAK_TICKS, PANIC_SPEEDUP, RUSH_DAMAGE, SHIELD_RANGE,
+    SPLIT_CHILDREN, TRAIL_LEN,
+};
+use crate::error::GameError;
+use crate::field::{Field, FieldKind};
+use crate::map::{self, Map, Square};
+use crate::path::{
+    cheapest_route, distance_field, evasive_step, pf_random, pf_search, reaches, string_pull,
+    threat_step, trace_route, walkable, FlowFields, Pathfinder,
+};
+use crate::pickup::{roll_loot, PickUp, PickUpKind, BOOST_TICKS, PICKUP_MONEY, PICKUP_TTL};
+use crate::slots::{Handle, Slots};
+use crate::theme::Theme;
+use crate::tower::{
+    footprint_cells, PlaceError, Projectile, Tower, TowerKind, FIRE_ANIM_TICKS, PROJECTILE_SPEED,
+};
+use crate::wave::{ramped_hp, Formation, Phase, Pressure, Wave, BUILD_TICKS};
+use nalgebra::{DMatrix, Vector2};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Modifier, Style};
+use tui::widgets::{Block, Borders, Gauge, StatefulWidget, Widget};
+
+/// A threat field along with what it was computed from.
+struct ThreatCache {
+    generation: u64,
+    towers: Vec<(TowerKind, Vector2<usize>)>,
+    field: DMatrix<f32>,
+}
+
+/// Overlays drawn on the map, each along with what it was computed from, so
+/// that the frames drawn between two ticks don't work them out again.
+#[derive(Default)]
+struct OverlayCache {
+    chokepoints: Option<(u64, Vec<Vector2<usize>>)>,
+    /// Keyed by map generation and tick, since enemies only move on ticks.
+    path_heat: Option<((u64, u64), DMatrix<u32>)>,
+    placement: Option<(PlacementKey, Option<PlaceError>)>,
+    /// The cells of the routes from the spawn points, by map generation.
+    route: Option<(u64, HashSet<Vector2<usize>>)>,
+}
+
+/// The map generation, tick, cursor and tower being placed a placement
+/// preview was worked out for.
+type PlacementKey = (u64, u64, Vector2<usize>, TowerKind);
+
+/// Where the map went on screen in the last frame drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Viewport {
+    /// The screen cells the map was drawn into, inside any border.
+    map_area: Rect,
+    /// The map cell drawn at the top left of `map_area`.
+    origin: Vector2<usize>,
+}
+
+/// An enemy about to spawn, shown on its spawn cell as a countdown first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Telegraph {
+    pub kind: EnemyKind,
+    pub pos: Vector2<usize>,
+    /// The hp it spawns with, ramp included.
+    pub hp: u32,
+    /// Ticks left before it spawns.
+    pub ticks_left: u32,
+}
+
+/// See `GameState::placement_preview`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PlacementInfo {
+    pub kind: TowerKind,
+    pub cost: u32,
+    pub affordable: bool,
+    /// Why the cursor cell can't be built on, if it can't.
+    pub problem: Option<PlaceError>,
+}
+
+impl PlacementInfo {
+    pub fn is_possible(&self) -> bool {
+        self.affordable && self.problem.is_none()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Lose,
+}
+
+/// What happened during one tick, as `GameState::advance` reports it.
+/// Enemies are given by where they were and what kind they were.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickReport {
+    /// Enemies that came onto the map, split children included.
+    pub spawned: Vec<(Vector2<usize>, EnemyKind)>,
+    pub killed: Vec<(Vector2<usize>, EnemyKind)>,
+    /// Enemies that reached a destination, where they reached it.
+    pub leaked: Vec<(Vector2<usize>, EnemyKind)>,
+    pub shots_fired: u32,
+    pub money_earned: u32,
+}
+
+impl TickReport {
+    /// A line for the HUD on the enemies killed and let through and the
+    /// money earned, or `None` for a tick in which no enemy died or got
+    /// through.
+    pub fn summary(&self) -> Option<String> {
+        if self.killed.is_empty() && self.leaked.is_empty() {
+            return None;
+        }
+        let mut parts = Vec::new();
+        if !self.killed.is_empty() {
+            parts.push(format!("{} killed", self.killed.len()));
+        }
+        if !self.leaked.is_empty() {
+            parts.push(format!("{} got through", self.leaked.len()));
+        }
+        if self.money_earned > 0 {
+            parts.push(format!("+${}", self.money_earned));
+        }
+        Some(parts.join(", "))
+    }
+}
+
+/// Tuning for chaos mode, where every enemy killed goes off and hurts the
+/// enemies around it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Chaos {
+    pub damage: u32,
+    /// How many steps away, counted along the grid, the blast reaches.
+    pub radius: usize,
+}
+
+impl Default for Chaos {
+    fn default() -> Chaos {
+        Chaos {
+            damage: 5,
+            radius: 1,
+        }
+    }
+}
+
+/// Which box-drawing frames are drawn around the game.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Border {
+    #[default]
+    None,
+    /// One around the map.
+    Map,
+    /// One around the map and another around the whole screen.
+    All,
+}
+
+impl Border {
+    /// The next option in the order they're cycled through.
+    pub fn next(self) -> Border {
+        match self {
+            Border::None => Border::Map,
+            Border::Map => Border::All,
+            Border::All => Border::None,
+        }
+    }
+}
+
+impl FromStr for Border {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "none" => Ok(Border::None),
+            "map" => Ok(Border::Map),
+            "all" => Ok(Border::All),
+            _ => Err(format!("unknown border `{}`", s)),
+        }
+    }
+}
+
+/// Size of the maze generated for the daily challenge.
+const DAILY_MAP_SIZE: (usize, usize) = (41, 17);
+/// Width of the wave progress bar in the HUD.
+const PROGRESS_WIDTH: u16 = 30;
+/// Rows kept free below the map for the HUD when the map has to scroll.
+const HUD_HEIGHT: u16 = 4;
+/// How many steps apart, counted along the grid, enemies can be and still
+/// count as one bunch for `GameState::focus_point`.
+const CLUSTER_RADIUS: usize = 5;
+/// How many steps from where it was meant to spawn an enemy may be moved to
+/// keep it off another enemy.
+const SPAWN_SPREAD: usize = 2;
+/// Hp an enemy loses for every tick it ends on a hazard.
+pub const HAZARD_DAMAGE: u32 = 2;
+/// Share of a tower's cost handed back when it is sold.
+pub const REFUND_RATE: f64 = 0.5;
+/// Price of a single wall.
+pub const WALL_COST: u32 = 2;
+/// Price of refilling a tower's magazine on the spot.
+pub const RELOAD_COST: u32 = 10;
+/// Glider routes worked out per tick unless told otherwise.
+pub const PATH_BUDGET: usize = 8;
+
+pub struct GameState {
+    pub enemies: Slots<Enemy>,
+    pub towers: Vec<Tower>,
+    pub projectiles: Vec<Projectile>,
+    pub pickups: Vec<PickUp>,
+    pub fields: Vec<Field>,
+    /// Ticks left on a collected damage boost.
+    pub damage_boost: u32,
+    pub map: Map,
+    /// Cells that were walls until a breaker knocked them down, which a
+    /// mason may put back.
+    pub broken_walls: Vec<Vector2<usize>>,
+    pub economy: Economy,
+    pub difficulty: Difficulty,
+    pub pathfinder: Pathfinder,
+    /// Whether enemies route around tower fire instead of following
+    /// `pathfinder`; see `threat_step`.
+    pub threat_aware: bool,
+    /// The most glider routes worked out in one tick. The gliders whose
+    /// routes are oldest go first; the rest keep following the ones they
+    /// have unless the map has changed since they were worked out.
+    pub path_budget: usize,
+    /// Whether enemies close to death rush for the exit at
+    /// `PANIC_SPEEDUP` times their speed.
+    pub panic: bool,
+    /// Chaos mode, if it's on.
+    pub chaos: Option<Chaos>,
+    /// Spawn pressure, if it's on.
+    pub pressure: Option<Pressure>,
+    /// The tick the current or last wave started on.
+    wave_started: u64,
+    /// Ticks each wave cleared so far took, from its start until its last
+    /// enemy was gone.
+    pub clear_times: Vec<u64>,
+    /// Whether a lost game runs on until the enemies left have leaked or
+    /// died, for the player to watch.
+    pub spectate_on_loss: bool,
+    spectating: bool,
+    /// Ticks after a shot hurts an enemy during which further shots do
+    /// nothing to it. Zero lets every shot count.
+    pub hit_cooldown: u64,
+    /// Glider routes worked out during the last tick.
+    pub routes_computed: usize,
+    /// What has happened so far during the tick being played.
+    report: TickReport,
+    /// Waves still to come, the next one first.
+    pub waves: VecDeque<Wave>,
+    /// Enemies of the current wave still waiting to spawn.
+    pub pending: VecDeque<EnemyKind>,
+    /// How many enemies the current wave holds, counting the children of
+    /// its splitters as they appear.
+    pub wave_size: usize,
+    /// How many of the current wave's enemies have spawned, children left
+    /// out.
+    spawned: usize,
+    /// Ticks enemies are shown counting down on their spawn cell before
+    /// they spawn; zero spawns them straight away.
+    pub telegraph_ticks: u32,
+    /// Enemies counting down to spawn, oldest first.
+    pub telegraphed: Vec<Telegraph>,
+    /// The current wave's `Wave::hp_ramp`.
+    pub hp_ramp: f64,
+    /// The current wave's `Wave::spawn_interval`.
+    pub spawn_interval: u32,
+    /// Ticks left before the next enemies spawn.
+    pub spawn_timer: u32,
+    /// Whether pending enemies are let in; see `set_spawning`.
+    spawning: bool,
+    /// Whether the current wave comes in from the edge of the map.
+    pub edge_spawns: bool,
+    /// The shape the current wave's enemies come in in.
+    pub formation: Formation,
+    /// The spawn points the current wave comes in from; see
+    /// `Wave::active_spawns`.
+    pub active_spawns: Vec<usize>,
+    /// Index of the wave being fought, or of the next one while building.
+    pub wave: usize,
+    pub phase: Phase,
+    pub money: u32,
+    pub kills: u32,
+    pub lives: u32,
+    /// Lives owed to overflow that don't add up to a whole one yet.
+    pub overflow: f64,
+    pub rng: StdRng,
+    /// Ticks played so far, which also drives tile animations.
+    pub tick: u64,
+    pub next_enemy_id: u64,
+    pub cursor: Vector2<usize>,
+    /// Where the mouse was pressed for the wall line being dragged out.
+    pub drag_start: Option<Vector2<usize>>,
+    /// Whether the mouse has left the cell it was pressed on since, which
+    /// is what tells a drag from a plain click.
+    dragged: bool,
+    /// Where on the screen the mouse was last seen, counted from 1.
+    pub mouse: Option<(u16, u16)>,
+    pub show_chokepoints: bool,
+    /// Whether to draw the cells enemies have just left.
+    pub show_trails: bool,
+    /// Whether to shade every cell by how many towers can shoot at it.
+    pub show_ranges: bool,
+    /// Whether to shade every cell by how many enemies are headed through
+    /// it, as a god view of the traffic.
+    pub show_paths: bool,
+    /// Whether the HUD shows the coordinates of the cursor and the mouse.
+    pub show_coords: bool,
+    /// Whether towers are drawn as arrows pointing the way they face.
+    pub show_facing: bool,
+    pub border: Border,
+    /// Whether towers may not be built on the route enemies take.
+    pub no_build_on_path: bool,
+    /// The tower being placed, while in placement mode.
+    pub placing: Option<TowerKind>,
+    pub message: Option<String>,
+    pub theme: Theme,
+    threat_cache: RefCell<Option<ThreatCache>>,
+    overlay_cache: RefCell<OverlayCache>,
+    flow_fields: FlowFields,
+    viewport: Cell<Viewport>,
+}
+
+impl GameState {
+    /// A game on `map` at the start of its first build phase, with the
+    /// default economy and a random seed. Every other way of starting a game
+    /// goes through here.
+    pub fn new(map: Map, difficulty: Difficulty) -> Self {
+        let economy = Economy::default();
+        GameState {
+            enemies: Slots::new(),
+            towers: Vec::new(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+            fields: Vec::new(),
+            damage_boost: 0,
+            map,
+            broken_walls: Vec::new(),
+            economy: economy.clone(),
+            difficulty,
+            pathfinder: Pathfinder::default(),
+            threat_aware: false,
+            path_budget: PATH_BUDGET,
+            panic: false,
+            hit_cooldown: 0,
+            chaos: None,
+            pressure: None,
+            wave_started: 0,
+            clear_times: Vec::new(),
+            spectate_on_loss: false,
+            spectating: false,
+            routes_computed: 0,
+            report: TickReport::default(),
+            waves: (0..difficulty.wave_count())
+                .map(|i| Wave::generate(i, difficulty))
+                .collect(),
+            pending: VecDeque::new(),
+            wave_size: 0,
+            spawned: 0,
+            telegraph_ticks: 0,
+            telegraphed: Vec::new(),
+            hp_ramp: 0.0,
+            spawn_interval: 0,
+            spawn_timer: 0,
+            spawning: true,
+            edge_spawns: false,
+            formation: Formation::default(),
+            active_spawns: Vec::new(),
+            wave: 0,
+            phase: Phase::Build {
+                ticks_left: BUILD_TICKS,
+            },
+            money: economy.starting_money,
+            kills: 0,
+            lives: economy.starting_lives(),
+            overflow: 0.0,
+            rng: StdRng::from_entropy(),
+            tick: 0,
+            next_enemy_id: 0,
+            cursor: Vector2::new(0, 0),
+            drag_start: None,
+            dragged: false,
+            mouse: None,
+            show_chokepoints: false,
+            show_trails: false,
+            show_ranges: false,
+            show_coords: false,
+            show_facing: false,
+            show_paths: false,
+            border: Border::default(),
+            placing: None,
+            no_build_on_path: difficulty.no_build_on_path(),
+            message: None,
+            theme: Theme::default(),
+            threat_cache: RefCell::new(None),
+            overlay_cache: RefCell::default(),
+            flow_fields: FlowFields::default(),
+            viewport: Cell::new(Viewport::default()),
+        }
+    }
+
+    /// Starts the game with the resources of `economy` instead.
+    pub fn with_economy(mut self, economy: &Economy) -> Self {
+        self.money = economy.starting_money;
+        self.lives = economy.starting_lives();
+        self.economy = economy.clone();
+        self
+    }
+
+    /// Closes the game out with a sudden-death wave, which has to be
+    /// cleared like any other before the game is won.
+    pub fn with_sudden_death(mut self) -> Self {
+        let wave = Wave::sudden_death(self.waves.len(), self.difficulty);
+        self.waves.push_back(wave);
+        self
+    }
+
+    /// Sends every wave in from the edge of the map.
+    pub fn with_edge_spawns(mut self) -> Self {
+        for wave in self.waves.iter_mut() {
+            wave.edge_spawns = true;
+        }
+        // Set straight away too, so the edge is kept open from the first
+        // build phase on.
+        self.edge_spawns = true;
+        self
+    }
+
+    /// Sends each wave in from a single spawn point, going through them in
+    /// turn. Does nothing on maps with fewer than two.
+    pub fn with_spawn_rotation(mut self) -> Self {
+        let count = self.map.spawn_points().count();
+        if count > 1 {
+            for (i, wave) in self.waves.iter_mut().enumerate() {
+                wave.active_spawns = vec![i % count];
+            }
+        }
+        self
+    }
+
+    /// Has the `wave`th wave still to come, counting from the next, come in
+    /// only from the spawn points at `spawns`, indices in row-major order
+    /// into the map's spawn points. Empty lets it use all of them.
+    pub fn set_active_spawns(&mut self, wave: usize, spawns: Vec<usize>) -> Result<(), GameError> {
+        let count = self.map.spawn_points().count();
+        if let Some(&bad) = spawns.iter().find(|&&i| i >= count) {
+            return Err(GameError::InvalidState(format!(
+                "spawn point {} doesn't exist; the map has {}",
+                bad, count
+            )));
+        }
+        let wave = self
+            .waves
+            .get_mut(wave)
+            .ok_or_else(|| GameError::InvalidState(format!("there is no wave {} to come", wave)))?;
+        wave.active_spawns = spawns;
+        Ok(())
+    }
+
+    /// Moves enemies with `pathfinder` instead.
+    pub fn with_pathfinder(mut self, pathfinder: Pathfinder) -> Self {
+        self.pathfinder = pathfinder;
+        self
+    }
+
+    /// Has enemies avoid the cells towers cover where they can.
+    pub fn with_threat_aware(mut self) -> Self {
+        self.threat_aware = true;
+        self
+    }
+
+    /// Works out at most `budget` glider routes per tick instead.
+    pub fn with_path_budget(mut self, budget: usize) -> Self {
+        self.path_budget = budget;
+        self
+    }
+
+    /// Draws towers as arrows turned towards what they last shot at.
+    pub fn with_tower_facing(mut self) -> Self {
+        self.show_facing = true;
+        self
+    }
+
+    /// Has enemies close to death speed up.
+    pub fn with_panic(mut self) -> Self {
+        self.panic = true;
+        self
+    }
+
+    /// Makes enemies shrug off shots for `ticks` after one hurts them.
+    pub fn with_hit_cooldown(mut self, ticks: u64) -> Self {
+        self.hit_cooldown = ticks;
+        self
+    }
+
+    /// Warns of every enemy for `ticks` on its spawn cell before it spawns.
+    pub fn with_telegraph(mut self, ticks: u32) -> Self {
+        self.telegraph_ticks = ticks;
+        self
+    }
+
+    /// Makes every wave's enemies tougher the later they spawn; see
+    /// `Wave::hp_ramp`.
+    pub fn with_hp_ramp(mut self, ramp: f64) -> Self {
+        for wave in self.waves.iter_mut() {
+            wave.hp_ramp = ramp;
+        }
+        self
+    }
+
+    /// Lets the player watch a lost game play out.
+    pub fn with_spectating(mut self) -> Self {
+        self.spectate_on_loss = true;
+        self
+    }
+
+    /// Turns on chaos mode.
+    pub fn with_chaos(mut self, chaos: Chaos) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Turns on spawn pressure.
+    pub fn with_pressure(mut self, pressure: Pressure) -> Self {
+        self.pressure = Some(pressure);
+        self
+    }
+
+    /// Seeds the game's rng, making it play out the same way every time.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// The daily challenge for `date`: a generated map played with the
+    /// default economy on normal difficulty, seeded only by the date so that
+    /// everyone playing on the same day gets the same game.
+    pub fn daily(date: Date) -> Self {
+        let mut rng = StdRng::seed_from_u64(date.seed());
+        let (w, h) = DAILY_MAP_SIZE;
+        let map = Map::generate(w, h, &mut rng);
+        GameState::new(map, Difficulty::Normal).with_seed(rng.gen())
+    }
+
+    pub fn spawn(&mut self, kind: EnemyKind, pos: Vector2<usize>) -> Handle {
+        let id = self.next_enemy_id;
+        self.next_enemy_id += 1;
+        self.report.spawned.push((pos, kind));
+        self.enemies.insert(Enemy::new(id, kind, pos, self.wave))
+    }
+
+    pub fn move_cursor(&mut self, dx: i32, dy: i32) {
+        let target = self.cursor.map(|x| x as i32) + Vector2::new(dx, dy);
+        if self.map.in_bounds(target) {
+            self.set_cursor(target.map(|x| x as usize));
+        }
+    }
+
+    /// Moves the cursor to `pos`, collecting any pick-up lying there.
+    pub fn set_cursor(&mut self, pos: Vector2<usize>) {
+        self.cursor = pos;
+        self.collect_pickup(pos);
+    }
+
+    /// Picks up whatever lies on `pos` and applies its effect.
+    pub fn collect_pickup(&mut self, pos: Vector2<usize>) -> Option<PickUpKind> {
+        let i = self.pickups.iter().position(|p| p.pos == pos)?;
+        let kind = self.pickups.remove(i).kind;
+        match kind {
+            PickUpKind::Money => self.money += PICKUP_MONEY,
+            PickUpKind::DamageBoost => self.damage_boost = BOOST_TICKS,
+        }
+        Some(kind)
+    }
+
+    pub fn try_place_tower(
+        &mut self,
+        kind: TowerKind,
+        pos: Vector2<usize>,
+    ) -> Result<(), PlaceError> {
+        let cells = footprint_cells(pos, kind.footprint());
+        self.check_tower_site(&cells)?;
+        if self.at_tower_cap() {
+            return Err(PlaceError::TowerCap);
+        }
+        if self.money < kind.cost() {
+            return Err(PlaceError::TooExpensive);
+        }
+        self.money -= kind.cost();
+        for cell in cells {
+            self.map.set_occupied(cell, true);
+        }
+        self.towers.push(Tower::new(kind, pos));
+        Ok(())
+    }
+
+    /// The most towers that can stand at once: the economy's cap, or else
+    /// the difficulty's.
+    pub fn max_towers(&self) -> Option<u32> {
+        self.economy
+            .max_towers
+            .or_else(|| self.difficulty.max_towers())
+    }
+
+    /// Whether as many towers stand as `max_towers` allows.
+    pub fn at_tower_cap(&self) -> bool {
+        self.max_towers()
+            .is_some_and(|max| self.towers.len() >= max as usize)
+    }
+
+    pub fn try_place_wall(&mut self, pos: Vector2<usize>) -> Result<(), PlaceError> {
+        self.check_site(&[pos])?;
+        if self.money < WALL_COST {
+            return Err(PlaceError::TooExpensive);
+        }
+        self.money -= WALL_COST;
+        self.map[pos] = Square::Wall;
+        Ok(())
+    }
+
+    /// Lays down a field of `kind` on the empty cell `pos`. Enemies may be
+    /// standing on it, since fields never block their way.
+    pub fn try_place_field(
+        &mut self,
+        kind: FieldKind,
+        pos: Vector2<usize>,
+    ) -> Result<(), PlaceError> {
+        if self.spectating {
+            return Err(PlaceError::GameOver);
+        }
+        if !self.map.contains(pos) {
+            return Err(PlaceError::OffMap);
+        }
+        if self.map[pos] != Square::Empty {
+            return Err(PlaceError::NotEmpty);
+        }
+        if self.map.is_occupied(pos) || self.fields.iter().any(|f| f.pos == pos) {
+            return Err(PlaceError::Occupied);
+        }
+        if self.money < kind.cost() {
+            return Err(PlaceError::TooExpensive);
+        }
+        self.money -= kind.cost();
+        self.fields.push(Field {
+            pos,
+            kind,
+            ttl: kind.ttl(),
+        });
+        Ok(())
+    }
+
+    /// Why nothing can be built across `cells`, price aside.
+    fn check_site(&self, cells: &[Vector2<usize>]) -> Result<(), PlaceError> {
+        if self.spectating {
+            return Err(PlaceError::GameOver);
+        }
+        if !cells.iter().all(|&c| self.map.contains(c)) {
+            return Err(PlaceError::OffMap);
+        }
+        if cells.iter().any(|&c| self.map[c] != Square::Empty) {
+            return Err(PlaceError::NotEmpty);
+        }
+        let taken =
+            |c: Vector2<usize>| self.map.is_occupied(c) || self.enemies.iter().any(|e| e.pos == c);
+        if cells.iter().any(|&c| taken(c)) {
+            return Err(PlaceError::Occupied);
+        }
+        if self.blocks_path(cells) {
+            return Err(PlaceError::BlocksPath);
+        }
+        Ok(())
+    }
+
+    /// `check_site`, plus the no-build-on-path rule when it is in force.
+    fn check_tower_site(&self, cells: &[Vector2<usize>]) -> Result<(), PlaceError> {
+        self.check_site(cells)?;
+        if self.no_build_on_path && cells.iter().any(|c| self.route_cells().contains(c)) {
+            return Err(PlaceError::OnPath);
+        }
+        Ok(())
+    }
+
+    /// Up to `n` cells worth building a basic tower on, best first: those
+    /// from which it could reach the most cells of the routes enemies take
+    /// from the spawn points, ties going in row-major order. Cells on the
+    /// routes themselves are left out, since building there would send
+    /// enemies another way, as are cells nothing can be built on or that
+    /// reach none of the routes.
+    pub fn suggest_placements(&self, n: usize) -> Vec<Vector2<usize>> {
+        let route = self.route_cells();
+        let mut ranked: Vec<_> = self
+            .map
+            .cells_of(Square::Empty)
+            .filter(|c| !route.contains(c))
+            .map(|c| {
+                let tower = Tower::new(TowerKind::Basic, c);
+                (route.iter().filter(|&&r| tower.in_range(r)).count(), c)
+            })
+            .filter(|&(coverage, _)| coverage > 0)
+            .collect();
+        ranked.sort_by_key(|&(coverage, c)| (Reverse(coverage), c.y, c.x));
+        ranked
+            .into_iter()
+            .map(|(_, c)| c)
+            .filter(|&c| self.check_tower_site(&[c]).is_ok())
+            .take(n)
+            .collect()
+    }
+
+    /// What building the tower being placed on the cursor would take, or
+    /// `None` outside placement mode. Whether the site is fit to build on is
+    /// kept until the map, the tick or the cursor changes.
+    pub fn placement_preview(&self) -> Option<PlacementInfo> {
+        let kind = self.placing?;
+        let key = (self.map.generation(), self.tick, self.cursor, kind);
+        let cached = self.overlay_cache.borrow().placement;
+        let problem = match cached {
+            Some((cached, problem)) if cached == key => problem,
+            _ => {
+                // Checking the site looks up the cached route, so the cache
+                // isn't held on to meanwhile.
+                let problem = self
+                    .check_tower_site(&footprint_cells(self.cursor, kind.footprint()))
+                    .err();
+                self.overlay_cache.borrow_mut().placement = Some((key, problem));
+                problem
+            }
+        };
+        Some(PlacementInfo {
+            kind,
+            cost: kind.cost(),
+            affordable: self.money >= kind.cost(),
+            problem,
+        })
+    }
+
+    /// The cells enemies walk through from the spawn points to the
+    /// destinations, kept until the map changes.
+    fn route_cells(&self) -> HashSet<Vector2<usize>> {
+        let generation = self.map.generation();
+        let mut cache = self.overlay_cache.borrow_mut();
+        match &cache.route {
+            Some((cached, cells)) if *cached == generation => cells.clone(),
+            _ => {
+                let cells: HashSet<_> = self
+                    .map
+                    .spawn_points()
+                    .flat_map(|s| trace_route(&self.map, s))
+                    .collect();
+                cache.route = Some((generation, cells.clone()));
+                cells
+            }
+        }
+    }
+
+    /// The map's chokepoints, kept until the map changes.
+    pub fn chokepoints(&self) -> Vec<Vector2<usize>> {
+        let generation = self.map.generation();
+        let mut cache = self.overlay_cache.borrow_mut();
+        match &cache.chokepoints {
+            Some((cached, points)) if *cached == generation => points.clone(),
+            _ => {
+                let points = self.map.chokepoints();
+                cache.chokepoints = Some((generation, points.clone()));
+                points
+            }
+        }
+    }
+
+    /// Turns target lock on or off for the tower on `pos`, and returns
+    /// whether it is now on.
+    pub fn toggle_target_lock(&mut self, pos: Vector2<usize>) -> Result<bool, GameError> {
+        let tower = self
+            .towers
+            .iter_mut()
+            .find(|t| t.kind.attacks() && t.cells().contains(&pos))
+            .ok_or_else(|| GameError::InvalidState("no tower here can shoot".to_string()))?;
+        tower.target_lock = !tower.target_lock;
+        tower.locked = None;
+        Ok(tower.target_lock)
+    }
+
+    /// Pays `RELOAD_COST` to refill the magazine of the reloading tower on
+    /// `pos` right away.
+    pub fn instant_reload(&mut self, pos: Vector2<usize>) -> Result<(), GameError> {
+        if self.spectating {
+            return Err(GameError::InvalidState("the game is over".to_string()));
+        }
+        let tower = self
+            .towers
+            .iter_mut()
+            .find(|t| t.reloading > 0 && t.cells().contains(&pos))
+            .ok_or_else(|| GameError::InvalidState("no tower here is reloading".to_string()))?;
+        if self.money < RELOAD_COST {
+            return Err(GameError::InvalidState(format!(
+                "a reload costs ${}",
+                RELOAD_COST
+            )));
+        }
+        self.money -= RELOAD_COST;
+        tower.reloading = 0;
+        tower.ammo = tower.kind.magazine();
+        Ok(())
+    }
+
+    /// How many towers can shoot at each cell, indexed by `(y, x)` like the
+    /// grid.
+    pub fn range_heat(&self) -> DMatrix<u32> {
+        let (h, w) = (self.map.grid.nrows(), self.map.grid.ncols());
+        let mut heat = DMatrix::zeros(h, w);
+        for tower in self.towers.iter().filter(|t| t.kind.attacks()) {
+            for cell in tower.cells_in_range(w, h) {
+                heat[(cell.y, cell.x)] += 1;
+            }
+        }
+        heat
+    }
+
+    /// How many living enemies have each cell still ahead of them on their
+    /// way to the nearest destination, indexed by `(y, x)` like the grid.
+    /// The cells enemies stand on don't count. Kept until the map changes
+    /// or the game ticks.
+    pub fn path_heat(&self) -> DMatrix<u32> {
+        let key = (self.map.generation(), self.tick);
+        let mut cache = self.overlay_cache.borrow_mut();
+        if let Some((cached, heat)) = &cache.path_heat {
+            if *cached == key {
+                return heat.clone();
+            }
+        }
+        let (h, w) = (self.map.grid.nrows(), self.map.grid.ncols());
+        let mut heat = DMatrix::zeros(h, w);
+        for enemy in self.enemies.iter() {
+            for cell in self.map.plan_moves(enemy.pos, usize::MAX) {
+                heat[(cell.y, cell.x)] += 1;
+            }
+        }
+        cache.path_heat = Some((key, heat.clone()));
+        heat
+    }
+
+    /// The damage per tick all towers together can deal to each cell,
+    /// indexed by `(y, x)` like the grid. A tower only threatens the cells
+    /// in its range that it can see past the walls. Kept until the towers or
+    /// the map change.
+    pub fn threat_field(&self) -> DMatrix<f32> {
+        let generation = self.map.generation();
+        let towers: Vec<_> = self.towers.iter().map(|t| (t.kind, t.pos)).collect();
+        let mut cache = self.threat_cache.borrow_mut();
+        if let Some(cache) = cache.as_ref() {
+            if cache.generation == generation && cache.towers == towers {
+                return cache.field.clone();
+            }
+        }
+        let (h, w) = (self.map.grid.nrows(), self.map.grid.ncols());
+        let mut field = DMatrix::zeros(h, w);
+        for tower in self.towers.iter() {
+            let dps = tower.kind.dps();
+            for cell in tower.cells_in_range(w, h) {
+                if tower.cells().iter().any(|&c| self.map.in_sight(c, cell)) {
+                    field[(cell.y, cell.x)] += dps;
+                }
+            }
+        }
+        *cache = Some(ThreatCache {
+            generation,
+            towers,
+            field: field.clone(),
+        });
+        field
+    }
+
+    /// The map cell under the terminal cell at `(x, y)`, counted from 1 as
+    /// termion reports mouse events, going by the last frame drawn. `None`
+    /// for anywhere outside the map, such as the HUD or a border.
+    pub fn screen_to_cell(&self, x: u16, y: u16) -> Option<Vector2<usize>> {
+        let Viewport { map_area, origin } = self.viewport.get();
+        let (x, y) = (x.checked_sub(1)?, y.checked_sub(1)?);
+        let inside = |c: u16, start: u16, len: u16| c >= start && c - start < len;
+        if !inside(x, map_area.x, map_area.width) || !inside(y, map_area.y, map_area.height) {
+            return None;
+        }
+        Some(origin + Vector2::new((x - map_area.x) as usize, (y - map_area.y) as usize))
+    }
+
+    /// Follows the mouse to `(x, y)` on the screen. In placement mode the
+    /// cursor comes along, so the tower goes where the mouse points.
+    pub fn hover(&mut self, x: u16, y: u16) {
+        self.mouse = Some((x, y));
+        if self.placing.is_some() {
+            if let Some(cell) = self.screen_to_cell(x, y) {
+                self.set_cursor(cell);
+            }
+        }
+    }
+
+    /// The map cell under the mouse while placing a tower, if the mouse is
+    /// over the map at all.
+    pub fn hovered_cell(&self) -> Option<Vector2<usize>> {
+        self.placing?;
+        let (x, y) = self.mouse?;
+        self.screen_to_cell(x, y)
+    }
+
+    /// Where the action is, for the camera to keep in view: the middle of
+    /// the thickest bunch of enemies, or with none about the first
+    /// destination.
+    pub fn focus_point(&self) -> Vector2<usize> {
+        let near = |a: Vector2<usize>| {
+            self.enemies
+                .iter()
+                .map(|e| e.pos)
+                .filter(move |&b| map::manhattan(a, b) <= CLUSTER_RADIUS)
+        };
+        // The first enemy with the most others around it anchors the bunch.
+        let mut anchor = None;
+        for enemy in self.enemies.iter() {
+            let count = near(enemy.pos).count();
+            if anchor.is_none_or(|(_, most)| count > most) {
+                anchor = Some((enemy.pos, count));
+            }
+        }
+        match anchor {
+            Some((pos, count)) => {
+                let sum = near(pos).fold(Vector2::new(0, 0), |sum, p| sum + p);
+                sum.map(|c| (c as f64 / count as f64).round() as usize)
+            }
+            None => self.map.destinations().next().unwrap_or_else(|| {
+                Vector2::new(self.map.grid.ncols() / 2, self.map.grid.nrows() / 2)
+            }),
+        }
+    }
+
+    /// Sells every tower for `REFUND_RATE` of its cost, to start the defense
+    /// over. Only allowed during the build phase; returns the refund, or
+    /// `None` during combat.
+    pub fn clear_towers(&mut self) -> Option<u32> {
+        if self.phase == Phase::Combat || self.spectating {
+            return None;
+        }
+        let mut refund = 0;
+        for tower in self.towers.drain(..) {
+            refund += (tower.kind.cost() as f64 * REFUND_RATE).round() as u32;
+            for cell in tower.cells() {
+                self.map.set_occupied(cell, false);
+            }
+        }
+        self.money += refund;
+        Some(refund)
+    }
+
+    /// Starts what may turn into a wall line dragged out from the cursor.
+    pub fn press(&mut self) {
+        self.drag_start = Some(self.cursor);
+        self.dragged = false;
+    }
+
+    /// Notes the mouse still held down over the cursor. Once it is off the
+    /// cell it was pressed on, the press counts as a drag.
+    pub fn hold(&mut self) {
+        if self.drag_start.is_some_and(|start| start != self.cursor) {
+            self.dragged = true;
+        }
+    }
+
+    /// Ends a press at the cursor. A drag builds walls along the line it
+    /// covered, as `place_wall_line` does, and returns what that gave; a
+    /// plain click builds nothing and returns `None`.
+    pub fn release(&mut self) -> Option<(usize, Option<PlaceError>)> {
+        let start = self.drag_start.take()?;
+        let dragged = std::mem::replace(&mut self.dragged, false);
+        (dragged || start != self.cursor).then(|| self.place_wall_line(start, self.cursor))
+    }
+
+    /// Builds walls along the line from `from` to `to`, one cell at a time so
+    /// that every wall is checked against the ones placed before it. Cells
+    /// that can't be built on are skipped; the first error is returned along
+    /// with the number of walls placed.
+    pub fn place_wall_line(
+        &mut self,
+        from: Vector2<usize>,
+        to: Vector2<usize>,
+    ) -> (usize, Option<PlaceError>) {
+        let mut placed = 0;
+        let mut error = None;
+        for pos in map::line(from, to) {
+            match self.try_place_wall(pos) {
+                Ok(()) => placed += 1,
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        (placed, error)
+    }
+
+    /// Whether building on `cells` would cut a spawn point or a live enemy
+    /// off from every destination it can currently reach.
+    fn blocks_path(&self, cells: &[Vector2<usize>]) -> bool {
+        let before = distance_field(&self.map);
+        let mut blocked = self.map.clone();
+        for &cell in cells {
+            blocked.set_occupied(cell, true);
+        }
+        let after = distance_field(&blocked);
+        let cut_off = self
+            .map
+            .spawn_points()
+            .chain(self.enemies.iter().map(|e| e.pos))
+            .any(|s| reaches(&self.map, &before, s) && !reaches(&blocked, &after, s));
+        // The edge as a whole has to stay open, though not every cell of it.
+        // Enemies spawn on edge cells themselves, so one built over doesn't
+        // count even if its neighbors are open.
+        let edges = self.map.edge_cells();
+        let edge_open = |field: &DMatrix<Option<usize>>| {
+            edges
+                .iter()
+                .any(|s| field[(s.y, s.x)].is_some_and(|d| d > 0))
+        };
+        cut_off || (self.spawns_from_edges() && edge_open(&before) && !edge_open(&after))
+    }
+
+    fn spawns_from_edges(&self) -> bool {
+        self.edge_spawns || self.map.spawn_points().next().is_none()
+    }
+
+    /// How the game ended, or `None` while it is still being played. The
+    /// game is only won once the last wave has been fought off completely.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.lives == 0 {
+            Some(Outcome::Lose)
+        } else if self.waves.is_empty() && self.phase != Phase::Combat && self.enemies.is_empty() {
+            Some(Outcome::Win)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the game has ended and there is nothing left to watch. A
+    /// lost game that is being spectated has an outcome but isn't over yet.
+    pub fn is_game_over(&self) -> bool {
+        self.outcome().is_some() && !self.spectating
+    }
+
+    /// Whether the game has been lost but runs on so the player can watch
+    /// the enemies that are left; see `spectate_on_loss`. Nothing can be
+    /// built meanwhile.
+    pub fn is_spectating(&self) -> bool {
+        self.spectating
+    }
+
+    /// Stops watching a lost game, ending it.
+    pub fn stop_spectating(&mut self) {
+        self.spectating = false;
+    }
+
+    /// Plays out one tick and reports what happened during it. A game that
+    /// is over stays as it is, with nothing to report.
+    pub fn advance(&mut self) -> TickReport {
+        if self.is_game_over() {
+            return TickReport::default();
+        }
+        self.report = TickReport::default();
+        let money = self.money;
+        self.tick += 1;
+        self.damage_boost = self.damage_boost.saturating_sub(1);
+        for enemy in self.enemies.iter_mut() {
+            enemy.prev_pos = enemy.pos;
+        }
+        for pickup in self.pickups.iter_mut() {
+            pickup.ttl -= 1;
+        }
+        self.pickups.retain(|p| p.ttl > 0);
+        for field in self.fields.iter_mut() {
+            field.ttl -= 1;
+        }
+        self.fields.retain(|f| f.ttl > 0);
+        // Only the enemies already on the map play on once the game is lost.
+        if !self.spectating {
+            match self.phase {
+                Phase::Build { ticks_left } if ticks_left > 1 => {
+                    self.phase = Phase::Build {
+                        ticks_left: ticks_left - 1,
+                    }
+                }
+                Phase::Build { .. } => self.start_wave(),
+                Phase::Combat => {}
+            }
+            self.land_telegraphed();
+            self.spawn_pending();
+        }
+        self.move_enemies();
+        self.remove_destroyed_towers();
+        self.repair_walls();
+        self.assign_shields();
+        self.reveal_cloaked();
+        for enemy in self.enemies.iter_mut() {
+            if self.map[enemy.pos] == Square::Hazard {
+                enemy.take_damage(HAZARD_DAMAGE);
+            }
+        }
+        self.update_projectiles();
+        self.fire_towers();
+        self.money += self.towers.iter().map(|t| t.kind.income()).sum::<u32>();
+        self.remove_dead();
+        self.drain_overflow();
+        for enemy in self.enemies.iter_mut() {
+            enemy.regenerate();
+            enemy.fade_danger();
+            enemy.age += 1;
+        }
+        if self.lives == 0 {
+            self.spectating = self.spectate_on_loss && !self.enemies.is_empty();
+        } else if self.wave_cleared() {
+            self.end_wave();
+        }
+        self.report.money_earned = self.money.saturating_sub(money);
+        std::mem::take(&mut self.report)
+    }
+
+    /// Takes lives for every enemy over the economy's soft cap.
+    fn drain_overflow(&mut self) {
+        self.overflow += self.economy.overflow(self.enemies.len());
+        let drained = self.overflow.floor();
+        self.overflow -= drained;
+        self.lives = self.lives.saturating_sub(drained as u32);
+    }
+
+    /// Whether the wave being fought has finished spawning and every enemy
+    /// it sent in is gone.
+    pub fn wave_cleared(&self) -> bool {
+        self.phase == Phase::Combat
+            && self.pending.is_empty()
+            && self.telegraphed.is_empty()
+            && !self.enemies.iter().any(|e| e.wave == self.wave)
+    }
+
+    /// How many waves the game has in all: those fought, the one being
+    /// fought and those still to come.
+    pub fn total_waves(&self) -> usize {
+        self.wave + self.waves.len() + (self.phase == Phase::Combat) as usize
+    }
+
+    /// How many enemies of the current wave are gone, killed or leaked, out
+    /// of how many it has. Both are zero between waves.
+    pub fn wave_progress(&self) -> (usize, usize) {
+        if self.phase != Phase::Combat {
+            return (0, 0);
+        }
+        let alive = self.enemies.iter().filter(|e| e.wave == self.wave).count();
+        let done = self.wave_size - self.pending.len() - self.telegraphed.len() - alive;
+        (done, self.wave_size)
+    }
+
+    /// Holds back or again lets in the enemies still waiting to spawn,
+    /// while those already on the map carry on as usual. A wave isn't over
+    /// until all of its enemies have spawned, so holding them back keeps it
+    /// going.
+    pub fn set_spawning(&mut self, spawning: bool) {
+        self.spawning = spawning;
+    }
+
+    pub fn is_spawning(&self) -> bool {
+        self.spawning
+    }
+
+    /// Cuts the wait short: ends the build phase and sends the next wave in
+    /// right away, paying the economy's rush bonus for the ticks saved, or
+    /// during combat lets the next enemies in on the coming tick instead of
+    /// at the end of the spawn interval.
+    pub fn skip_to_next_wave(&mut self) {
+        match self.phase {
+            Phase::Build { ticks_left } if !self.waves.is_empty() => {
+                let bonus = self.economy.rush_reward(ticks_left);
+                self.money += bonus;
+                if bonus > 0 {
+                    self.message = Some(format!("rush bonus: ${}", bonus));
+                }
+                self.start_wave();
+            }
+            Phase::Build { .. } => {}
+            Phase::Combat => self.spawn_timer = 0,
+        }
+    }
+
+    fn start_wave(&mut self) {
+        let wave = match self.waves.pop_front() {
+            Some(wave) => wave,
+            None => return,
+        };
+        self.wave_size = wave.enemies.len();
+        self.spawned = 0;
+        self.hp_ramp = wave.hp_ramp;
+        self.spawn_interval = wave.spawn_interval;
+        self.edge_spawns = wave.edge_spawns;
+        self.formation = wave.formation;
+        self.active_spawns = wave.active_spawns;
+        self.spawn_timer = 0;
+        self.pending = wave.enemies.into();
+        self.phase = Phase::Combat;
+        self.wave_started = self.tick;
+        if wave.sudden_death {
+            self.message = Some("sudden death!".to_string());
+        }
+    }
+
+    /// Every `spawn_interval` ticks, times the size of the wave's formation,
+    /// lets the next pending enemies in at every spawn point, one for each
+    /// cell of the formation there is room for. An interval of zero lets the
+    /// whole wave in at once, shared out between the spawn points in turn.
+    /// Waves coming in from the edge let in as many at a time, each group
+    /// around a random edge cell with a way to a destination, or at the
+    /// spawn points if there is no such cell.
+    fn spawn_pending(&mut self) {
+        if self.pending.is_empty() || !self.spawning {
+            return;
+        }
+        if self.spawn_timer > 0 {
+            self.spawn_timer -= 1;
+            return;
+        }
+        let active = &self.active_spawns;
+        let mut spawns: Vec<_> = self
+            .map
+            .spawn_points()
+            .enumerate()
+            .filter(|(i, _)| active.is_empty() || active.contains(i))
+            .map(|(_, s)| s)
+            .collect();
+        let mut field = None;
+        if self.spawns_from_edges() {
+            let count = if self.spawn_interval == 0 {
+                self.pending.len()
+            } else {
+                spawns.len().max(1)
+            };
+            let field = field.get_or_insert_with(|| distance_field(&self.map));
+            let edges: Vec<_> = self
+                .map
+                .edge_cells()
+                .into_iter()
+                .filter(|s| field[(s.y, s.x)].is_some_and(|d| d > 0))
+                .collect();
+            // An enclosed map has no way in from its edge, so the wave
+            // comes in at the spawn points after all.
+            if !edges.is_empty() {
+                spawns = (0..count)
+                    .filter_map(|_| edges.choose(&mut self.rng).copied())
+                    .collect();
+            }
+        }
+        if self.formation != Formation::Single {
+            let (map, formation) = (&self.map, self.formation);
+            let field = field.get_or_insert_with(|| distance_field(map));
+            spawns = spawns
+                .into_iter()
+                .flat_map(|s| formation.cells(map, s, way_in(map, field, s)))
+                .collect();
+        }
+        let batch = if self.spawn_interval == 0 {
+            self.pending.len()
+        } else {
+            spawns.len()
+        };
+        // With nowhere to come in, the enemies wait rather than count as
+        // fought off.
+        if spawns.is_empty() {
+            return;
+        }
+        for pos in spawns.into_iter().cycle().take(batch) {
+            match self.pending.pop_front() {
+                Some(kind) => {
+                    let hp = ramped_hp(kind.max_hp(), self.hp_ramp, self.spawned);
+                    self.spawned += 1;
+                    if self.telegraph_ticks > 0 {
+                        self.telegraphed.push(Telegraph {
+                            kind,
+                            pos,
+                            hp,
+                            ticks_left: self.telegraph_ticks,
+                        });
+                    } else {
+                        self.spawn_with_hp(kind, pos, hp);
+                    }
+                }
+                None => break,
+            }
+        }
+        // A whole formation at once is followed by a longer wait, so
+        // enemies come in no faster on average than one by one.
+        self.spawn_timer = (self.spawn_interval * self.formation.size() as u32).saturating_sub(1);
+    }
+
+    /// Counts every telegraphed enemy down, spawning those whose time is up.
+    fn land_telegraphed(&mut self) {
+        for telegraph in self.telegraphed.iter_mut() {
+            telegraph.ticks_left -= 1;
+        }
+        let (due, waiting): (Vec<_>, _) =
+            self.telegraphed.drain(..).partition(|t| t.ticks_left == 0);
+        self.telegraphed = waiting;
+        for t in due {
+            self.spawn_with_hp(t.kind, t.pos, t.hp);
+        }
+    }
+
+    /// Spawns an enemy meant for `pos` with `hp` instead of its kind's
+    /// usual hp, moving it off any enemy already there.
+    fn spawn_with_hp(&mut self, kind: EnemyKind, pos: Vector2<usize>, hp: u32) {
+        let pos = self.free_spawn_cell(pos);
+        let h = self.spawn(kind, pos);
+        let enemy = self.enemies.get_mut(h).unwrap();
+        enemy.max_hp = hp;
+        enemy.hp = hp;
+    }
+
+    /// Where to put an enemy meant to spawn on `pos`: `pos` itself if no
+    /// enemy stands there, otherwise the nearest free cell that can be
+    /// walked to from it, in the order `Map::reachable_within` gives.
+    /// Enemies stack on `pos` if there is none close by.
+    pub fn free_spawn_cell(&self, pos: Vector2<usize>) -> Vector2<usize> {
+        let taken = |s: Vector2<usize>| self.enemies.iter().any(|e| e.pos == s);
+        self.map
+            .reachable_within(pos, SPAWN_SPREAD)
+            .into_iter()
+            .find(|&s| !taken(s))
+            .unwrap_or(pos)
+    }
+
+    /// Works out which enemies a detector has in range, going by the towers
+    /// standing now, so that one taken down stops revealing straight away.
+    fn reveal_cloaked(&mut self) {
+        let towers = &self.towers;
+        for enemy in self.enemies.iter_mut() {
+            enemy.revealed = towers
+                .iter()
+                .any(|t| t.kind.reveals() && t.in_range(enemy.pos));
+        }
+    }
+
+    /// Takes down the towers rushers have worn down to nothing.
+    fn remove_destroyed_towers(&mut self) {
+        let map = &mut self.map;
+        self.towers.retain(|tower| {
+            if tower.hp > 0 {
+                return true;
+            }
+            for cell in tower.cells() {
+                map.set_occupied(cell, false);
+            }
+            false
+        });
+    }
+
+    /// Lets every mason put back one broken wall next to it. A wall is only
+    /// put back on a free cell, and never if it would cut anything off from
+    /// the destinations.
+    fn repair_walls(&mut self) {
+        let masons: Vec<_> = self
+            .enemies
+            .iter()
+            .filter(|e| e.can_repair_walls())
+            .map(|e| e.pos)
+            .collect();
+        for pos in masons {
+            let wall = self.map.neighbors_4(pos).find(|&t| {
+                self.broken_walls.contains(&t)
+                    && self.map[t] == Square::Empty
+                    && !self.map.is_occupied(t)
+                    && !self.enemies.iter().any(|e| e.pos == t)
+                    && !self.pickups.iter().any(|p| p.pos == t)
+                    && !self.blocks_path(&[t])
+            });
+            if let Some(wall) = wall {
+                self.map[wall] = Square::Wall;
+                self.broken_walls.retain(|&w| w != wall);
+            }
+        }
+    }
+
+    /// Puts every enemy within `SHIELD_RANGE` of a shield with some pool
+    /// left under its cover, the nearest one if there are several. Shields
+    /// don't cover themselves or each other.
+    fn assign_shields(&mut self) {
+        let shields: Vec<_> = self
+            .enemies
+            .entries()
+            .filter(|(_, e)| e.has_shield())
+            .map(|(h, e)| (h, e.pos))
+            .collect();
+        for enemy in self.enemies.iter_mut() {
+            enemy.shielded_by = if enemy.kind == EnemyKind::Shield {
+                None
+            } else {
+                shields
+                    .iter()
+                    .map(|&(h, pos)| (h, map::manhattan(pos, enemy.pos)))
+                    .filter(|&(_, d)| d <= SHIELD_RANGE)
+                    .min_by_key(|&(_, d)| d)
+                    .map(|(h, _)| h)
+            };
+        }
+    }
+
+    fn end_wave(&mut self) {
+        self.money += self.economy.wave_bonus(self.wave);
+        self.heal_between_waves();
+        let clear_ticks = self.tick - self.wave_started;
+        self.clear_times.push(clear_ticks);
+        if let (Some(pressure), Some(next)) = (self.pressure, self.waves.front_mut()) {
+            next.rescale(pressure.scale(clear_ticks, self.wave_size));
+        }
+        self.wave += 1;
+        self.phase = Phase::Build {
+            ticks_left: BUILD_TICKS,
+        };
+    }
+
+    /// Gives back the lives owed for clearing a wave, up to the heal cap.
+    /// Never takes any away, even from a game that started above the cap.
+    fn heal_between_waves(&mut self) {
+        let heal = self
+            .economy
+            .wave_heal
+            .unwrap_or_else(|| self.difficulty.wave_heal());
+        let cap = self
+            .economy
+            .heal_cap
+            .unwrap_or_else(|| self.economy.starting_lives());
+        if self.lives < cap {
+            self.lives = (self.lives + heal).min(cap);
+        }
+    }
+
+    fn move_enemies(&mut self) {
+        let threat = if self.threat_aware {
+            Some(self.threat_field())
+        } else {
+            None
+        };
+        let mut gliders: Vec<_> = self
+            .enemies
+            .iter()
+            .filter(|e| e.moves_freely())
+            .map(|e| (e.routed_at, e.id))
+            .collect();
+        gliders.sort();
+        let reroute: HashSet<_> = gliders
+            .into_iter()
+            .take(self.path_budget)
+            .map(|(_, id)| id)
+            .collect();
+        self.routes_computed = 0;
+        let tick = self.tick;
+        let panic = self.panic;
+        let map = &mut self.map;
+        let rng = &mut self.rng;
+        let pathfinder = self.pathfinder;
+        let flow_fields = &mut self.flow_fields;
+        let broken_walls = &mut self.broken_walls;
+        let towers = &mut self.towers;
+        let fields = &self.fields;
+        let destinations: Vec<_> = map.destinations().collect();
+        for enemy in self.enemies.iter_mut() {
+            let dest = enemy.target_dest.and_then(|i| destinations.get(i).copied());
+            // Scaling the enemy's own speed keeps whatever else has slowed
+            // it down in force.
+            let mut multiplier = if panic && enemy.is_desperate() {
+                PANIC_SPEEDUP
+            } else {
+                1.0
+            };
+            if let Some(field) = fields.iter().find(|f| f.pos == enemy.pos) {
+                multiplier *= field.kind.speed_factor();
+            }
+            for _ in 0..enemy.take_steps(multiplier) {
+                if map[enemy.pos] == Square::Destination {
+                    break;
+                }
+                if enemy.attacks_towers() && towers.iter().any(|t| t.hp > 0) {
+                    let pos = enemy.pos;
+                    let next_to =
+                        |t: &Tower| t.cells().iter().any(|&c| map::manhattan(c, pos) == 1);
+                    if let Some(tower) = towers.iter_mut().find(|t| t.hp > 0 && next_to(t)) {
+                        // Attacking takes the whole tick.
+                        tower.hp = tower.hp.saturating_sub(RUSH_DAMAGE);
+                        break;
+                    }
+                    let goals: Vec<_> = towers
+                        .iter()
+                        .filter(|t| t.hp > 0)
+                        .flat_map(|t| t.cells())
+                        .flat_map(|c| map.neighbors_4(c).collect::<Vec<_>>())
+                        .filter(|t| map.passable(*t))
+                        .collect();
+                    if let Some(next) = pathfinder.step_towards(map, pos, &goals, flow_fields) {
+                        enemy.step_to(next);
+                        continue;
+                    }
+                }
+                // Straight lines don't wrap round the edges, so on a
+                // toroidal map even gliders keep to the grid.
+                if enemy.moves_freely() && !map.toroidal {
+                    // A route the map changed under is worked out again
+                    // straight away, whatever the budget says.
+                    let stale = enemy.routed_generation != Some(map.generation());
+                    let due = reroute.contains(&enemy.id) && enemy.routed_at != Some(tick);
+                    if (stale && enemy.routed_at.is_some()) || due {
+                        let route = cheapest_route(map, enemy.pos);
+                        enemy.waypoints = string_pull(map, enemy.pos, &route);
+                        enemy.routed_at = Some(tick);
+                        enemy.routed_generation = Some(map.generation());
+                        self.routes_computed += 1;
+                    }
+                    if enemy.waypoints.first() == Some(&enemy.pos) {
+                        enemy.waypoints.remove(0);
+                    }
+                    // A route worked out on an earlier tick may have been
+                    // built over since; the grid is fallen back on then.
+                    let step = enemy
+                        .waypoints
+                        .first()
+                        .map(|&corner| map::line(enemy.pos, corner)[1])
+                        .filter(|&next| walkable(map, enemy.pos, next));
+                    if let Some(next) = step {
+                        enemy.breaking = None;
+                        enemy.step_to(next);
+                        continue;
+                    }
+                }
+                let next = match &threat {
+                    _ if !enemy.danger.is_empty() => {
+                        let goals: Vec<_> = match dest {
+                            Some(dest) => vec![dest],
+                            None => destinations.clone(),
+                        };
+                        let danger = enemy.danger_cells();
+                        evasive_step(map, enemy.pos, &goals, &danger)
+                            .or_else(|| evasive_step(map, enemy.pos, &destinations, &danger))
+                    }
+                    Some(threat) => {
+                        let goals: Vec<_> = match dest {
+                            Some(dest) => vec![dest],
+                            None => destinations.clone(),
+                        };
+                        threat_step(map, enemy.pos, &goals, threat)
+                            .or_else(|| threat_step(map, enemy.pos, &destinations, threat))
+                    }
+                    None => pathfinder.next_step(map, enemy.pos, dest, flow_fields),
+                };
+                match next {
+                    Some(next) => {
+                        enemy.breaking = None;
+                        enemy.step_to(next);
+                    }
+                    None if enemy.can_break_walls() => {
+                        // Hammering on a wall takes the whole tick.
+                        broken_walls.extend(break_wall(map, enemy));
+                        break;
+                    }
+                    None => enemy.step_to(pf_random(map, enemy.pos, rng)),
+                }
+            }
+        }
+        let difficulty = self.difficulty;
+        let base_hp = self.economy.base_hp.is_some();
+        let mut leaked = 0;
+        let leaks = &mut self.report.leaked;
+        self.enemies.retain(|e| {
+            let arrived = map[e.pos] == Square::Destination;
+            if arrived {
+                leaks.push((e.pos, e.kind));
+                // Against base hp, what an enemy has left is what it costs,
+                // times the lives it would cost otherwise.
+                let hp = if base_hp { e.hp } else { 1 };
+                leaked += hp * difficulty.leak_damage(e.kind);
+            }
+            !arrived
+        });
+        self.lives = self.lives.saturating_sub(leaked);
+    }
+
+    fn update_projectiles(&mut self) {
+        let map = &self.map;
+        let enemies = &mut self.enemies;
+        let (tick, hit_cooldown) = (self.tick, self.hit_cooldown);
+        let mut field = None;
+        self.projectiles.retain_mut(|projectile| {
+            let enemy = match enemies.get(projectile.target) {
+                Some(enemy) => enemy,
+                None => return false,
+            };
+            projectile.prev_pos = projectile.pos;
+            for _ in 0..PROJECTILE_SPEED {
+                projectile.step_towards(enemy.pos);
+            }
+            if projectile.pos != enemy.pos {
+                return true;
+            }
+            // Shots landing while the target can't be hurt are wasted.
+            if enemy.is_invulnerable(tick) {
+                return false;
+            }
+            // A shield soaks up what it can before the target is hurt.
+            let mut damage = projectile.damage;
+            if let Some(shield) = enemy.shielded_by.and_then(|h| enemies.get_mut(h)) {
+                let absorbed = damage.min(shield.shield_pool);
+                shield.shield_pool -= absorbed;
+                damage -= absorbed;
+            }
+            let enemy = enemies.get_mut(projectile.target).unwrap();
+            enemy.invuln_until = tick + hit_cooldown;
+            if damage > 0 {
+                enemy.take_damage(damage);
+            }
+            if enemy.hp > 0 && projectile.knockback > 0 {
+                let field = field.get_or_insert_with(|| distance_field(map));
+                knock_back(map, field, enemy, projectile.knockback);
+            }
+            false
+        });
+    }
+
+    fn fire_towers(&mut self) {
+        let boost = if self.damage_boost > 0 { 2 } else { 1 };
+        for tower in self.towers.iter_mut() {
+            tower.fire_anim = tower.fire_anim.saturating_sub(1);
+            if tower.reloading > 0 {
+                tower.reloading -= 1;
+                if tower.reloading == 0 {
+                    tower.ammo = tower.kind.magazine();
+                }
+                continue;
+            }
+            if !tower.kind.attacks() {
+                continue;
+            }
+            if tower.cooldown > 0 {
+                tower.cooldown -= 1;
+                continue;
+            }
+            let enemies = &self.enemies;
+            let targetable = |e: &Enemy| !e.is_cloaked() && tower.in_range(e.pos);
+            let locked = tower
+                .locked
+                .filter(|_| tower.target_lock)
+                .and_then(|h| enemies.get(h).filter(|e| targetable(e)).map(|e| (h, e)));
+            let target = locked.or_else(|| {
+                enemies
+                    .entries()
+                    .filter(|(_, e)| targetable(e))
+                    .min_by_key(|(_, e)| tower.distance_squared(e.pos))
+            });
+            if let Some((target, enemy)) = target {
+                if tower.target_lock {
+                    tower.locked = Some(target);
+                }
+                if let Some(facing) = Direction::facing(tower.pos, enemy.pos) {
+                    tower.facing = facing;
+                }
+                self.projectiles.push(Projectile {
+                    pos: tower.pos,
+                    prev_pos: tower.pos,
+                    target,
+                    damage: tower.damage_at(enemy.pos) * boost,
+                    knockback: tower.kind.knockback(),
+                });
+                self.report.shots_fired += 1;
+                tower.cooldown = tower.kind.reload_ticks();
+                tower.fire_anim = FIRE_ANIM_TICKS;
+                if let Some(ammo) = tower.ammo.as_mut() {
+                    *ammo -= 1;
+                    if *ammo == 0 {
+                        tower.reloading = tower.kind.magazine_reload_ticks();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets off every enemy killed this tick, then every enemy those blasts
+    /// killed, and so on until no more die. Enemies go off in slot order and
+    /// only once each, so the chain always ends the same way.
+    fn chain_deaths(&mut self, chaos: Chaos) {
+        let mut gone_off = HashSet::new();
+        loop {
+            let dying: Vec<_> = self
+                .enemies
+                .entries()
+                .filter(|(h, e)| e.hp == 0 && !gone_off.contains(h))
+                .map(|(h, e)| (h, e.pos))
+                .collect();
+            if dying.is_empty() {
+                return;
+            }
+            for (h, pos) in dying {
+                gone_off.insert(h);
+                splash(&mut self.enemies, pos, chaos.radius, chaos.damage);
+            }
+        }
+    }
+
+    /// Drops every enemy killed this tick in one pass. The survivors keep
+    /// their handles and their order; leaked enemies are dropped the same
+    /// way, as soon as they arrive, by `move_enemies`.
+    fn remove_dead(&mut self) {
+        if let Some(chaos) = self.chaos {
+            self.chain_deaths(chaos);
+        }
+        let economy = &self.economy;
+        let mut reward = 0;
+        let mut kills = 0;
+        let mut splitters = Vec::new();
+        let mut graves = Vec::new();
+        self.enemies.retain(|e| {
+            if e.hp == 0 {
+                reward += economy.kill_reward(e.kind.bounty());
+                kills += 1;
+                graves.push((e.pos, e.kind));
+                if e.can_split() {
+                    splitters.push(e.clone());
+                }
+            }
+            e.hp > 0
+        });
+        self.money += reward;
+        self.kills += kills;
+        self.report.killed.extend(graves.iter().copied());
+        for (pos, killed) in graves {
+            if let Some(kind) = roll_loot(killed, &mut self.rng) {
+                self.pickups.push(PickUp {
+                    pos,
+                    kind,
+                    ttl: PICKUP_TTL,
+                });
+            }
+        }
+        for parent in splitters {
+            for _ in 0..SPLIT_CHILDREN {
+                let id = self.next_enemy_id;
+                self.next_enemy_id += 1;
+                self.report.spawned.push((parent.pos, parent.kind));
+                self.enemies.insert(parent.split_child(id));
+            }
+            if parent.wave == self.wave {
+                self.wave_size += SPLIT_CHILDREN;
+            }
+        }
+    }
+}
+
+/// A one-line summary for logs, e.g.
+/// `tick=42 lives=18 money=120 enemies=7 towers=4 wave=2`.
+impl fmt::Display for GameState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tick={} lives={} money={} enemies={} towers={} wave={}",
+            self.tick,
+            self.lives,
+            self.money,
+            self.enemies.len(),
+            self.towers.len(),
+            (self.wave + 1).min(self.total_waves())
+        )
+    }
+}
+
+/// The map as it was last painted. The grid rarely changes between frames, so
+/// most frames only copy these cells over instead of recomputing every
+/// square's glyph and style.
+#[derive(Default)]
+pub struct MapCache {
+    generation: Option<u64>,
+    buffer: Buffer,
+    /// How many squares have been painted into `buffer` so far.
+    pub squares_drawn: u64,
+    /// How far the frame being drawn falls between the last tick and the
+    /// next, from 0 to 1, for drawing projectiles in flight.
+    pub alpha: f32,
+    pub camera: Camera,
+}
+
+/// The screen cells the map shows through, going from map cells to the
+/// screen cells they are drawn on.
+struct InView<'a> {
+    buf: &'a mut Buffer,
+    area: Rect,
+    origin: Vector2<usize>,
+}
+
+impl InView<'_> {
+    /// The screen cell map cell `pos` is drawn on, or `None` if the camera
+    /// doesn't look at it.
+    fn get_mut(&mut self, pos: Vector2<usize>) -> Option<&mut tui::buffer::Cell> {
+        let dx = pos.x.checked_sub(self.origin.x)?;
+        let dy = pos.y.checked_sub(self.origin.y)?;
+        if dx >= self.area.width as usize || dy >= self.area.height as usize {
+            return None;
+        }
+        Some(
+            self.buf
+                .get_mut(self.area.x + dx as u16, self.area.y + dy as u16),
+        )
+    }
+
+    /// The map cells in view, in row-major order.
+    fn cells(&self) -> impl Iterator<Item = Vector2<usize>> {
+        let (origin, w, h) = (
+            self.origin,
+            self.area.width as usize,
+            self.area.height as usize,
+        );
+        (0..h).flat_map(move |dy| (0..w).map(move |dx| origin + Vector2::new(dx, dy)))
+    }
+
+    /// Writes `text` from map cell `pos` rightwards, cut off where the view
+    /// ends.
+    fn set_string(&mut self, pos: Vector2<usize>, text: &str, style: Style) {
+        for (i, ch) in text.chars().enumerate() {
+            if let Some(c) = self.get_mut(pos + Vector2::new(i, 0)) {
+                c.set_char(ch).set_style(style);
+            }
+        }
+    }
+}
+
+impl StatefulWidget for &GameState {
+    type State = MapCache;
+
+    fn render(self, area: Rect, buf: &mut Buffer, cache: &mut MapCache) {
+        if cache.generation != Some(self.map.generation()) {
+            let (w, h) = (self.map.grid.ncols() as u16, self.map.grid.nrows() as u16);
+            cache.buffer = Buffer::empty(Rect::new(0, 0, w, h));
+            self.map.draw(&self.theme, &mut cache.buffer);
+            cache.squares_drawn += self.map.grid.len() as u64;
+            cache.generation = Some(self.map.generation());
+        }
+        let frame = Block::default()
+            .borders(Borders::ALL)
+            .border_style(self.theme.fg(self.theme.hud));
+        let area = match self.border {
+            Border::All => {
+                frame.clone().render(area, buf);
+                frame.inner(area)
+            }
+            _ => area,
+        };
+        let framed = if self.border == Border::None { 0 } else { 2 };
+        let size = (self.map.grid.ncols(), self.map.grid.nrows());
+        let view = (
+            size.0.min(area.width.saturating_sub(framed) as usize),
+            size.1
+                .min(area.height.saturating_sub(HUD_HEIGHT + framed) as usize),
+        );
+        let map_area = Rect::new(area.x, area.y, view.0 as u16, view.1 as u16);
+        let map_area = match self.border {
+            Border::None => map_area,
+            _ => {
+                let outer = Rect::new(area.x, area.y, map_area.width + 2, map_area.height + 2);
+                frame.clone().render(outer, buf);
+                frame.inner(outer)
+            }
+        };
+        if cache.camera.follow {
+            cache.camera.track(self.focus_point(), view, size);
+        }
+        let origin = cache.camera.origin(view, size);
+        self.viewport.set(Viewport { map_area, origin });
+        let (left, top) = (map_area.x, map_area.y);
+        // The part of the painted map the camera looks at is copied onto the
+        // screen, and everything on the map is drawn over it there.
+        for dy in 0..view.1 {
+            for dx in 0..view.0 {
+                let cell = cache
+                    .buffer
+                    .get((origin.x + dx) as u16, (origin.y + dy) as u16);
+                *buf.get_mut(left + dx as u16, top + dy as u16) = cell.clone();
+            }
+        }
+        let mut world = InView {
+            buf,
+            area: map_area,
+            origin,
+        };
+        for pos in self.map.destinations() {
+            let sq = Square::Destination;
+            if let Some(c) = world.get_mut(pos) {
+                c.set_char(sq.glyph(Some(self.tick), &self.theme))
+                    .set_style(sq.style(Some(self.tick), &self.theme));
+            }
+        }
+        if self.show_chokepoints {
+            for pos in self.chokepoints() {
+                if let Some(c) = world.get_mut(pos) {
+                    c.set_symbol("·")
+                        .set_style(self.theme.fg(self.theme.chokepoint));
+                }
+            }
+        }
+        if self.show_ranges {
+            let heat = self.range_heat();
+            for pos in world.cells() {
+                let color = match heat[(pos.y, pos.x)] {
+                    0 => continue,
+                    1 => self.theme.range_low,
+                    2 => self.theme.range_mid,
+                    _ => self.theme.range_high,
+                };
+                world.get_mut(pos).unwrap().set_bg(color);
+            }
+        }
+        if self.show_paths {
+            let heat = self.path_heat();
+            for pos in world.cells() {
+                let color = match heat[(pos.y, pos.x)] {
+                    0 => continue,
+                    1 => self.theme.traffic_low,
+                    2 => self.theme.traffic_mid,
+                    _ => self.theme.traffic_high,
+                };
+                world.get_mut(pos).unwrap().set_bg(color);
+            }
+        }
+        if self.show_trails {
+            let enemy_at: Vec<_> = self.enemies.iter().map(|e| e.pos).collect();
+            for enemy in self.enemies.iter() {
+                for (age, pos) in enemy.trail.iter().enumerate() {
+                    // Walls may have gone up on a trail since it was left.
+                    if !self.map.passable(*pos) || enemy_at.contains(pos) {
+                        continue;
+                    }
+                    let style = match age {
+                        0 => self.theme.fg(self.theme.trail),
+                        _ => self.theme.fg(self.theme.trail).add_modifier(Modifier::DIM),
+                    };
+                    let symbol = if age + 1 < TRAIL_LEN { "." } else { "," };
+                    if let Some(c) = world.get_mut(*pos) {
+                        c.set_symbol(symbol).set_style(style);
+                    }
+                }
+            }
+        }
+        for tower in self.towers.iter() {
+            for cell in tower.cells() {
+                let c = match world.get_mut(cell) {
+                    Some(c) => c,
+                    None => continue,
+                };
+                let symbol = if self.show_facing && cell == tower.pos {
+                    tower.facing.arrow()
+                } else {
+                    tower.kind.symbol()
+                };
+                c.set_symbol(symbol)
+                    .set_style(self.theme.fg(self.theme.tower));
+                if tower.hp <= tower.kind.max_hp() / 2 || tower.reloading > 0 {
+                    c.set_style(Style::default().add_modifier(Modifier::DIM));
+                }
+                if tower.fire_anim > 0 {
+                    c.set_style(
+                        self.theme
+                            .fg(self.theme.muzzle_flash)
+                            .remove_modifier(Modifier::DIM)
+                            .add_modifier(Modifier::BOLD),
+                    );
+                }
+            }
+        }
+        for field in self.fields.iter() {
+            if let Some(c) = world.get_mut(field.pos) {
+                c.set_symbol(field.kind.symbol())
+                    .set_style(self.theme.fg(self.theme.field));
+            }
+        }
+        for pickup in self.pickups.iter() {
+            if let Some(c) = world.get_mut(pickup.pos) {
+                c.set_symbol(pickup.kind.symbol())
+                    .set_style(self.theme.fg(self.theme.pickup));
+            }
+        }
+        for projectile in self.projectiles.iter() {
+            if let Some(c) = world.get_mut(projectile.render_pos(cache.alpha)) {
+                c.set_symbol("o")
+                    .set_style(self.theme.fg(self.theme.projectile));
+            }
+        }
+        for telegraph in self.telegraphed.iter() {
+            let countdown = char::from_digit(telegraph.ticks_left.min(9), 10).unwrap();
+            if let Some(c) = world.get_mut(telegraph.pos) {
+                c.set_char(countdown).set_style(
+                    self.theme
+                        .fg(self.theme.spawn_point)
+                        .add_modifier(Modifier::BOLD),
+                );
+            }
+        }
+        for enemy in self.enemies.iter() {
+            let c = match world.get_mut(enemy.render_pos(cache.alpha)) {
+                Some(c) => c,
+                None => continue,
+            };
+            c.set_symbol(enemy.kind.symbol())
+                .set_style(self.theme.fg(self.theme.enemy));
+            if enemy.is_cloaked() {
+                c.set_style(Style::default().add_modifier(Modifier::DIM));
+            } else if enemy.shielded_by.is_some() {
+                c.set_style(Style::default().add_modifier(Modifier::BOLD));
+            }
+        }
+        if let Some(start) = self.drag_start.filter(|_| self.dragged) {
+            for pos in map::line(start, self.cursor) {
+                if let Some(c) = world.get_mut(pos) {
+                    c.set_bg(self.theme.drag);
+                }
+            }
+        }
+        if let Some(c) = world.get_mut(self.cursor) {
+            c.set_style(Style::default().add_modifier(Modifier::REVERSED));
+        }
+        if let Some(preview) = self.placement_preview() {
+            let color = if preview.is_possible() {
+                self.theme.affordable
+            } else {
+                self.theme.unaffordable
+            };
+            let (w, _) = preview.kind.footprint();
+            for cell in footprint_cells(self.cursor, preview.kind.footprint()) {
+                if let Some(c) = world.get_mut(cell) {
+                    c.set_symbol(preview.kind.symbol())
+                        .set_style(self.theme.fg(color));
+                }
+            }
+            let cost = format!("${}", preview.cost);
+            let at = self.cursor + Vector2::new(w, 0);
+            world.set_string(at, &cost, self.theme.fg(color));
+        }
+        let buf = world.buf;
+        // Highlighted on the screen rather than the map, so that it stays
+        // under the mouse even while the camera scrolls.
+        if let Some(cell) = self.hovered_cell() {
+            let (x, y) = (
+                left + (cell.x - origin.x) as u16,
+                top + (cell.y - origin.y) as u16,
+            );
+            buf.get_mut(x, y).set_bg(self.theme.hover);
+        }
+        let lives = if self.economy.base_hp.is_some() {
+            "base hp"
+        } else {
+            "lives"
+        };
+        let mut status = format!(
+            "{}: {}  money: {}  wave: {}/{}",
+            lives,
+            self.lives,
+            self.money,
+            (self.wave + 1).min(self.total_waves()),
+            self.total_waves()
+        );
+        if let Some(max) = self.max_towers() {
+            status += &format!("  towers: {}/{}", self.towers.len(), max);
+        }
+        if self.damage_boost > 0 {
+            status += &format!("  boost: {}", self.damage_boost);
+        }
+        if !self.spawning {
+            status += "  spawning paused";
+        }
+        if self.spectating {
+            status += "  game over, q to end";
+        }
+        if self.show_coords {
+            let mouse = self.mouse.and_then(|(x, y)| self.screen_to_cell(x, y));
+            status += &format!(
+                "  cursor: {}  mouse: {}",
+                coords_label(Some(self.cursor)),
+                coords_label(mouse)
+            );
+        }
+        let mut hud = vec![status];
+        hud.push(match (self.phase, self.waves.front()) {
+            (Phase::Build { ticks_left }, Some(next)) => {
+                let summary = next.summary();
+                let counts: Vec<_> = EnemyKind::ALL
+                    .iter()
+                    .filter_map(|kind| summary.get(kind).map(|n| format!("{} {}", n, kind.name())))
+                    .collect();
+                let name = if next.sudden_death {
+                    "SUDDEN DEATH"
+                } else {
+                    "next wave"
+                };
+                format!("{} in {}: {}", name, ticks_left, counts.join(", "))
+            }
+            (Phase::Build { .. }, None) => "all waves cleared".to_string(),
+            (Phase::Combat, _) => {
+                let left = self.enemies.len() + self.pending.len() + self.telegraphed.len();
+                format!("enemies left: {}", left)
+            }
+        });
+        hud.extend(self.message.clone());
+        let top = area.y + view.1 as u16 + framed;
+        for (i, line) in hud.iter().enumerate() {
+            let y = top + i as u16;
+            if y < area.bottom() {
+                let style = self.theme.fg(self.theme.hud);
+                buf.set_stringn(area.x, y, line, area.width as usize, style);
+            }
+        }
+        let (done, total) = self.wave_progress();
+        let y = top + hud.len() as u16;
+        if total > 0 && y < area.bottom() {
+            let gauge = Gauge::default()
+                .gauge_style(self.theme.fg(self.theme.progress))
+                .ratio(done as f64 / total as f64)
+                .label(format!("{}/{}", done, total));
+            gauge.render(Rect::new(area.x, y, area.width.min(PROGRESS_WIDTH), 1), buf);
+        }
+    }
+}
+
+/// How a cell's coordinates read in the HUD: `(x, y)`, or `--` for no cell.
+pub fn coords_label(cell: Option<Vector2<usize>>) -> String {
+    match cell {
+        Some(c) => format!("({}, {})", c.x, c.y),
+        None => "--".to_string(),
+    }
+}
+
+/// Deals `damage` to every living enemy within `radius` steps of `center`,
+/// counted along the grid.
+fn splash(enemies: &mut Slots<Enemy>, center: Vector2<usize>, radius: usize, damage: u32) {
+    for enemy in enemies.iter_mut() {
+        if enemy.hp > 0 && map::manhattan(enemy.pos, center) <= radius {
+            enemy.take_damage(damage);
+        }
+    }
+}
+
+/// The direction from `s` of its neighbor closest to a destination by
+/// `field`, or south if none of them has a way there.
+fn way_in(m: &Map, field: &DMatrix<Option<usize>>, s: Vector2<usize>) -> Vector2<i32> {
+    m.neighbors_4(s)
+        .filter_map(|t| field[(t.y, t.x)].map(|d| (t, d)))
+        .min_by_key(|&(_, d)| d)
+        .map(|(t, _)| t.map(|c| c as i32) - s.map(|c| c as i32))
+        .unwrap_or(Direction::South.to_offset())
+}
+
+/// Works on the wall a blocked breaker is facing, knocking it down once
+/// `BREAK_TICKS` ticks have been spent on it. Only walls whose removal opens a
+/// path to a destination are worth the effort; otherwise the breaker waits.
+/// Returns the wall that gave way, if one did this tick.
+fn break_wall(m: &mut Map, enemy: &mut Enemy) -> Option<Vector2<usize>> {
+    let breaking = match enemy.breaking {
+        Some(breaking) if m[breaking.wall] == Square::Wall => breaking,
+        _ => match opening_wall(m, enemy.pos) {
+            Some(wall) => Breaking {
+                wall,
+                ticks_left: BREAK_TICKS,
+            },
+            None => return None,
+        },
+    };
+    if breaking.ticks_left > 1 {
+        enemy.breaking = Some(Breaking {
+            ticks_left: breaking.ticks_left - 1,
+            ..breaking
+        });
+        None
+    } else {
+        m[breaking.wall] = Square::Empty;
+        enemy.walls_broken += 1;
+        enemy.breaking = None;
+        Some(breaking.wall)
+    }
+}
+
+/// An adjacent wall which, once removed, lets `s` reach a destination.
+fn opening_wall(m: &Map, s: Vector2<usize>) -> Option<Vector2<usize>> {
+    m.neighbors_4(s)
+        .filter(|t| m[*t] == Square::Wall)
+        .find(|&wall| {
+            let mut opened = m.clone();
+            opened[wall] = Square::Empty;
+            pf_search(&opened, s).is_some()
+        })
+}
+
+/// Pushes `enemy` up to `cells` steps further away from its nearest
+/// destination. The push stops early rather than moving the enemy onto
+/// anything it could not walk onto itself.
+fn knock_back(m: &Map, field: &DMatrix<Option<usize>>, enemy: &mut Enemy, cells: usize) {
+    for _ in 0..cells {
+        let here = match field[(enemy.pos.y, enemy.pos.x)] {
+            Some(d) => d,
+            None => return,
+        };
+        let back = m
+            .neighbors_4(enemy.pos)
+            .filter(|t| m.passable(*t))
+            .filter_map(|t| field[(t.y, t.x)].map(|d| (t, d)))
+            .filter(|&(_, d)| d > here)
+            .max_by_key(|&(_, d)| d);
+        match back {
+            Some((t, _)) => enemy.step_to(t),
+            None => return,
+        }
+    }
+    enemy.breaking = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where every enemy is after each of the first `ticks` ticks of a game
+    /// on the sample map, with enemies moved by `pathfinder`.
+    fn trajectories(pathfinder: Pathfinder, ticks: usize) -> Vec<Vec<(u64, Vector2<usize>)>> {
+        let map = Map::new(crate::MAP).unwrap();
+        let mut state = GameState::new(map, Difficulty::Normal)
+            .with_seed(0)
+            .with_pathfinder(pathfinder);
+        (0..ticks)
+            .map(|_| {
+                state.advance();
+                state.enemies.iter().map(|e| (e.id, e.pos)).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_pathfinder_moves_enemies_the_same_way() {
+        let expected = trajectories(Pathfinder::Bfs, 40);
+        assert!(expected.iter().any(|tick| !tick.is_empty()));
+        for &pathfinder in [
+            Pathfinder::Dijkstra,
+            Pathfinder::AStar,
+            Pathfinder::FlowField,
+        ]
+        .iter()
+        {
+            assert_eq!(trajectories(pathfinder, 40), expected, "{}", pathfinder);
+        }
+    }
+
+    /// How big the second wave comes in after the first is cleared in
+    /// `clear_ticks`, with spawn pressure on.
+    fn second_wave_after(clear_ticks: u64) -> usize {
+        let map = Map::new(crate::MAP).unwrap();
+        let mut state = GameState::new(map, Difficulty::Normal).with_pressure(Pressure::default());
+        state.skip_to_next_wave();
+        state.pending.clear();
+        state.wave_started = state.tick;
+        state.tick += clear_ticks;
+        state.advance();
+        assert_eq!(state.clear_times, vec![clear_ticks + 1]);
+        state.waves[0].enemies.len()
+    }
+
+    #[test]
+    fn fast_clears_bring_bigger_waves_than_slow_ones() {
+        let normal = Wave::generate(1, Difficulty::Normal).enemies.len() as f64;
+        let pressure = Pressure::default();
+        let (fast, slow) = (second_wave_after(0), second_wave_after(1000));
+        assert!(fast > slow);
+        assert_eq!(fast, (normal * pressure.max).round() as usize);
+        assert_eq!(slow, (normal * pressure.min).round() as usize);
+    }
+
+    /// A map with two routes of the same length from its spawn point to its
+    /// destination, one going down first and one going right.
+    const TWO_ROUTES: &str = "#####\n#^  #\n# # #\n#  $#\n#####";
+
+    fn quiet_game(desc: &str) -> GameState {
+        let mut state = GameState::new(Map::new(desc).unwrap(), Difficulty::Normal);
+        state.waves.clear();
+        state
+    }
+
+    #[test]
+    fn evasive_enemies_take_the_other_route_past_where_they_were_hit() {
+        let mut state = quiet_game(TWO_ROUTES);
+        let spawn = Vector2::new(1, 1);
+        let h = state.spawn(EnemyKind::Evasive, spawn);
+        state.advance();
+        let first = state.enemies.get(h).unwrap().pos;
+        assert_eq!(first, Vector2::new(1, 2));
+        state.enemies.get_mut(h).unwrap().take_damage(1);
+        state.enemies.get_mut(h).unwrap().pos = spawn;
+        state.advance();
+        assert_eq!(state.enemies.get(h).unwrap().pos, Vector2::new(2, 1));
+        for _ in 0..4 {
+            state.advance();
+        }
+        assert!(state.enemies.is_empty(), "the enemy still got through");
+    }
+
+    #[test]
+    fn danger_memory_fades() {
+        let mut state = quiet_game(TWO_ROUTES);
+        let h = state.spawn(EnemyKind::Evasive, Vector2::new(1, 2));
+        state.enemies.get_mut(h).unwrap().take_damage(1);
+        state.enemies.get_mut(h).unwrap().pos = Vector2::new(1, 1);
+        for _ in 0..crate::enemy::DANGER_TICKS - 1 {
+            state.enemies.get_mut(h).unwrap().fade_danger();
+        }
+        assert_eq!(
+            state.enemies.get(h).unwrap().danger_cells(),
+            vec![Vector2::new(1, 2)]
+        );
+        state.enemies.get_mut(h).unwrap().fade_danger();
+        assert!(state.enemies.get(h).unwrap().danger_cells().is_empty());
+        state.advance();
+        assert_eq!(state.enemies.get(h).unwrap().pos, Vector2::new(1, 2));
+    }
+
+    #[test]
+    fn survivors_keep_their_order_and_handles_after_many_kills() {
+        let mut state = quiet_game(
+            "##############\n#^           #\n#            #\n#           $#\n##############",
+        );
+        let handles: Vec<_> = (0..12)
+            .map(|i| state.spawn(EnemyKind::Basic, Vector2::new(1 + i % 6, 1 + i / 6)))
+            .collect();
+        for (i, &h) in handles.iter().enumerate() {
+            if i % 3 != 1 {
+                state.enemies.get_mut(h).unwrap().hp = 0;
+            }
+        }
+        let report = state.advance();
+        assert_eq!(report.killed.len(), 8);
+        let survivors: Vec<_> = handles.iter().skip(1).step_by(3).copied().collect();
+        let ids: Vec<_> = state.enemies.iter().map(|e| e.id).collect();
+        let expected: Vec<_> = survivors
+            .iter()
+            .map(|&h| state.enemies.get(h).unwrap().id)
+            .collect();
+        assert_eq!(ids, expected);
+        assert_eq!(ids, vec![1, 4, 7, 10]);
+        for (i, &h) in handles.iter().enumerate() {
+            assert_eq!(state.enemies.get(h).is_some(), i % 3 == 1);
+        }
+    }
+
+    #[test]
+    fn enemies_spawning_together_spread_out_in_reach_of_the_spawn() {
+        // The pocket at (3, 1) is as close to the spawn point as the cells
+        // below it but can't be walked to, so nobody may spawn there.
+        let mut state = quiet_game("#####\n#^# #\n# ###\n#  $#\n#####");
+        state.phase = Phase::Combat;
+        state.pending = vec![EnemyKind::Basic; 3].into();
+        state.spawn_pending();
+        let cells: Vec<_> = state.enemies.iter().map(|e| (e.id, e.pos)).collect();
+        assert_eq!(
+            cells,
+            vec![
+                (0, Vector2::new(1, 1)),
+                (1, Vector2::new(1, 2)),
+                (2, Vector2::new(1, 3)),
+            ]
+        );
+    }
+
+    const OPEN: &str = "#######\n#^    #\n#     #\n#     #\n#    $#\n#######";
+
+    #[test]
+    fn wall_line_fills_a_diagonal_drag() {
+        let mut state = quiet_game(OPEN);
+        state.money = 100;
+        let (from, to) = (Vector2::new(2, 1), Vector2::new(5, 3));
+        assert_eq!(state.place_wall_line(from, to), (4, None));
+        let inside = |s: &Vector2<usize>| s.x > 0 && s.x < 6 && s.y > 0 && s.y < 5;
+        let built: Vec<_> = state.map.cells_of(Square::Wall).filter(inside).collect();
+        assert_eq!(
+            built,
+            vec![
+                Vector2::new(2, 1),
+                Vector2::new(3, 2),
+                Vector2::new(4, 2),
+                Vector2::new(5, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn wall_line_skips_the_cell_that_would_trap_a_spawn() {
+        let mut state = quiet_game(OPEN);
+        state.money = 100;
+        let (placed, err) = state.place_wall_line(Vector2::new(1, 2), Vector2::new(2, 1));
+        assert_eq!((placed, err), (1, Some(PlaceError::BlocksPath)));
+        assert_eq!(state.map[Vector2::new(1, 2)], Square::Wall);
+        assert_eq!(state.map[Vector2::new(2, 1)], Square::Empty);
+    }
+
+    #[test]
+    fn only_a_drag_builds_walls() {
+        let mut state = quiet_game(OPEN);
+        state.money = 100;
+        state.set_cursor(Vector2::new(3, 2));
+        state.press();
+        state.hold();
+        assert_eq!(state.release(), None);
+        assert_eq!(state.map[Vector2::new(3, 2)], Square::Empty);
+        assert_eq!(state.money, 100);
+        state.press();
+        state.set_cursor(Vector2::new(3, 3));
+        state.hold();
+        assert_eq!(state.release(), Some((2, None)));
+    }
+
+    #[test]
+    fn a_daily_challenge_is_the_same_game_every_time() {
+        let date: Date = "2026-10-14".parse().unwrap();
+        let (a, b) = (GameState::daily(date), GameState::daily(date));
+        assert_eq!(a.map.fingerprint(), b.map.fingerprint());
+        assert_eq!(a.waves, b.waves);
+        let other = GameState::daily("2026-10-15".parse().unwrap());
+        assert_ne!(a.map.fingerprint(), other.map.fingerprint());
+        assert_eq!(a.difficulty, Difficulty::Normal);
+        assert_eq!(a.money, Economy::default().starting_money);
+    }
+
+    #[test]
+    fn cached_overlays_follow_changes_to_the_map() {
+        let mut state = quiet_game(OPEN);
+        state.placing = Some(TowerKind::Basic);
+        state.set_cursor(Vector2::new(3, 2));
+        assert_eq!(state.placement_preview().unwrap().problem, None);
+        assert_eq!(state.chokepoints(), state.map.chokepoints());
+        state.map[Vector2::new(3, 2)] = Square::Wall;
+        assert_eq!(
+            state.placement_preview().unwrap().problem,
+            Some(PlaceError::NotEmpty)
+        );
+        assert_eq!(state.chokepoints(), state.map.chokepoints());
+    }
+
+    #[test]
+    fn a_tick_reports_what_spawned_died_and_got_through() {
+        let mut state = quiet_game("#######\n#^#####\n# ~  $#\n#######");
+        state.pending.push_back(EnemyKind::Basic);
+        let doomed = state.spawn(EnemyKind::Basic, Vector2::new(1, 2));
+        state.enemies.get_mut(doomed).unwrap().hp = 1;
+        state.spawn(EnemyKind::Basic, Vector2::new(4, 2));
+        let report = state.advance();
+        assert_eq!(report.spawned, vec![(Vector2::new(1, 1), EnemyKind::Basic)]);
+        assert_eq!(report.killed, vec![(Vector2::new(2, 2), EnemyKind::Basic)]);
+        assert_eq!(report.leaked, vec![(Vector2::new(5, 2), EnemyKind::Basic)]);
+        assert_eq!(state.kills, 1);
+        assert_eq!(
+            report.summary().unwrap(),
+            format!("1 killed, 1 got through, +${}", report.money_earned)
+        );
+        assert_eq!(state.advance().summary(), None);
+    }
+
+    #[test]
+    fn a_walled_in_breaker_breaks_through_and_gets_to_the_destination() {
+        let mut state = quiet_game("#######\n#^ # $#\n#######");
+        state.spawn(EnemyKind::Breaker, Vector2::new(2, 1));
+        let leaked = (0..40).find(|_| !state.advance().leaked.is_empty());
+        assert!(leaked.is_some());
+        assert_eq!(state.map[Vector2::new(3, 1)], Square::Empty);
+        assert!(state.enemies.is_empty());
+    }
+
+    #[test]
+    fn a_breaker_stops_breaking_walls_once_it_has_broken_its_share() {
+        let mut state = quiet_game("#######\n#^ # $#\n#######");
+        let h = state.spawn(EnemyKind::Breaker, Vector2::new(2, 1));
+        state.enemies.get_mut(h).unwrap().walls_broken = crate::enemy::MAX_WALLS_BROKEN;
+        for _ in 0..40 {
+            state.advance();
+        }
+        assert_eq!(state.map[Vector2::new(3, 1)], Square::Wall);
+        assert!(state.enemies.get(h).is_some());
+    }
+
+    #[test]
+    fn frames_only_repaint_the_map_after_it_changes() {
+        let mut state = quiet_game(OPEN);
+        state.spawn(EnemyKind::Basic, Vector2::new(1, 1));
+        let area = Rect::new(0, 0, 40, 20);
+        let mut cache = MapCache::default();
+        let squares = state.map.grid.len() as u64;
+        assert_eq!(state_frame(&state, area, &mut cache), squares);
+        assert_eq!(state_frame(&state, area, &mut cache), squares);
+        state.advance();
+        assert_eq!(state_frame(&state, area, &mut cache), squares);
+        state.map[Vector2::new(3, 2)] = Square::Wall;
+        assert_eq!(state_frame(&state, area, &mut cache), 2 * squares);
+    }
+
+    /// Draws a frame of `state` and returns how many squares have been
+    /// painted into `cache` so far.
+    fn state_frame(state: &GameState, area: Rect, cache: &mut MapCache) -> u64 {
+        let mut buf = Buffer::empty(area);
+        state.render(area, &mut buf, cache);
+        cache.squares_drawn
+    }
+
+    #[test]
+    fn knockback_pushes_enemies_away_from_the_destination_but_not_into_walls() {
+        let map = Map::new("########\n#     $#\n#^######").unwrap();
+        let field = distance_field(&map);
+        let distance = |e: &Enemy| field[(e.pos.y, e.pos.x)].unwrap();
+        let mut enemy = Enemy::new(0, EnemyKind::Basic, Vector2::new(4, 1), 0);
+        let before = distance(&enemy);
+        knock_back(&map, &field, &mut enemy, 2);
+        assert_eq!(enemy.pos, Vector2::new(2, 1));
+        assert!(distance(&enemy) > before);
+        knock_back(&map, &field, &mut enemy, 5);
+        assert_eq!(enemy.pos, Vector2::new(1, 1));
+        assert!(map.passable(enemy.pos));
+    }
+
+    #[test]
+    fn a_game_is_only_won_once_the_last_wave_is_cleared() {
+        let mut state = quiet_game(OPEN);
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Basic];
+        state.waves.push_back(wave);
+        state.phase = Phase::Build { ticks_left: 1 };
+        assert_eq!(state.outcome(), None);
+        state.advance();
+        // The queue is empty now, but the wave is still being fought.
+        assert!(state.waves.is_empty());
+        assert_eq!(state.phase, Phase::Combat);
+        assert_eq!(state.outcome(), None);
+        for _ in 0..50 {
+            if state.outcome().is_some() {
+                break;
+            }
+            state.advance();
+        }
+        assert_eq!(state.outcome(), Some(Outcome::Win));
+        assert!(state.enemies.is_empty() && state.is_game_over());
+    }
+
+    #[test]
+    fn a_game_is_lost_when_the_lives_run_out() {
+        let mut state = quiet_game(OPEN);
+        state.waves.push_back(Wave::generate(0, Difficulty::Normal));
+        state.lives = 0;
+        assert_eq!(state.outcome(), Some(Outcome::Lose));
+    }
+
+    #[test]
+    fn enemies_cover_ground_in_proportion_to_their_speed() {
+        let corridor = format!(
+            "{}\n#^{}$#\n{}",
+            "#".repeat(34),
+            " ".repeat(30),
+            "#".repeat(34)
+        );
+        let cells_in_ten_ticks = |speed: f32| {
+            let mut state = quiet_game(&corridor);
+            let h = state.spawn(EnemyKind::Basic, Vector2::new(2, 1));
+            state.enemies.get_mut(h).unwrap().speed = speed;
+            for _ in 0..10 {
+                state.advance();
+            }
+            state.enemies.get(h).unwrap().pos.x - 2
+        };
+        assert_eq!(cells_in_ten_ticks(0.5), 5);
+        assert_eq!(cells_in_ten_ticks(1.0), 10);
+        assert_eq!(cells_in_ten_ticks(2.0), 20);
+        assert_eq!(cells_in_ten_ticks(0.3), 3);
+    }
+
+    #[test]
+    fn a_wave_is_not_cleared_while_it_is_still_spawning() {
+        let mut state = quiet_game(OPEN);
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Basic; 3];
+        wave.spawn_interval = 5;
+        state.waves.push_back(wave);
+        state.phase = Phase::Build { ticks_left: 1 };
+        state.advance();
+        assert_eq!((state.enemies.len(), state.pending.len()), (1, 2));
+        for enemy in state.enemies.iter_mut() {
+            enemy.hp = 0;
+        }
+        state.advance();
+        assert!(state.enemies.is_empty());
+        assert!(!state.wave_cleared());
+        assert_eq!(state.phase, Phase::Combat);
+        assert_eq!(state.wave, 0);
+    }
+
+    #[test]
+    fn bosses_cost_their_leak_damage_and_ordinary_enemies_one_life() {
+        let lives_lost = |kind: EnemyKind, difficulty: Difficulty| {
+            let mut state = GameState::new(Map::new("#####\n#^ $#\n#####").unwrap(), difficulty);
+            state.waves.clear();
+            let lives = state.lives;
+            let h = state.spawn(kind, Vector2::new(2, 1));
+            state.enemies.get_mut(h).unwrap().speed = 1.0;
+            assert_eq!(state.advance().leaked.len(), 1);
+            lives - state.lives
+        };
+        assert_eq!(lives_lost(EnemyKind::Basic, Difficulty::Normal), 1);
+        assert_eq!(lives_lost(EnemyKind::Boss, Difficulty::Normal), 5);
+        assert_eq!(lives_lost(EnemyKind::Boss, Difficulty::Hard), 10);
+        assert_eq!(
+            lives_lost(EnemyKind::Boss, Difficulty::Easy),
+            Difficulty::Easy.leak_damage(EnemyKind::Boss)
+        );
+    }
+
+    #[test]
+    fn wave_progress_counts_enemies_gone_out_of_the_whole_wave() {
+        let mut state = quiet_game(OPEN);
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Basic; 3];
+        wave.spawn_interval = 3;
+        state.waves.push_back(wave);
+        state.phase = Phase::Build { ticks_left: 1 };
+        assert_eq!(state.wave_progress(), (0, 0));
+        state.advance();
+        assert_eq!(state.wave_progress(), (0, 3));
+        let kill_all = |state: &mut GameState| {
+            for enemy in state.enemies.iter_mut() {
+                enemy.hp = 0;
+            }
+        };
+        kill_all(&mut state);
+        state.advance();
+        assert_eq!(state.wave_progress(), (1, 3));
+        while !state.pending.is_empty() {
+            state.advance();
+        }
+        assert_eq!(state.wave_progress(), (1, 3));
+        kill_all(&mut state);
+        state.advance();
+        // Cleared, and on to building for the next.
+        assert_eq!(state.wave, 1);
+        assert_eq!(state.wave_progress(), (0, 0));
+    }
+
+    /// The ticks, counted from the one the wave starts on, on which each of
+    /// `n` enemies of a wave sent in `interval` ticks apart spawns.
+    fn spawn_ticks(n: usize, interval: u32) -> Vec<usize> {
+        let mut state = quiet_game(OPEN);
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Basic; n];
+        wave.spawn_interval = interval;
+        state.waves.push_back(wave);
+        state.phase = Phase::Build { ticks_left: 1 };
+        (0..n * (interval as usize + 1) + 1)
+            .flat_map(|tick| vec![tick; state.advance().spawned.len()])
+            .collect()
+    }
+
+    #[test]
+    fn wave_enemies_spawn_spread_out_over_the_interval() {
+        assert_eq!(spawn_ticks(4, 3), vec![0, 3, 6, 9]);
+        assert_eq!(spawn_ticks(3, 1), vec![0, 1, 2]);
+        assert_eq!(spawn_ticks(5, 0), vec![0; 5]);
+    }
+
+    #[test]
+    fn enemy_handles_survive_other_enemies_leaking_within_a_tick() {
+        let mut state = quiet_game("#######\n#^   $#\n#######");
+        let leaker = state.spawn(EnemyKind::Basic, Vector2::new(4, 1));
+        let stayer = state.spawn(EnemyKind::Basic, Vector2::new(2, 1));
+        let id = state.enemies.get(stayer).unwrap().id;
+        assert_eq!(state.advance().leaked.len(), 1);
+        assert!(state.enemies.get(leaker).is_none());
+        let enemy = state.enemies.get(stayer).unwrap();
+        assert_eq!((enemy.id, enemy.pos), (id, Vector2::new(3, 1)));
+    }
+
+    #[test]
+    fn a_new_game_starts_empty_in_the_build_phase_with_every_wave_queued() {
+        let state = GameState::new(Map::new(crate::MAP).unwrap(), Difficulty::Normal);
+        let economy = Economy::default();
+        assert!(state.enemies.is_empty());
+        assert!(state.towers.is_empty());
+        assert_eq!(state.money, economy.starting_money);
+        assert_eq!(state.lives, economy.starting_lives());
+        assert_eq!(state.kills, 0);
+        assert_eq!(state.wave, 0);
+        assert_eq!(state.waves.len(), Difficulty::Normal.wave_count());
+        assert!(matches!(
+            state.phase,
+            Phase::Build {
+                ticks_left: BUILD_TICKS
+            }
+        ));
+    }
+
+    #[test]
+    fn an_enemy_with_an_affinity_heads_for_its_own_destination() {
+        let mut state = quiet_game("#########\n#$ ^    $#\n#########");
+        let spawn = Vector2::new(3, 1);
+        let nearest = state.spawn(EnemyKind::Basic, spawn);
+        let assigned = state.spawn(EnemyKind::Basic, spawn);
+        state.enemies.get_mut(nearest).unwrap().target_dest = None;
+        state.enemies.get_mut(assigned).unwrap().target_dest = Some(1);
+        state.advance();
+        assert!(state.enemies.get(nearest).unwrap().pos.x < spawn.x);
+        assert!(state.enemies.get(assigned).unwrap().pos.x > spawn.x);
+    }
+
+    #[test]
+    fn skipping_the_build_phase_starts_the_next_wave_at_once() {
+        let mut state = GameState::new(Map::new(crate::MAP).unwrap(), Difficulty::Normal);
+        let (queued, money) = (state.waves.len(), state.money);
+        state.skip_to_next_wave();
+        assert!(matches!(state.phase, Phase::Combat));
+        assert_eq!(state.waves.len(), queued - 1);
+        assert_eq!(state.money, money + state.economy.rush_reward(BUILD_TICKS));
+    }
+
+    #[test]
+    fn an_enemy_forced_across_a_hazard_is_hurt_by_it() {
+        let mut state = quiet_game("#######\n#^~~~$#\n#######");
+        let h = state.spawn(EnemyKind::Boss, Vector2::new(1, 1));
+        while state.enemies.get(h).unwrap().pos.x < 3 {
+            state.advance();
+        }
+        let enemy = state.enemies.get(h).unwrap();
+        assert_eq!(state.map[enemy.pos], Square::Hazard);
+        assert!(enemy.hp < EnemyKind::Boss.max_hp());
+    }
+
+    #[test]
+    fn the_preview_says_when_a_tower_is_unaffordable_or_the_cell_is_a_wall() {
+        let mut state = quiet_game(OPEN);
+        state.placing = Some(TowerKind::Basic);
+        state.cursor = Vector2::new(3, 2);
+        state.money = TowerKind::Basic.cost() - 1;
+        let preview = state.placement_preview().unwrap();
+        assert_eq!(preview.cost, TowerKind::Basic.cost());
+        assert!(!preview.affordable);
+        assert_eq!(preview.problem, None);
+        assert!(!preview.is_possible());
+
+        state.money = TowerKind::Basic.cost();
+        state.cursor = Vector2::new(0, 2);
+        let preview = state.placement_preview().unwrap();
+        assert!(preview.affordable);
+        assert_eq!(preview.problem, Some(PlaceError::NotEmpty));
+        assert!(!preview.is_possible());
+    }
+
+    #[test]
+    fn the_no_build_on_path_rule_keeps_towers_off_the_route() {
+        let mut state = quiet_game(OPEN);
+        state.money = 10 * TowerKind::Basic.cost();
+        let on_path = trace_route(&state.map, Vector2::new(1, 1))[1];
+        state.no_build_on_path = true;
+        assert_eq!(
+            state.try_place_tower(TowerKind::Basic, on_path),
+            Err(PlaceError::OnPath)
+        );
+        state.no_build_on_path = false;
+        assert_eq!(state.try_place_tower(TowerKind::Basic, on_path), Ok(()));
+    }
+
+    #[test]
+    fn a_splitter_leaves_two_children_until_it_runs_out_of_splits() {
+        let mut state = quiet_game(OPEN);
+        let pos = Vector2::new(3, 2);
+        let h = state.spawn(EnemyKind::Splitter, pos);
+        state.enemies.get_mut(h).unwrap().hp = 0;
+        state.remove_dead();
+        assert_eq!(state.enemies.len(), SPLIT_CHILDREN);
+        for child in state.enemies.iter() {
+            assert_eq!(child.pos, pos);
+            assert_eq!(child.splits, 1);
+            assert_eq!(child.max_hp, EnemyKind::Splitter.max_hp() / 2);
+        }
+
+        state.enemies = Slots::new();
+        let h = state.spawn(EnemyKind::Splitter, pos);
+        let last = state.enemies.get_mut(h).unwrap();
+        last.splits = crate::enemy::MAX_SPLITS;
+        last.hp = 0;
+        state.remove_dead();
+        assert!(state.enemies.is_empty());
+    }
+
+    #[test]
+    fn pickups_expire_after_their_ttl_unless_collected() {
+        // Waves still to come keep the game going while it is left alone.
+        let mut state = GameState::new(Map::new(OPEN).unwrap(), Difficulty::Normal);
+        let pos = Vector2::new(3, 2);
+        let ttl = 3;
+        state.pickups.push(PickUp {
+            pos,
+            kind: PickUpKind::Money,
+            ttl,
+        });
+        for _ in 1..ttl {
+            state.advance();
+        }
+        assert_eq!(state.pickups.len(), 1);
+        state.advance();
+        assert!(state.pickups.is_empty());
+        assert_eq!(state.collect_pickup(pos), None);
+    }
+
+    #[test]
+    fn collecting_a_pickup_applies_what_it_gives() {
+        let mut state = quiet_game(OPEN);
+        let pos = Vector2::new(3, 2);
+        for kind in PickUpKind::ALL {
+            state.pickups.push(PickUp {
+                pos,
+                kind,
+                ttl: PICKUP_TTL,
+            });
+        }
+        let money = state.money;
+        assert_eq!(state.collect_pickup(pos), Some(PickUpKind::Money));
+        assert_eq!(state.money, money + PICKUP_MONEY);
+        assert_eq!(state.damage_boost, 0);
+        assert_eq!(state.collect_pickup(pos), Some(PickUpKind::DamageBoost));
+        assert_eq!(state.damage_boost, BOOST_TICKS);
+        assert!(state.pickups.is_empty());
+    }
+
+    #[test]
+    fn a_two_by_two_tower_needs_and_takes_all_four_cells() {
+        let mut state = quiet_game(OPEN);
+        state.money = 10 * TowerKind::Heavy.cost();
+        let heavy = TowerKind::Heavy;
+        assert_eq!(heavy.footprint(), (2, 2));
+        assert_eq!(
+            state.try_place_tower(heavy, Vector2::new(5, 2)),
+            Err(PlaceError::NotEmpty)
+        );
+        assert_eq!(
+            state.try_place_tower(heavy, Vector2::new(6, 2)),
+            Err(PlaceError::OffMap)
+        );
+        assert_eq!(state.try_place_tower(heavy, Vector2::new(2, 2)), Ok(()));
+        for (x, y) in [(2, 2), (3, 2), (2, 3), (3, 3)] {
+            assert!(state.map.is_occupied(Vector2::new(x, y)));
+        }
+        assert!(!state.map.is_occupied(Vector2::new(4, 2)));
+        assert_eq!(
+            state.try_place_tower(TowerKind::Basic, Vector2::new(3, 3)),
+            Err(PlaceError::Occupied)
+        );
+    }
+
+    #[test]
+    fn towers_hold_fire_while_an_enemy_is_cloaked() {
+        use crate::enemy::{CLOAK_CYCLE, CLOAK_TICKS};
+        let mut state = quiet_game(OPEN);
+        state
+            .towers
+            .push(Tower::new(TowerKind::Basic, Vector2::new(3, 3)));
+        let h = state.spawn(EnemyKind::Cloaked, Vector2::new(3, 2));
+        state.enemies.get_mut(h).unwrap().age = CLOAK_CYCLE - CLOAK_TICKS;
+        assert!(state.enemies.get(h).unwrap().is_cloaked());
+        state.fire_towers();
+        assert!(state.projectiles.is_empty());
+
+        state.enemies.get_mut(h).unwrap().age = CLOAK_CYCLE;
+        assert!(!state.enemies.get(h).unwrap().is_cloaked());
+        state.fire_towers();
+        assert_eq!(state.projectiles.len(), 1);
+        assert_eq!(state.projectiles[0].target, h);
+    }
+
+    #[test]
+    fn clearing_towers_refunds_them_but_only_between_waves() {
+        let mut state = quiet_game(OPEN);
+        state.money = 1000;
+        state
+            .try_place_tower(TowerKind::Basic, Vector2::new(2, 2))
+            .unwrap();
+        state
+            .try_place_tower(TowerKind::Heavy, Vector2::new(3, 2))
+            .unwrap();
+
+        state.phase = Phase::Combat;
+        let money = state.money;
+        assert_eq!(state.clear_towers(), None);
+        assert_eq!((state.towers.len(), state.money), (2, money));
+
+        state.phase = Phase::Build { ticks_left: 1 };
+        let refund = [TowerKind::Basic, TowerKind::Heavy]
+            .iter()
+            .map(|k| (k.cost() as f64 * REFUND_RATE).round() as u32)
+            .sum::<u32>();
+        assert_eq!(state.clear_towers(), Some(refund));
+        assert_eq!(state.money, money + refund);
+        assert!(state.towers.is_empty());
+        assert!(!state.map.is_occupied(Vector2::new(3, 2)));
+    }
+
+    #[test]
+    fn masons_put_broken_walls_back_unless_that_would_cut_off_a_spawn() {
+        let broken = Vector2::new(3, 1);
+        let mut state = quiet_game("#######\n#^ #  #\n#     #\n#    $#\n#######");
+        state.map[broken] = Square::Empty;
+        state.broken_walls.push(broken);
+        state.spawn(EnemyKind::Mason, Vector2::new(2, 1));
+        state.repair_walls();
+        assert_eq!(state.map[broken], Square::Wall);
+        assert!(state.broken_walls.is_empty());
+
+        let mut state = quiet_game("######\n#^ #$#\n######");
+        state.map[broken] = Square::Empty;
+        state.broken_walls.push(broken);
+        state.spawn(EnemyKind::Mason, Vector2::new(2, 1));
+        state.repair_walls();
+        assert_eq!(state.map[broken], Square::Empty);
+        assert_eq!(state.broken_walls, [broken]);
+    }
+
+    #[test]
+    fn with_spawning_paused_enemies_on_the_map_keep_moving_but_none_join() {
+        let mut state = quiet_game(OPEN);
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Basic; 3];
+        wave.spawn_interval = 1;
+        state.waves.push_back(wave);
+        state.phase = Phase::Build { ticks_left: 1 };
+        state.advance();
+        assert_eq!(state.enemies.len(), 1);
+        let first = state.enemies.iter().next().unwrap().id;
+        let start = state.enemies.iter().next().unwrap().pos;
+
+        state.set_spawning(false);
+        for _ in 0..3 {
+            state.advance();
+        }
+        assert_eq!((state.enemies.len(), state.pending.len()), (1, 2));
+        let enemy = state.enemies.iter().next().unwrap();
+        assert_eq!(enemy.id, first);
+        assert_ne!(enemy.pos, start);
+        assert!(!state.wave_cleared());
+
+        state.set_spawning(true);
+        state.advance();
+        assert_eq!(state.enemies.len(), 2);
+    }
+
+    #[test]
+    fn a_shield_soaks_up_hits_on_its_allies_until_it_is_killed() {
+        use crate::enemy::SHIELD_POOL;
+        let mut state = quiet_game(OPEN);
+        let shield = state.spawn(EnemyKind::Shield, Vector2::new(2, 2));
+        let ally = state.spawn(EnemyKind::Basic, Vector2::new(3, 2));
+        let hit = |state: &mut GameState| {
+            let pos = state.enemies.get(ally).unwrap().pos;
+            state.projectiles.push(Projectile {
+                pos,
+                prev_pos: pos,
+                target: ally,
+                damage: 5,
+                knockback: 0,
+            });
+            state.update_projectiles();
+        };
+        state.assign_shields();
+        assert_eq!(state.enemies.get(ally).unwrap().shielded_by, Some(shield));
+        hit(&mut state);
+        assert_eq!(
+            state.enemies.get(ally).unwrap().hp,
+            EnemyKind::Basic.max_hp()
+        );
+        assert_eq!(
+            state.enemies.get(shield).unwrap().shield_pool,
+            SHIELD_POOL - 5
+        );
+
+        state.enemies.get_mut(shield).unwrap().hp = 0;
+        state.remove_dead();
+        state.assign_shields();
+        assert_eq!(state.enemies.get(ally).unwrap().shielded_by, None);
+        hit(&mut state);
+        assert_eq!(
+            state.enemies.get(ally).unwrap().hp,
+            EnemyKind::Basic.max_hp() - 5
+        );
+    }
+
+    #[test]
+    fn rushers_go_for_the_nearest_tower_and_wear_it_down() {
+        let mut state = quiet_game("#########\n#$      #\n#########");
+        state.money = 100;
+        state
+            .try_place_tower(TowerKind::Economy, Vector2::new(7, 1))
+            .unwrap();
+        let hp = state.towers[0].hp;
+        let h = state.spawn(EnemyKind::Rusher, Vector2::new(4, 1));
+        for _ in 0..20 {
+            if state.enemies.get(h).unwrap().pos.x == 6 {
+                break;
+            }
+            state.advance();
+            assert!(state.enemies.get(h).unwrap().pos.x >= 4);
+        }
+        assert_eq!(state.enemies.get(h).unwrap().pos.x, 6);
+        state.advance();
+        assert_eq!(state.towers[0].hp, hp - RUSH_DAMAGE);
+        assert_eq!(state.enemies.get(h).unwrap().pos.x, 6);
+    }
+
+    #[test]
+    fn a_tower_out_of_ammo_holds_fire_until_it_has_reloaded() {
+        let heavy = TowerKind::Heavy;
+        let magazine = heavy.magazine().unwrap();
+        let mut state = quiet_game(OPEN);
+        state.towers.push(Tower::new(heavy, Vector2::new(2, 2)));
+        state.spawn(EnemyKind::Boss, Vector2::new(2, 1));
+        let mut shots = 0;
+        while state.towers[0].ammo != Some(0) {
+            state.fire_towers();
+            shots = state.projectiles.len();
+        }
+        assert_eq!(shots, magazine as usize);
+        assert_eq!(state.towers[0].reloading, heavy.magazine_reload_ticks());
+
+        for _ in 0..heavy.magazine_reload_ticks() {
+            state.fire_towers();
+        }
+        assert_eq!(state.projectiles.len(), shots);
+        assert_eq!(state.towers[0].ammo, Some(magazine));
+        for _ in 0..=heavy.reload_ticks() {
+            state.fire_towers();
+        }
+        assert_eq!(state.projectiles.len(), shots + 1);
+    }
+
+    #[test]
+    fn range_heat_is_highest_where_tower_ranges_overlap() {
+        let mut state = quiet_game("############\n#^        $#\n############");
+        for (kind, x) in [
+            (TowerKind::Basic, 2),
+            (TowerKind::Basic, 6),
+            (TowerKind::Economy, 4),
+        ] {
+            state.towers.push(Tower::new(kind, Vector2::new(x, 1)));
+        }
+        let heat = state.range_heat();
+        let at = |x: usize| heat[(1, x)];
+        assert_eq!(at(4), 2);
+        assert_eq!(at(1), 1);
+        assert_eq!(at(9), 1);
+        assert_eq!(at(10), 0);
+    }
+
+    #[test]
+    fn edge_spawns_start_on_the_border_somewhere_they_can_get_out_from() {
+        let map = Map::new("## ####\n#     #\n      #\n#   #$#\n#### ##").unwrap();
+        let field = distance_field(&map);
+        let edges = map.edge_cells();
+        for seed in 0..10 {
+            let mut state = GameState::new(map.clone(), Difficulty::Normal).with_seed(seed);
+            let mut wave = Wave::generate(0, Difficulty::Normal);
+            wave.enemies = vec![EnemyKind::Basic];
+            wave.formation = Formation::Single;
+            wave.edge_spawns = true;
+            state.waves = VecDeque::from(vec![wave]);
+            state.phase = Phase::Build { ticks_left: 1 };
+            let report = state.advance();
+            let (pos, _) = report.spawned[0];
+            assert!(edges.contains(&pos));
+            assert!(reaches(&map, &field, pos));
+        }
+    }
+
+    #[test]
+    fn threat_aware_enemies_go_the_long_way_round_a_defended_corridor() {
+        let map = "###########\n#^       $#\n# ### ### #\n# ####### #\n\
+                   # ####### #\n# ####### #\n#         #\n###########";
+        let first_step = |threat_aware: bool| {
+            let mut state = quiet_game(map);
+            state.threat_aware = threat_aware;
+            state.money = 100;
+            state
+                .try_place_tower(TowerKind::Basic, Vector2::new(5, 2))
+                .unwrap();
+            let h = state.spawn(EnemyKind::Basic, Vector2::new(1, 1));
+            state.enemies.get_mut(h).unwrap().speed = 1.0;
+            state.advance();
+            state.enemies.get(h).unwrap().pos
+        };
+        assert_eq!(first_step(false), Vector2::new(2, 1));
+        assert_eq!(first_step(true), Vector2::new(1, 2));
+    }
+
+    #[test]
+    fn a_lone_tower_threatens_just_the_cells_it_can_shoot_at() {
+        let mut state = quiet_game("#########\n#^      #\n#   #   #\n#      $#\n#########");
+        let tower = Tower::new(TowerKind::Basic, Vector2::new(2, 2));
+        state.towers.push(tower.clone());
+        let field = state.threat_field();
+        let (h, w) = (state.map.grid.nrows(), state.map.grid.ncols());
+        let in_range = tower.cells_in_range(w, h);
+        let dps = TowerKind::Basic.dps();
+        // Behind the wall, though in range.
+        let hidden = Vector2::new(5, 2);
+        assert!(in_range.contains(&hidden));
+        for y in 0..h {
+            for x in 0..w {
+                let cell = Vector2::new(x, y);
+                let threatened = in_range.contains(&cell) && cell != hidden;
+                let expected = if threatened { dps } else { 0.0 };
+                assert_eq!(field[(y, x)], expected, "at {:?}", cell);
+            }
+        }
+        state.towers.clear();
+        assert_eq!(state.threat_field().sum(), 0.0);
+    }
+
+    #[test]
+    fn routes_are_worked_out_within_the_budget_taking_turns() {
+        let row = |inside: &str| format!("#{}#", inside);
+        let desc = [
+            "#".repeat(42),
+            row(&" ".repeat(40)),
+            row(&format!("^{}$", " ".repeat(38))),
+            row(&" ".repeat(40)),
+            "#".repeat(42),
+        ]
+        .join("\n");
+        let mut state = quiet_game(&desc).with_path_budget(2);
+        let gliders: Vec<_> = (0..7)
+            .map(|_| state.spawn(EnemyKind::Glider, Vector2::new(1, 2)))
+            .collect();
+        for _ in 0..4 {
+            state.advance();
+            assert!(state.routes_computed <= 2);
+        }
+        for h in gliders {
+            assert!(state.enemies.get(h).unwrap().routed_at.is_some());
+        }
+    }
+
+    #[test]
+    fn desperate_enemies_rush_unless_slowed() {
+        let corridor = format!(
+            "{}\n#^{}$#\n{}",
+            "#".repeat(34),
+            " ".repeat(30),
+            "#".repeat(34)
+        );
+        let cells_in_ten_ticks = |hp: u32, slowed: bool| {
+            let mut state = quiet_game(&corridor);
+            state.panic = true;
+            if slowed {
+                state.fields = (2..32)
+                    .map(|x| Field {
+                        pos: Vector2::new(x, 1),
+                        kind: FieldKind::Slow,
+                        ttl: 100,
+                    })
+                    .collect();
+            }
+            let h = state.spawn(EnemyKind::Basic, Vector2::new(2, 1));
+            let enemy = state.enemies.get_mut(h).unwrap();
+            enemy.speed = 1.0;
+            enemy.hp = hp;
+            for _ in 0..10 {
+                state.advance();
+            }
+            state.enemies.get(h).unwrap().pos.x - 2
+        };
+        let full = EnemyKind::Basic.max_hp();
+        assert_eq!(cells_in_ten_ticks(full, false), 10);
+        assert!(cells_in_ten_ticks(1, false) > 10);
+        assert!(cells_in_ten_ticks(1, true) < 10);
+        assert!(cells_in_ten_ticks(1, true) > cells_in_ten_ticks(full, true));
+    }
+
+    #[test]
+    fn the_focus_is_the_middle_of_the_biggest_bunch_of_enemies() {
+        let mut state = quiet_game(&format!(
+            "{}\n#^{}$#\n{}",
+            "#".repeat(34),
+            " ".repeat(30),
+            "#".repeat(34)
+        ));
+        assert_eq!(state.focus_point(), Vector2::new(32, 1));
+        for x in [4, 6, 8] {
+            state.spawn(EnemyKind::Basic, Vector2::new(x, 1));
+        }
+        state.spawn(EnemyKind::Basic, Vector2::new(28, 1));
+        assert_eq!(state.focus_point(), Vector2::new(6, 1));
+    }
+
+    #[test]
+    fn a_border_moves_the_map_in_by_one_cell_each_way() {
+        let top_left = |border: Border| {
+            let mut state = quiet_game(OPEN);
+            state.border = border;
+            let area = Rect::new(0, 0, 40, 20);
+            let mut buf = Buffer::empty(area);
+            state.render(area, &mut buf, &mut MapCache::default());
+            let map_area = state.viewport.get().map_area;
+            assert_eq!(buf.get(map_area.x, map_area.y).symbol, "#");
+            (map_area.x, map_area.y, buf)
+        };
+        let (x, y, _) = top_left(Border::None);
+        assert_eq!((x, y), (0, 0));
+        let (x, y, buf) = top_left(Border::Map);
+        assert_eq!((x, y), (1, 1));
+        assert_eq!(buf.get(0, 0).symbol, "┌");
+        let (x, y, _) = top_left(Border::All);
+        assert_eq!((x, y), (2, 2));
+    }
+
+    #[test]
+    fn against_base_hp_a_leak_costs_what_the_enemy_has_left() {
+        let leak = |base_hp: u32, hp: u32| {
+            let economy = Economy {
+                base_hp: Some(base_hp),
+                ..Economy::default()
+            };
+            let mut state = quiet_game("#####\n#^ $#\n#####").with_economy(&economy);
+            assert_eq!(state.lives, base_hp);
+            let h = state.spawn(EnemyKind::Basic, Vector2::new(2, 1));
+            let enemy = state.enemies.get_mut(h).unwrap();
+            enemy.speed = 1.0;
+            enemy.hp = hp;
+            state.advance();
+            state
+        };
+        let full = EnemyKind::Basic.max_hp();
+        assert_eq!(leak(50, 1).lives, 49);
+        assert_eq!(leak(50, full).lives, 50 - full);
+        let state = leak(full, full);
+        assert_eq!(state.lives, 0);
+        assert_eq!(state.outcome(), Some(Outcome::Lose));
+    }
+
+    #[test]
+    fn economy_towers_earn_money_every_tick_and_never_shoot() {
+        let eco = TowerKind::Economy;
+        assert!(!eco.attacks());
+        let mut state = GameState::new(Map::new(OPEN).unwrap(), Difficulty::Normal);
+        state.money = eco.cost();
+        state.try_place_tower(eco, Vector2::new(3, 2)).unwrap();
+        assert_eq!(state.money, 0);
+        state.spawn(EnemyKind::Boss, Vector2::new(3, 3));
+        for _ in 0..5 {
+            state.advance();
+        }
+        assert_eq!(state.money, 5 * eco.income());
+        assert!(state.projectiles.is_empty());
+    }
+
+    #[test]
+    fn in_chaos_mode_deaths_chain_through_a_cluster_the_same_way_every_time() {
+        let chain = || {
+            let mut state = quiet_game(OPEN);
+            state.chaos = Some(Chaos::default());
+            let handles: Vec<_> = (1..=5)
+                .map(|x| {
+                    let h = state.spawn(EnemyKind::Basic, Vector2::new(x, 2));
+                    state.enemies.get_mut(h).unwrap().hp = if x < 5 { 5 } else { 10 };
+                    h
+                })
+                .collect();
+            let far = state.spawn(EnemyKind::Basic, Vector2::new(3, 4));
+            state.enemies.get_mut(handles[0]).unwrap().hp = 0;
+            state.remove_dead();
+            assert_eq!(state.kills, 4);
+            let last = state.enemies.get(handles[4]).unwrap().hp;
+            let far = state.enemies.get(far).unwrap().hp;
+            (last, far, state.enemies.len())
+        };
+        assert_eq!(chain(), (5, EnemyKind::Basic.max_hp(), 2));
+        assert_eq!(chain(), chain());
+    }
+
+    #[test]
+    fn enemies_over_the_soft_cap_drain_lives() {
+        let economy = Economy {
+            soft_cap: 2,
+            overflow_drain: 0.25,
+            ..Economy::default()
+        };
+        let mut state = quiet_game(OPEN).with_economy(&economy);
+        let lives = state.lives;
+        for x in 1..=2 {
+            state.spawn(EnemyKind::Basic, Vector2::new(x, 2));
+        }
+        for _ in 0..10 {
+            state.drain_overflow();
+        }
+        assert_eq!(state.lives, lives);
+        for x in 3..=4 {
+            state.spawn(EnemyKind::Basic, Vector2::new(x, 2));
+        }
+        state.drain_overflow();
+        assert_eq!(state.lives, lives);
+        state.drain_overflow();
+        assert_eq!(state.lives, lives - 1);
+        for _ in 0..4 {
+            state.drain_overflow();
+        }
+        assert_eq!(state.lives, lives - 3);
+    }
+
+    #[test]
+    fn screen_cells_map_back_onto_the_map_cells_drawn_there() {
+        let drawn = |border: Border| {
+            let mut state = quiet_game(OPEN);
+            state.border = border;
+            let area = Rect::new(0, 0, 40, 20);
+            state.render(area, &mut Buffer::empty(area), &mut MapCache::default());
+            state
+        };
+        let cell = |x, y| Some(Vector2::new(x, y));
+        let state = drawn(Border::None);
+        assert_eq!(state.screen_to_cell(1, 1), cell(0, 0));
+        assert_eq!(state.screen_to_cell(7, 6), cell(6, 5));
+        assert_eq!(state.screen_to_cell(8, 1), None);
+        assert_eq!(state.screen_to_cell(1, 7), None);
+        assert_eq!(state.screen_to_cell(0, 0), None);
+
+        let state = drawn(Border::Map);
+        assert_eq!(state.screen_to_cell(2, 2), cell(0, 0));
+        assert_eq!(state.screen_to_cell(1, 1), None);
+        assert_eq!(state.screen_to_cell(8, 7), cell(6, 5));
+
+        // Scrolled across a map bigger than the view.
+        state.viewport.set(Viewport {
+            map_area: Rect::new(1, 1, 10, 5),
+            origin: Vector2::new(20, 7),
+        });
+        assert_eq!(state.screen_to_cell(2, 2), cell(20, 7));
+        assert_eq!(state.screen_to_cell(11, 6), cell(29, 11));
+        assert_eq!(state.screen_to_cell(12, 2), None);
+        assert_eq!(state.screen_to_cell(2, 7), None);
+    }
+
+    #[test]
+    fn a_tower_flashes_for_a_few_ticks_after_it_fires() {
+        let mut state = quiet_game(OPEN);
+        state
+            .towers
+            .push(Tower::new(TowerKind::Heavy, Vector2::new(2, 2)));
+        assert_eq!(state.towers[0].fire_anim, 0);
+        let h = state.spawn(EnemyKind::Boss, Vector2::new(2, 1));
+        state.fire_towers();
+        assert_eq!(state.towers[0].fire_anim, FIRE_ANIM_TICKS);
+        state.enemies.remove(h);
+        for left in (0..FIRE_ANIM_TICKS).rev() {
+            state.fire_towers();
+            assert_eq!(state.towers[0].fire_anim, left);
+        }
+        state.fire_towers();
+        assert_eq!(state.towers[0].fire_anim, 0);
+    }
+
+    #[test]
+    fn hits_during_the_cooldown_after_a_hit_do_nothing() {
+        let mut state = quiet_game(OPEN).with_hit_cooldown(3);
+        let pos = Vector2::new(3, 2);
+        let h = state.spawn(EnemyKind::Boss, pos);
+        let shoot = |state: &mut GameState| {
+            for _ in 0..2 {
+                state.projectiles.push(Projectile {
+                    pos,
+                    prev_pos: pos,
+                    target: h,
+                    damage: 4,
+                    knockback: 0,
+                });
+            }
+            state.update_projectiles();
+            state.enemies.get(h).unwrap().hp
+        };
+        let full = EnemyKind::Boss.max_hp();
+        assert_eq!(shoot(&mut state), full - 4);
+        state.tick += 2;
+        assert_eq!(shoot(&mut state), full - 4);
+        assert!(state.projectiles.is_empty());
+        state.tick += 1;
+        assert_eq!(shoot(&mut state), full - 8);
+    }
+
+    #[test]
+    fn the_hover_highlight_follows_the_mouse_and_goes_when_it_leaves_the_map() {
+        let mut state = quiet_game(OPEN);
+        let area = Rect::new(0, 0, 40, 20);
+        let mut cache = MapCache::default();
+        let highlighted = |state: &GameState, cache: &mut MapCache| {
+            let mut buf = Buffer::empty(area);
+            state.render(area, &mut buf, cache);
+            (0..area.height)
+                .flat_map(|y| (0..area.width).map(move |x| (x, y)))
+                .filter(|&(x, y)| buf.get(x, y).bg == state.theme.hover)
+                .collect::<Vec<_>>()
+        };
+        highlighted(&state, &mut cache);
+        state.placing = Some(TowerKind::Basic);
+        state.hover(4, 3);
+        assert_eq!(state.hovered_cell(), Some(Vector2::new(3, 2)));
+        assert_eq!(state.cursor, Vector2::new(3, 2));
+        assert_eq!(highlighted(&state, &mut cache), [(3, 2)]);
+        state.hover(3, 4);
+        assert_eq!(highlighted(&state, &mut cache), [(2, 3)]);
+        state.hover(30, 15);
+        assert_eq!(state.hovered_cell(), None);
+        assert_eq!(state.cursor, Vector2::new(2, 3));
+        assert!(highlighted(&state, &mut cache).is_empty());
+    }
+
+    #[test]
+    fn the_rush_bonus_grows_with_the_build_time_saved() {
+        let bonus = |ticks_left: u32| {
+            let mut state = GameState::new(Map::new(OPEN).unwrap(), Difficulty::Normal);
+            state.phase = Phase::Build { ticks_left };
+            let money = state.money;
+            state.skip_to_next_wave();
+            assert_eq!(state.phase, Phase::Combat);
+            state.money - money
+        };
+        assert!(bonus(20) > bonus(5));
+        assert!(bonus(5) > 0);
+        assert_eq!(bonus(20), Economy::default().rush_reward(20));
+        assert_eq!(bonus(0), 0);
+    }
+
+    #[test]
+    fn coordinates_read_as_a_pair_or_dashes_off_the_map() {
+        assert_eq!(coords_label(Some(Vector2::new(3, 12))), "(3, 12)");
+        assert_eq!(coords_label(None), "--");
+
+        let mut state = quiet_game(OPEN);
+        state.show_coords = true;
+        state.cursor = Vector2::new(2, 3);
+        state.mouse = Some((30, 15));
+        let area = Rect::new(0, 0, 60, 20);
+        let mut buf = Buffer::empty(area);
+        state.render(area, &mut buf, &mut MapCache::default());
+        let text: String = buf.content().iter().map(|c| c.symbol.as_str()).collect();
+        assert!(text.contains("cursor: (2, 3)  mouse: --"));
+    }
+
+    #[test]
+    fn each_wave_comes_in_only_from_its_own_spawn_points() {
+        let mut state = quiet_game("#########\n#^     ^#\n#       #\n#   $   #\n#########");
+        let spawns: Vec<_> = state.map.spawn_points().collect();
+        for _ in 0..2 {
+            let mut wave = Wave::generate(0, Difficulty::Normal);
+            wave.enemies = vec![EnemyKind::Basic; 3];
+            wave.spawn_interval = 2;
+            wave.formation = Formation::Single;
+            state.waves.push_back(wave);
+        }
+        assert!(state.set_active_spawns(0, vec![2]).is_err());
+        assert!(state.set_active_spawns(2, vec![0]).is_err());
+        state.set_active_spawns(0, vec![1]).unwrap();
+        state.set_active_spawns(1, vec![0]).unwrap();
+        for (wave, spawn) in [(0, spawns[1]), (1, spawns[0])] {
+            state.skip_to_next_wave();
+            assert_eq!(state.wave, wave);
+            let mut origins = Vec::new();
+            while !state.pending.is_empty() || origins.is_empty() {
+                origins.extend(state.advance().spawned.into_iter().map(|(pos, _)| pos));
+            }
+            assert_eq!(origins, [spawn; 3]);
+            state.enemies = Slots::new();
+            state.advance();
+        }
+    }
+
+    #[test]
+    fn a_lost_game_can_be_watched_but_not_built_on() {
+        let corridor = "############\n#^         $#\n#          #\n############";
+        let lose = |spectate: bool| {
+            let mut state = quiet_game(corridor);
+            if spectate {
+                state = state.with_spectating();
+            }
+            state.lives = 1;
+            state.money = 1000;
+            let leaker = state.spawn(EnemyKind::Basic, Vector2::new(10, 1));
+            state.enemies.get_mut(leaker).unwrap().speed = 1.0;
+            let h = state.spawn(EnemyKind::Basic, Vector2::new(2, 1));
+            state.enemies.get_mut(h).unwrap().speed = 1.0;
+            state.advance();
+            assert_eq!(state.outcome(), Some(Outcome::Lose));
+            (state, h)
+        };
+        let (mut state, h) = lose(true);
+        assert!(state.is_spectating());
+        assert!(!state.is_game_over());
+        let pos = state.enemies.get(h).unwrap().pos;
+        state.advance();
+        assert_ne!(state.enemies.get(h).unwrap().pos, pos);
+        let free = Vector2::new(5, 2);
+        assert_eq!(
+            state.try_place_tower(TowerKind::Basic, free),
+            Err(PlaceError::GameOver)
+        );
+        assert_eq!(state.try_place_wall(free), Err(PlaceError::GameOver));
+
+        let (mut state, h) = lose(false);
+        assert!(state.is_game_over());
+        let pos = state.enemies.get(h).unwrap().pos;
+        state.advance();
+        assert_eq!(state.enemies.get(h).unwrap().pos, pos);
+    }
+
+    #[test]
+    fn suggested_placements_are_buildable_cells_covering_the_most_route() {
+        let state = GameState::new(Map::new(crate::MAP).unwrap(), Difficulty::Normal);
+        let route: Vec<_> = state
+            .map
+            .spawn_points()
+            .flat_map(|s| trace_route(&state.map, s))
+            .collect();
+        let coverage = |c: Vector2<usize>| {
+            let tower = Tower::new(TowerKind::Basic, c);
+            route.iter().filter(|&&r| tower.in_range(r)).count()
+        };
+        let suggestions = state.suggest_placements(5);
+        assert!(!suggestions.is_empty() && suggestions.len() <= 5);
+        for &c in suggestions.iter() {
+            assert!(state.check_tower_site(&[c]).is_ok());
+            assert!(!route.contains(&c));
+        }
+        let covered: Vec<_> = suggestions.iter().map(|&c| coverage(c)).collect();
+        assert!(covered.windows(2).all(|w| w[0] >= w[1]));
+        let best = state
+            .map
+            .cells_of(Square::Empty)
+            .filter(|c| !route.contains(c) && state.check_tower_site(&[*c]).is_ok())
+            .map(coverage)
+            .max();
+        assert_eq!(Some(covered[0]), best);
+        assert!(suggestions
+            .iter()
+            .any(|&c| route.iter().any(|&r| map::manhattan(c, r) == 1)));
+    }
+
+    #[test]
+    fn later_enemies_in_a_ramped_wave_are_tougher() {
+        let mut state = quiet_game(OPEN);
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Basic; 4];
+        wave.spawn_interval = 1;
+        wave.formation = Formation::Single;
+        wave.hp_ramp = 0.1;
+        state.waves.push_back(wave);
+        state.skip_to_next_wave();
+        while !state.pending.is_empty() {
+            state.advance();
+        }
+        let mut enemies: Vec<_> = state.enemies.iter().map(|e| (e.id, e.max_hp)).collect();
+        enemies.sort();
+        let base = EnemyKind::Basic.max_hp();
+        let hp: Vec<_> = enemies.into_iter().map(|(_, hp)| hp).collect();
+        assert_eq!(hp, [base, base + 1, base + 2, base + 3]);
+    }
+
+    #[test]
+    fn path_heat_is_highest_where_enemy_paths_overlap() {
+        let mut state = quiet_game("###########\n#^       $#\n###########");
+        state.spawn(EnemyKind::Basic, Vector2::new(2, 1));
+        state.spawn(EnemyKind::Basic, Vector2::new(5, 1));
+        let heat = state.path_heat();
+        let row: Vec<_> = (0..11).map(|x| heat[(1, x)]).collect();
+        assert_eq!(row, [0, 0, 0, 1, 1, 1, 2, 2, 2, 2, 0]);
+    }
+
+    #[test]
+    fn a_telegraphed_enemy_is_a_countdown_marker_before_it_spawns() {
+        let mut state = quiet_game(OPEN).with_telegraph(3);
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Basic];
+        wave.formation = Formation::Single;
+        state.waves.push_back(wave);
+        state.skip_to_next_wave();
+        for left in (1..=3).rev() {
+            state.advance();
+            assert!(state.enemies.is_empty());
+            assert_eq!(state.telegraphed.len(), 1);
+            assert_eq!(state.telegraphed[0].ticks_left, left);
+            assert_eq!(state.telegraphed[0].pos, Vector2::new(1, 1));
+            assert!(!state.wave_cleared());
+        }
+        state.advance();
+        assert!(state.telegraphed.is_empty());
+        assert_eq!(state.enemies.len(), 1);
+    }
+
+    #[test]
+    fn the_one_line_summary_shows_the_current_values() {
+        let mut state = quiet_game(OPEN);
+        state.tick = 42;
+        state.lives = 18;
+        state.money = 120;
+        state
+            .towers
+            .push(Tower::new(TowerKind::Basic, Vector2::new(3, 2)));
+        for x in 1..=3 {
+            state.spawn(EnemyKind::Basic, Vector2::new(x, 3));
+        }
+        state.waves.push_back(Wave::generate(1, Difficulty::Normal));
+        state.wave = 1;
+        assert_eq!(
+            state.to_string(),
+            "tick=42 lives=18 money=120 enemies=3 towers=1 wave=2"
+        );
+    }
+
+    #[test]
+    fn towers_turn_to_face_what_they_shoot_at() {
+        let tower = Vector2::new(3, 2);
+        for (target, facing) in [
+            (Vector2::new(5, 2), Direction::East),
+            (Vector2::new(1, 3), Direction::West),
+            (Vector2::new(3, 4), Direction::South),
+            (Vector2::new(3, 1), Direction::North),
+        ] {
+            let mut state = quiet_game(OPEN);
+            state.towers.push(Tower::new(TowerKind::Basic, tower));
+            state.spawn(EnemyKind::Boss, target);
+            state.fire_towers();
+            assert_eq!(state.towers[0].facing, facing, "towards {:?}", target);
+        }
+        // With nothing to shoot at, it keeps facing the last way it fired.
+        let mut state = quiet_game(OPEN);
+        state.towers.push(Tower::new(TowerKind::Basic, tower));
+        let h = state.spawn(EnemyKind::Boss, Vector2::new(1, 2));
+        state.fire_towers();
+        state.enemies.remove(h);
+        state.fire_towers();
+        assert_eq!(state.towers[0].facing, Direction::West);
+    }
+
+    #[test]
+    fn at_the_tower_cap_nothing_more_is_built_until_towers_are_sold() {
+        let economy = Economy {
+            max_towers: Some(2),
+            ..Economy::default()
+        };
+        let mut state = quiet_game(OPEN).with_economy(&economy);
+        state.money = 1000;
+        let basic = TowerKind::Basic;
+        state.try_place_tower(basic, Vector2::new(2, 2)).unwrap();
+        assert!(!state.at_tower_cap());
+        state.try_place_tower(basic, Vector2::new(4, 2)).unwrap();
+        assert!(state.at_tower_cap());
+        let money = state.money;
+        let third = Vector2::new(3, 3);
+        let err = state.try_place_tower(basic, third).unwrap_err();
+        assert_eq!(err, PlaceError::TowerCap);
+        assert!(!err.to_string().is_empty());
+        assert_eq!((state.towers.len(), state.money), (2, money));
+        assert!(!state.map.is_occupied(third));
+
+        state.clear_towers().unwrap();
+        assert!(!state.at_tower_cap());
+        assert_eq!(state.try_place_tower(basic, third), Ok(()));
+    }
+
+    #[test]
+    fn a_wall_built_in_front_of_a_glider_reroutes_it_on_the_next_tick() {
+        let mut state = quiet_game(OPEN);
+        state.money = 1000;
+        let h = state.spawn(EnemyKind::Glider, Vector2::new(1, 2));
+        state.advance();
+        // Out of budget, so only a change to the map sends it rerouting.
+        state.path_budget = 0;
+        let enemy = state.enemies.get(h).unwrap();
+        let next = map::line(enemy.pos, enemy.waypoints[0])[1];
+        state.try_place_wall(next).unwrap();
+        state.advance();
+        let enemy = state.enemies.get(h).unwrap();
+        assert_eq!(state.routes_computed, 1);
+        assert_eq!(enemy.routed_generation, Some(state.map.generation()));
+        assert_ne!(enemy.pos, next);
+        assert!(state.map.passable(enemy.pos));
+    }
+
+    #[test]
+    fn empty_cells_are_drawn_as_dots_only_when_asked_to() {
+        let symbols = |dot: bool| {
+            let mut state = quiet_game(OPEN);
+            if dot {
+                state.theme.empty_glyph = '·';
+            }
+            state.spawn(EnemyKind::Basic, Vector2::new(2, 2));
+            let area = Rect::new(0, 0, 40, 20);
+            let mut buf = Buffer::empty(area);
+            state.render(area, &mut buf, &mut MapCache::default());
+            let map_area = state.viewport.get().map_area;
+            let at = |x: u16, y: u16| buf.get(map_area.x + x, map_area.y + y).symbol.clone();
+            (at(4, 3), at(2, 2))
+        };
+        let (empty, enemy) = symbols(true);
+        assert_eq!(empty, "·");
+        let (blank, plain_enemy) = symbols(false);
+        assert_eq!(blank, " ");
+        // What stands on an empty cell is drawn over the dot.
+        assert_eq!(enemy, plain_enemy);
+        assert_ne!(enemy, "·");
+    }
+
+    #[test]
+    fn a_locked_tower_keeps_shooting_its_target_when_a_closer_one_turns_up() {
+        let targets = |lock: bool| {
+            let mut state = quiet_game(OPEN);
+            let mut tower = Tower::new(TowerKind::Basic, Vector2::new(3, 2));
+            tower.target_lock = lock;
+            state.towers.push(tower);
+            let first = state.spawn(EnemyKind::Boss, Vector2::new(1, 2));
+            let mut shots = Vec::new();
+            for tick in 0..12 {
+                if tick == 1 {
+                    state.spawn(EnemyKind::Boss, Vector2::new(3, 3));
+                }
+                state.fire_towers();
+                shots.extend(state.projectiles.drain(..).map(|p| p.target));
+            }
+            (first, shots)
+        };
+        let (first, shots) = targets(true);
+        assert!(shots.len() > 1);
+        assert!(shots.iter().all(|&h| h == first));
+        let (first, shots) = targets(false);
+        assert_eq!(shots[0], first);
+        assert!(shots[1..].iter().all(|&h| h != first));
+    }
+
+    #[test]
+    fn clearing_a_wave_heals_up_to_the_cap_but_fighting_one_does_not() {
+        let economy = Economy {
+            wave_heal: Some(3),
+            ..Economy::default()
+        };
+        let mut state = quiet_game(OPEN).with_economy(&economy);
+        let max = state.lives;
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Boss];
+        state.waves.push_back(wave);
+        state.phase = Phase::Build { ticks_left: 1 };
+        state.lives = max - 5;
+        state.advance();
+        assert_eq!(state.phase, Phase::Combat);
+        state.advance();
+        assert_eq!(state.lives, max - 5);
+
+        for enemy in state.enemies.iter_mut() {
+            enemy.hp = 0;
+        }
+        while state.phase == Phase::Combat {
+            state.advance();
+        }
+        assert_eq!(state.lives, max - 2);
+        state.end_wave();
+        assert_eq!(state.lives, max);
+
+        // Easy heals by itself when the economy doesn't say otherwise.
+        assert_eq!(Difficulty::Easy.wave_heal(), 2);
+        assert_eq!(Difficulty::Normal.wave_heal(), 0);
+    }
+
+    #[test]
+    fn a_detector_reveals_cloaked_enemies_in_its_range_only() {
+        use crate::enemy::{CLOAK_CYCLE, CLOAK_TICKS};
+        let mut state =
+            quiet_game("############\n#^         #\n#          #\n#         $#\n############");
+        state
+            .towers
+            .push(Tower::new(TowerKind::Basic, Vector2::new(5, 2)));
+        state
+            .towers
+            .push(Tower::new(TowerKind::Detector, Vector2::new(2, 1)));
+        let near = state.spawn(EnemyKind::Cloaked, Vector2::new(3, 2));
+        let far = state.spawn(EnemyKind::Cloaked, Vector2::new(8, 2));
+        for enemy in state.enemies.iter_mut() {
+            enemy.age = CLOAK_CYCLE - CLOAK_TICKS;
+        }
+        state.reveal_cloaked();
+        assert!(!state.enemies.get(near).unwrap().is_cloaked());
+        assert!(state.enemies.get(far).unwrap().is_cloaked());
+        for _ in 0..6 {
+            state.fire_towers();
+        }
+        assert!(!state.projectiles.is_empty());
+        assert!(state.projectiles.iter().all(|p| p.target == near));
+
+        // Taking the detector down cloaks the enemy again.
+        state.towers.pop();
+        state.reveal_cloaked();
+        assert!(state.enemies.get(near).unwrap().is_cloaked());
+    }
+
+    #[test]
+    fn the_game_is_only_won_once_the_sudden_death_wave_is_cleared() {
+        let mut state = quiet_game(OPEN);
+        let mut wave = Wave::generate(0, Difficulty::Normal);
+        wave.enemies = vec![EnemyKind::Basic];
+        state.waves.push_back(wave);
+        let mut state = state.with_sudden_death();
+        assert!(state.waves.back().unwrap().sudden_death);
+        let clear_wave = |state: &mut GameState| {
+            state.phase = Phase::Build { ticks_left: 1 };
+            state.advance();
+            while state.phase == Phase::Combat {
+                assert_eq!(state.outcome(), None);
+                for enemy in state.enemies.iter_mut() {
+                    enemy.hp = 0;
+                }
+                state.advance();
+            }
+        };
+        clear_wave(&mut state);
+        assert_eq!(state.outcome(), None);
+        clear_wave(&mut state);
+        assert_eq!(state.outcome(), Some(Outcome::Win));
+    }
+
+    #[test]
+    fn slow_fields_hold_enemies_back_until_they_wear_off() {
+        let corridor = format!(
+            "{}\n#^{}$#\n{}",
+            "#".repeat(24),
+            " ".repeat(20),
+            "#".repeat(24)
+        );
+        let cells_in_ten_ticks = |slowed: bool| {
+            let mut state = quiet_game(&corridor);
+            if slowed {
+                state.fields = (2..22)
+                    .map(|x| Field {
+                        pos: Vector2::new(x, 1),
+                        kind: FieldKind::Slow,
+                        ttl: 100,
+                    })
+                    .collect();
+            }
+            let h = state.spawn(EnemyKind::Basic, Vector2::new(2, 1));
+            state.enemies.get_mut(h).unwrap().speed = 1.0;
+            for _ in 0..10 {
+                state.advance();
+            }
+            state.enemies.get(h).unwrap().pos.x - 2
+        };
+        assert_eq!(cells_in_ten_ticks(false), 10);
+        assert!(cells_in_ten_ticks(true) < 10);
+
+        let mut state = GameState::new(Map::new(OPEN).unwrap(), Difficulty::Normal);
+        state.money = 1000;
+        let pos = Vector2::new(3, 2);
+        state.try_place_field(FieldKind::Slow, pos).unwrap();
+        assert_eq!(state.map[pos], Square::Empty);
+        for _ in 1..FieldKind::Slow.ttl() {
+            state.advance();
+        }
+        assert_eq!(state.fields.len(), 1);
+        assert_eq!(state.fields[0].ttl, 1);
+        state.advance();
+        assert!(state.fields.is_empty());
+    }
+
+    #[test]
+    fn edge_spawns_on_an_enclosed_map_come_in_at_the_spawn_points() {
+        let map = Map::new(OPEN).unwrap();
+        let mut state = GameState::new(map, Difficulty::Normal).with_edge_spawns();
+        let start = state.lives;
+        state.skip_to_next_wave();
+        let report = state.advance();
+        assert!(!report.spawned.is_empty());
+        for &(pos, _) in &report.spawned {
+            assert!(map::manhattan(pos, Vector2::new(1, 1)) <= 2);
+        }
+        for _ in 0..200 {
+            state.advance();
+        }
+        // Nothing was killed, so the enemies can't all have been fought off.
+        assert_ne!((state.outcome(), state.lives), (Some(Outcome::Win), start));
+    }
+
+    #[test]
+    fn edge_spawns_keep_the_edge_open_from_the_first_build_phase() {
+        let map = Map::new("## ####\n#^    #\n      #\n#   #$#\n#######").unwrap();
+        let mut state = GameState::new(map, Difficulty::Normal).with_edge_spawns();
+        state.money = 1000;
+        assert!(matches!(state.phase, Phase::Build { .. }));
+        state.try_place_wall(Vector2::new(2, 0)).unwrap();
+        assert_eq!(
+            state.try_place_wall(Vector2::new(0, 2)),
+            Err(PlaceError::BlocksPath)
+        );
+    }
+
+    #[test]
+    fn the_route_kept_clear_is_worked_out_again_when_the_map_changes() {
+        let mut state = quiet_game(OPEN);
+        state.money = 1000;
+        state.no_build_on_path = true;
+        let spawn = Vector2::new(1, 1);
+        let traced = |state: &GameState| -> HashSet<_> {
+            trace_route(&state.map, spawn).into_iter().collect()
+        };
+        let before = state.route_cells();
+        assert_eq!(before, traced(&state));
+        let wall = trace_route(&state.map, spawn)[1];
+        state.try_place_wall(wall).unwrap();
+        let after = state.route_cells();
+        assert_eq!(after, traced(&state));
+        assert_ne!(after, before);
+        let moved_onto = *after.iter().find(|c| !before.contains(c)).unwrap();
+        assert_eq!(
+            state.try_place_tower(TowerKind::Basic, moved_onto),
+            Err(PlaceError::OnPath)
+        );
+    }
+
+    #[test]
+    fn hard_caps_the_towers_without_an_economy_saying_so() {
+        let wall = "#".repeat(14);
+        let row = format!("#{}#", " ".repeat(12));
+        let open = [
+            wall.clone(),
+            format!("#^{}#", " ".repeat(11)),
+            row.clone(),
+            row.clone(),
+            row,
+            format!("#{}$#", " ".repeat(11)),
+            wall,
+        ]
+        .join("\n");
+        let map = Map::new(&open).unwrap();
+        let mut state = GameState::new(map.clone(), Difficulty::Hard);
+        state.money = 100_000;
+        let max = Difficulty::Hard.max_towers().unwrap();
+        assert_eq!(state.max_towers(), Some(max));
+        let cells: Vec<_> = map.cells_of(Square::Empty).collect();
+        let mut cells = cells.into_iter();
+        while !state.at_tower_cap() {
+            let cell = cells.next().unwrap();
+            let _ = state.try_place_tower(TowerKind::Basic, cell);
+        }
+        assert_eq!(state.towers.len(), max as usize);
+        let free = cells.find(|&c| state.check_site(&[c]).is_ok()).unwrap();
+        assert_eq!(
+            state.try_place_tower(TowerKind::Basic, free),
+            Err(PlaceError::TowerCap)
+        );
+        assert_eq!(
+            GameState::new(map.clone(), Difficulty::Normal).max_towers(),
+            None
+        );
+
+        // An economy's cap wins over the difficulty's.
+        let economy = Economy {
+            max_towers: Some(3),
+            ..Economy::default()
+        };
+        let state = GameState::new(map, Difficulty::Hard).with_economy(&economy);
+        assert_eq!(state.max_towers(), Some(3));
+    }
+}
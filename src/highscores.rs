@@ -0,0 +1,127 @@
+use crate::error::GameError;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The best game played on a map. Games are compared by score first, then
+/// by how far they got.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Best {
+    /// Enemies killed, the same score a campaign adds up.
+    pub score: u32,
+    /// Waves cleared.
+    pub wave: usize,
+}
+
+/// The best game on every map played so far, by the map's
+/// `Map::fingerprint`, so that a map keeps its scores however its file is
+/// named and two maps sharing a name don't share them.
+///
+/// The scores file holds one `fingerprint = score wave` line per map, the
+/// fingerprint in hex; blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HighScores {
+    best: BTreeMap<u64, Best>,
+}
+
+impl HighScores {
+    /// Where scores are kept unless told otherwise: a file in the home
+    /// directory, if there is one.
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("HOME").map(|home| Path::new(&home).join(".terminal-invaders-scores"))
+    }
+
+    /// Reads the scores saved at `path`. A missing or unreadable file starts
+    /// the scores afresh rather than failing.
+    pub fn load<P: AsRef<Path>>(path: P) -> HighScores {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|desc| HighScores::parse(&desc).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn parse(desc: &str) -> Result<HighScores, GameError> {
+        let mut scores = HighScores::default();
+        for (n, line) in desc.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = || {
+                GameError::Config(format!(
+                    "line {}: expected `fingerprint = score wave`",
+                    n + 1
+                ))
+            };
+            let (map, value) = line.split_once('=').ok_or_else(malformed)?;
+            let map = u64::from_str_radix(map.trim(), 16).map_err(|_| malformed())?;
+            let numbers: Vec<_> = value.split_whitespace().collect();
+            let best = match numbers.as_slice() {
+                [score, wave] => Best {
+                    score: score.parse().map_err(|_| malformed())?,
+                    wave: wave.parse().map_err(|_| malformed())?,
+                },
+                _ => return Err(malformed()),
+            };
+            scores.best.insert(map, best);
+        }
+        Ok(scores)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut desc = String::new();
+        for (map, best) in self.best.iter() {
+            desc += &format!("{:016x} = {} {}\n", map, best.score, best.wave);
+        }
+        fs::write(path, desc)
+    }
+
+    pub fn get(&self, map: u64) -> Option<Best> {
+        self.best.get(&map).copied()
+    }
+
+    /// Keeps `best` for `map` if it beats what is there. Returns whether it
+    /// did.
+    pub fn record(&mut self, map: u64, best: Best) -> bool {
+        if self.get(map).is_some_and(|old| old >= best) {
+            return false;
+        }
+        self.best.insert(map, best);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Map;
+
+    #[test]
+    fn only_a_better_game_replaces_the_best() {
+        let map = Map::new(crate::MAP).unwrap().fingerprint();
+        let mut scores = HighScores::default();
+        assert!(scores.record(map, Best { score: 10, wave: 2 }));
+        assert!(!scores.record(map, Best { score: 9, wave: 5 }));
+        assert!(!scores.record(map, Best { score: 10, wave: 2 }));
+        assert_eq!(scores.get(map), Some(Best { score: 10, wave: 2 }));
+        assert!(scores.record(map, Best { score: 10, wave: 3 }));
+        assert_eq!(scores.get(map), Some(Best { score: 10, wave: 3 }));
+        assert_eq!(scores.get(map ^ 1), None);
+    }
+
+    #[test]
+    fn scores_survive_a_save_and_load() {
+        let path = env::temp_dir().join(format!("invaders-scores-{}", std::process::id()));
+        let mut scores = HighScores::default();
+        scores.record(0xfeed, Best { score: 4, wave: 1 });
+        scores.record(u64::MAX, Best { score: 40, wave: 7 });
+        scores.save(&path).unwrap();
+        assert_eq!(HighScores::load(&path), scores);
+        fs::write(&path, "not a score").unwrap();
+        assert_eq!(HighScores::load(&path), HighScores::default());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(HighScores::load(&path), HighScores::default());
+    }
+}
@@ -0,0 +1,67 @@
+//! Input waiting to be handled. Keys and mouse events come in whenever the
+//! terminal sends them but are only acted on when a frame is drawn, so a
+//! burst of them between frames is spread out or handled together instead
+//! of racing the redraws.
+
+use std::collections::VecDeque;
+
+/// How much queued input each frame handles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Drain {
+    /// Everything that came in since the last frame.
+    #[default]
+    All,
+    /// The oldest input only, leaving the rest for the frames after.
+    One,
+}
+
+/// Input in the order it came in, waiting for a frame.
+#[derive(Clone, Debug)]
+pub struct InputQueue<T> {
+    queue: VecDeque<T>,
+    drain: Drain,
+}
+
+impl<T> InputQueue<T> {
+    pub fn new(drain: Drain) -> Self {
+        InputQueue {
+            queue: VecDeque::new(),
+            drain,
+        }
+    }
+
+    pub fn push(&mut self, input: T) {
+        self.queue.push_back(input);
+    }
+
+    /// The input for this frame to handle, oldest first.
+    pub fn drain_frame(&mut self) -> Vec<T> {
+        match self.drain {
+            Drain::All => self.queue.drain(..).collect(),
+            Drain::One => self.queue.pop_front().into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draining_everything_hands_over_all_input_in_order() {
+        let mut queue = InputQueue::new(Drain::All);
+        (1..=5).for_each(|i| queue.push(i));
+        assert_eq!(queue.drain_frame(), vec![1, 2, 3, 4, 5]);
+        assert!(queue.drain_frame().is_empty());
+    }
+
+    #[test]
+    fn draining_one_at_a_time_loses_nothing() {
+        let mut queue = InputQueue::new(Drain::One);
+        (1..=3).for_each(|i| queue.push(i));
+        assert_eq!(queue.drain_frame(), vec![1]);
+        queue.push(4);
+        let rest: Vec<_> = (0..4).flat_map(|_| queue.drain_frame()).collect();
+        assert_eq!(rest, vec![2, 3, 4]);
+    }
+}
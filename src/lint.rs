@@ -0,0 +1,103 @@
+use crate::map::{Map, Square};
+use std::fmt;
+
+/// What `terminal-invaders lint` finds out about a map, without playing it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// Facts about the map, one per line.
+    pub info: Vec<String>,
+    /// Things worth a look that don't stop the map from being played.
+    pub warnings: Vec<String>,
+    /// Things that make the map unplayable.
+    pub errors: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+pub fn lint(map: &Map) -> Report {
+    let (w, h) = (map.grid.ncols(), map.grid.nrows());
+    let mut info = vec![
+        format!("size: {}x{}", w, h),
+        format!("spawn points: {}", map.spawn_points().count()),
+        format!("destinations: {}", map.destinations().count()),
+    ];
+    let chokepoints = map.chokepoints();
+    info.push(format!("chokepoints: {}", chokepoints.len()));
+    info.extend(
+        chokepoints
+            .iter()
+            .map(|c| format!("  chokepoint at ({}, {})", c.x, c.y)),
+    );
+    let warnings = map
+        .connected_regions()
+        .into_iter()
+        .filter(|region| !region.iter().any(|c| map[*c] == Square::Destination))
+        .map(|region| {
+            format!(
+                "{} cells from ({}, {}) can't reach a destination",
+                region.len(),
+                region[0].x,
+                region[0].y
+            )
+        })
+        .collect();
+    let errors = map.validate().iter().map(|p| p.to_string()).collect();
+    Report {
+        info,
+        warnings,
+        errors,
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.info.iter() {
+            writeln!(f, "{}", line)?;
+        }
+        for line in self.warnings.iter() {
+            writeln!(f, "warning: {}", line)?;
+        }
+        for line in self.errors.iter() {
+            writeln!(f, "error: {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_playable_map_passes_with_its_facts_listed() {
+        let report = lint(&Map::new("#####\n#^ $#\n#####").unwrap());
+        assert!(report.is_ok());
+        assert!(report.warnings.is_empty());
+        assert_eq!(
+            report.info,
+            [
+                "size: 5x3",
+                "spawn points: 1",
+                "destinations: 1",
+                "chokepoints: 1",
+                "  chokepoint at (2, 1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn an_unplayable_map_fails_with_what_is_wrong() {
+        let report = lint(&Map::new("#####\n#^#$#\n#####").unwrap());
+        assert!(!report.is_ok());
+        assert!(!report.errors.is_empty());
+        assert!(report.to_string().contains("error: "));
+
+        let report = lint(&Map::new("#####\n#^  #\n#####").unwrap());
+        assert!(!report.is_ok());
+        assert!(report.info.contains(&"destinations: 0".to_string()));
+    }
+}
@@ -1,243 +1,720 @@
+#[cfg(feature = "bridge")]
+mod bridge;
+mod camera;
+mod campaign;
+mod date;
+mod difficulty;
+mod direction;
+mod economy;
+mod enemy;
+mod error;
+mod field;
+mod game;
+mod highscores;
+mod input;
+mod lint;
+mod map;
+mod menu;
+#[cfg(test)]
+mod oracle;
+mod path;
+mod perf;
+mod pickup;
+mod screen;
+mod slots;
+#[cfg(test)]
+mod snapshot;
+mod speed;
+mod theme;
+mod tower;
 #[allow(dead_code)]
 mod util;
+mod wave;
 
+use crate::campaign::Campaign;
+use crate::date::Date;
+use crate::difficulty::Difficulty;
+use crate::economy::Economy;
+use crate::field::FieldKind;
+use crate::game::{coords_label, Border, Chaos, GameState, MapCache, Outcome, PATH_BUDGET};
+use crate::highscores::{Best, HighScores};
+use crate::input::{Drain, InputQueue};
+use crate::map::Map;
+use crate::menu::{MenuItem, PauseMenu, QuitPrompt};
+use crate::path::{field_recomputes, Pathfinder};
+use crate::perf::{Perf, PerfOverlay, SystemClock};
+use crate::screen::{EndScreen, TitleScreen};
+use crate::speed::{Speed, SLOW_MOTION_LIVES};
+use crate::theme::Theme;
+use crate::tower::TowerKind;
 use crate::util::event::Config;
-use nalgebra::{DMatrix, Vector2};
-use rand::seq::IteratorRandom;
-use std::cmp::max;
-use std::collections::HashMap;
-use std::collections::HashSet;
-use std::collections::VecDeque;
-use std::iter;
-use std::ops::Index;
-use std::time::Duration;
+use crate::wave::{Phase, Pressure};
+use std::cell::Cell;
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
 use std::{error::Error, io};
-use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
-use tui::buffer::Buffer;
-use tui::layout::Rect;
-use tui::widgets::Widget;
+use termion::event::{Key, MouseButton, MouseEvent};
+use termion::{input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
 use tui::{backend::TermionBackend, Terminal};
-use util::event::{Event, Events};
+use util::event::{Event, Events, MotionTerminal};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum Square {
-    Empty,
-    Wall,
-    SpawnPoint,
-    Destination,
-}
-
-impl Square {
-    fn to_char(&self) -> char {
-        match self {
-            Square::Empty => ' ',
-            Square::Wall => '#',
-            Square::SpawnPoint => '^',
-            Square::Destination => '$',
-        }
-    }
-    fn fr_char(c: char) -> Self {
-        match c {
-            ' ' => Square::Empty,
-            '#' => Square::Wall,
-            '^' => Square::SpawnPoint,
-            '$' => Square::Destination,
-            _ => panic!(),
-        }
-    }
-}
-
-struct Map {
-    grid: DMatrix<Square>,
-}
-
-const NEIGHBOR4: [Vector2<i32>; 4] = [
-    Vector2::new(0, 1),
-    Vector2::new(1, 0),
-    Vector2::new(-1, 0),
-    Vector2::new(0, -1),
+/// Cells suggested at a time when the player asks for a hint.
+const HINTS: usize = 3;
+/// What `--dots` draws empty ground as.
+const EMPTY_DOT: char = '·';
+/// Flags that change how a game plays out, none of which a daily challenge
+/// takes, so that everyone playing it on the same day plays the same game.
+const GAME_FLAGS: &[&str] = &[
+    "--economy",
+    "--map",
+    "--wall-edges",
+    "--toroidal",
+    "--corridor",
+    "--campaign",
+    "--difficulty",
+    "--pathfinder",
+    "--edge-spawns",
+    "--rotate-spawns",
+    "--max-towers",
+    "--telegraph",
+    "--hp-ramp",
+    "--threat-aware",
+    "--panic",
+    "--sudden-death",
+    "--chaos",
+    "--chaos-damage",
+    "--pressure",
+    "--pressure-par",
+    "--hit-cooldown",
+    "--path-budget",
+    "--seed",
 ];
 
-const NEIGHBOR8: [Vector2<i32>; 8] = [
-    Vector2::new(0, 1),
-    Vector2::new(1, 0),
-    Vector2::new(0, -1),
-    Vector2::new(-1, 0),
-    Vector2::new(1, 1),
-    Vector2::new(1, -1),
-    Vector2::new(-1, -1),
-    Vector2::new(-1, -1),
-];
+static MAP: &str = r#"
+###^#############
+### #############
+### #############
+###         ###########
+###### #### ###########
+###### #### #########
+######$####$###########
+"#;
 
-impl Map {
-    fn new(desc: &str) -> Self {
-        let lines = desc
-            .split('\n')
-            .filter(|l| l.len() > 0)
-            .map(|l| l.chars().map(Square::fr_char));
-        let (w, h) = lines
-            .clone()
-            .fold((0, 0), |(w, h), l| (max(w, l.count()), h + 1));
-        let lines = lines.map(|l| {
-            l.clone()
-                .chain(iter::repeat(Square::Empty).take(w - l.count()))
-        });
-        Map {
-            grid: DMatrix::from_iterator(w, h, lines.flatten()).transpose(),
-        }
-    }
-    fn in_bounds(&self, s: Vector2<i32>) -> bool {
-        s.x >= 0 && s.y >= 0 && s.x < self.grid.ncols() as i32 && s.y < self.grid.nrows() as i32
-    }
-    fn neighbors_offsets<'a>(
-        &'a self,
-        s: Vector2<usize>,
-        offsets: &'a [Vector2<i32>],
-    ) -> impl Iterator<Item = Vector2<usize>> + 'a {
-        let s = s.map(|x| x as i32);
-        offsets
-            .iter()
-            .map(move |t| s + t)
-            .filter(move |t| self.in_bounds(*t))
-            .map(|t| t.map(|x| x as usize))
-    }
-    fn neighbors_4(&self, s: Vector2<usize>) -> impl Iterator<Item = Vector2<usize>> + '_ {
-        self.neighbors_offsets(s, &NEIGHBOR4)
-    }
-    fn neighbors_8(&self, s: Vector2<usize>) -> impl Iterator<Item = Vector2<usize>> + '_ {
-        self.neighbors_offsets(s, &NEIGHBOR8)
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("terminal-invaders: {}", err);
+        process::exit(1);
     }
 }
 
-impl Index<Vector2<usize>> for Map {
-    type Output = Square;
-
-    fn index<'a>(&'a self, i: Vector2<usize>) -> &'a Square {
-        &self.grid.index((i.y, i.x))
+fn run() -> Result<(), Box<dyn Error>> {
+    if env::args().nth(1).as_deref() == Some("lint") {
+        let path = env::args().nth(2).ok_or("lint needs a map file path")?;
+        let report = lint::lint(&Map::from_file(path)?);
+        print!("{}", report);
+        if !report.is_ok() {
+            process::exit(1);
+        }
+        return Ok(());
     }
-}
-
-impl Widget for &Map {
-    fn render(self, _area: Rect, buf: &mut Buffer) {
-        for (y, row) in self.grid.row_iter().enumerate() {
-            for (x, sq) in row.iter().enumerate() {
-                let c = buf.get_mut(x as u16, y as u16);
-                c.set_char(sq.to_char());
+    #[cfg(feature = "png")]
+    if env::args().nth(1).as_deref() == Some("export") {
+        let map = env::args().nth(2).ok_or("export needs a map file path")?;
+        let image = env::args()
+            .nth(3)
+            .ok_or("export needs a path to write to")?;
+        Map::from_file(map)?.to_image(image)?;
+        return Ok(());
+    }
+    let mut economy = Economy::default();
+    let mut max_towers = None;
+    let mut seed = None;
+    let mut map = None;
+    let mut wall_edges = false;
+    let mut toroidal = false;
+    let mut difficulty = Difficulty::Normal;
+    let mut pathfinder = Pathfinder::default();
+    let mut daily = None;
+    let mut theme = Theme::default();
+    let mut campaign = None;
+    let mut edge_spawns = false;
+    let mut rotate_spawns = false;
+    let mut tower_facing = false;
+    let mut spectate = false;
+    let mut hp_ramp = None;
+    let mut telegraph = None;
+    let mut threat_aware = false;
+    let mut panic = false;
+    let mut sudden_death = false;
+    let mut border = Border::default();
+    let mut high_contrast = false;
+    let mut dots = false;
+    let mut drain = Drain::default();
+    let mut chaos = None;
+    let mut pressure = None;
+    let mut hit_cooldown = None;
+    let mut path_budget = PATH_BUDGET;
+    let mut slow_motion_lives = SLOW_MOTION_LIVES;
+    #[cfg(feature = "bridge")]
+    let mut bridge = false;
+    let mut game_flag = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if GAME_FLAGS.contains(&arg.as_str()) {
+            game_flag.get_or_insert_with(|| arg.clone());
+        }
+        match arg.as_str() {
+            "--economy" => {
+                let path = args.next().ok_or("--economy needs a file path")?;
+                economy = Economy::from_file(path)?;
+            }
+            "--theme" => {
+                let path = args.next().ok_or("--theme needs a file path")?;
+                theme = Theme::from_file(path)?;
+            }
+            "--map" => {
+                let path = args.next().ok_or("--map needs a file path")?;
+                map = Some(Map::from_file(path)?.playable()?);
+            }
+            "--wall-edges" => wall_edges = true,
+            "--toroidal" => toroidal = true,
+            "--corridor" => {
+                let value = args.next().ok_or("--corridor needs a length")?;
+                let length = value
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a valid length", value))?;
+                map = Some(Map::stress_corridor(length));
+            }
+            "--campaign" => {
+                let path = args.next().ok_or("--campaign needs a file path")?;
+                let mut levels = Campaign::from_file(path)?;
+                map = Some(levels.next_level()?);
+                campaign = Some(levels);
+            }
+            "--difficulty" => {
+                difficulty = args
+                    .next()
+                    .ok_or("--difficulty needs one of easy, normal or hard")?
+                    .parse()?;
+            }
+            "--pathfinder" => {
+                pathfinder = args
+                    .next()
+                    .ok_or("--pathfinder needs one of bfs, dijkstra, astar or flowfield")?
+                    .parse()?;
+            }
+            "--edge-spawns" => edge_spawns = true,
+            "--rotate-spawns" => rotate_spawns = true,
+            "--tower-facing" => tower_facing = true,
+            "--spectate" => spectate = true,
+            "--max-towers" => {
+                let value = args.next().ok_or("--max-towers needs a number")?;
+                max_towers = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a valid number of towers", value))?,
+                );
+            }
+            "--telegraph" => {
+                let value = args.next().ok_or("--telegraph needs a number of ticks")?;
+                telegraph = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a valid number of ticks", value))?,
+                );
             }
+            "--hp-ramp" => {
+                let value = args.next().ok_or("--hp-ramp needs a number")?;
+                hp_ramp = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a valid hp ramp", value))?,
+                );
+            }
+            "--threat-aware" => threat_aware = true,
+            "--panic" => panic = true,
+            "--sudden-death" => sudden_death = true,
+            "--high-contrast" => high_contrast = true,
+            "--dots" => dots = true,
+            "--one-key-per-frame" => drain = Drain::One,
+            "--chaos" => chaos = Some(chaos.unwrap_or_default()),
+            "--pressure" => pressure = Some(pressure.unwrap_or_default()),
+            "--pressure-par" => {
+                let value = args.next().ok_or("--pressure-par needs a number")?;
+                let par_ticks = value
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a valid number of ticks", value))?;
+                pressure = Some(Pressure {
+                    par_ticks,
+                    ..pressure.unwrap_or_default()
+                });
+            }
+            "--chaos-damage" => {
+                let value = args.next().ok_or("--chaos-damage needs a number")?;
+                let damage = value
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a valid amount of damage", value))?;
+                chaos = Some(Chaos {
+                    damage,
+                    ..chaos.unwrap_or_default()
+                });
+            }
+            "--border" => {
+                border = args
+                    .next()
+                    .ok_or("--border needs one of none, map or all")?
+                    .parse()?;
+            }
+            "--hit-cooldown" => {
+                let value = args.next().ok_or("--hit-cooldown needs a number")?;
+                hit_cooldown = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a valid number of ticks", value))?,
+                );
+            }
+            "--path-budget" => {
+                let value = args.next().ok_or("--path-budget needs a number")?;
+                path_budget = value
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a valid path budget", value))?;
+            }
+            "--slow-motion-lives" => {
+                let value = args.next().ok_or("--slow-motion-lives needs a number")?;
+                slow_motion_lives = value
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a valid number of lives", value))?;
+            }
+            "--daily" => daily = Some(Date::today()),
+            "--date" => {
+                daily = Some(args.next().ok_or("--date needs a date")?.parse()?);
+            }
+            #[cfg(feature = "bridge")]
+            "--bridge" => bridge = true,
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a number")?;
+                seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a valid seed", value))?,
+                );
+            }
+            _ => return Err(format!("unknown argument `{}`", arg).into()),
         }
     }
-}
-
-struct GameState {
-    enemies: Vec<Vector2<usize>>,
-    map: Map,
-}
 
-impl GameState {
-    fn advance(&mut self) {
-        for enemy in self.enemies.iter_mut() {
-            *enemy = pf_search(&self.map, *enemy);
-        }
+    // The date picks the map and the seed, and nothing else may change the
+    // game, so everyone playing it gets the same one.
+    if let (Some(_), Some(flag)) = (daily, &game_flag) {
+        return Err(format!("{} can't be combined with a daily challenge", flag).into());
     }
-}
-
-impl Widget for &GameState {
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        self.map.render(area, buf);
-        for enemy in self.enemies.iter() {
-            let c = buf.get_mut(enemy.x as u16, enemy.y as u16);
-            c.set_symbol("*");
-        }
+    let seed = seed.unwrap_or_else(rand::random);
+    // Overrides the economy file's cap whichever came first.
+    if max_towers.is_some() {
+        economy.max_towers = max_towers;
     }
-}
-
-fn pf_random(m: &Map, s: Vector2<usize>) -> Vector2<usize> {
-    let mut rng = rand::thread_rng();
-    m.neighbors_4(s)
-        .filter(|t| m[*t] == Square::Empty)
-        .choose(&mut rng)
-        .unwrap_or(s)
-}
+    let scores_path = HighScores::default_path();
+    let mut scores = scores_path
+        .as_ref()
+        .map(HighScores::load)
+        .unwrap_or_default();
+    // Whether the game being played has been recorded, and if so whether it
+    // set a new best.
+    let mut recorded = None;
 
-fn first_move(
-    parents: &HashMap<Vector2<usize>, Option<Vector2<usize>>>,
-    end: Vector2<usize>,
-) -> Vector2<usize> {
-    let mut cur = end;
-    let mut prev = end;
-    while let Some(&Some(parent)) = parents.get(&cur) {
-        prev = cur;
-        cur = parent;
+    let mut map = match map {
+        Some(map) => map,
+        None => Map::new(MAP)?,
+    };
+    if wall_edges {
+        map.add_border();
     }
-    prev
-}
-
-fn pf_search(m: &Map, s: Vector2<usize>) -> Vector2<usize> {
-    let mut parents = HashMap::new();
-    let mut q = VecDeque::new();
-    let mut cur = s;
-    let mut parent: Option<Vector2<usize>> = None;
-    while m[cur] != Square::Destination {
-        parents.insert(cur, parent);
-        q.extend(
-            m.neighbors_4(cur)
-                .filter(|t| {
-                    (m[*t] == Square::Empty || m[*t] == Square::Destination)
-                        && !parents.contains_key(t)
-                })
-                .map(|t| (t, Some(cur))),
-        );
-        let next = q.pop_front().unwrap();
-        cur = next.0;
-        parent = next.1;
+    if toroidal {
+        map = map.with_toroidal();
     }
-    parents.insert(cur, parent);
-    first_move(&parents, cur)
-}
+    // Kept across restarts once toggled.
+    let high_contrast = Cell::new(high_contrast);
+    let current_theme = || {
+        let mut current = if high_contrast.get() {
+            Theme::high_contrast()
+        } else {
+            theme.clone()
+        };
+        if dots {
+            current.empty_glyph = EMPTY_DOT;
+        }
+        current
+    };
+    let new_game = |map: &Map| {
+        let mut game_state = match daily {
+            Some(date) => GameState::daily(date),
+            None => GameState::new(map.clone(), difficulty).with_seed(seed),
+        }
+        .with_economy(&economy)
+        .with_pathfinder(pathfinder)
+        .with_path_budget(path_budget);
+        if sudden_death {
+            game_state = game_state.with_sudden_death();
+        }
+        if edge_spawns {
+            game_state = game_state.with_edge_spawns();
+        }
+        if let Some(ticks) = telegraph {
+            game_state = game_state.with_telegraph(ticks);
+        }
+        if let Some(ramp) = hp_ramp {
+            game_state = game_state.with_hp_ramp(ramp);
+        }
+        if spectate {
+            game_state = game_state.with_spectating();
+        }
+        if rotate_spawns {
+            game_state = game_state.with_spawn_rotation();
+        }
+        if tower_facing {
+            game_state = game_state.with_tower_facing();
+        }
+        if threat_aware {
+            game_state = game_state.with_threat_aware();
+        }
+        if panic {
+            game_state = game_state.with_panic();
+        }
+        if let Some(pressure) = pressure {
+            game_state = game_state.with_pressure(pressure);
+        }
+        if let Some(chaos) = chaos {
+            game_state = game_state.with_chaos(chaos);
+        }
+        if let Some(ticks) = hit_cooldown {
+            game_state = game_state.with_hit_cooldown(ticks);
+        }
+        game_state.theme = current_theme();
+        game_state.border = border;
+        game_state
+    };
+    let mut game_state = new_game(&map);
+    // Scores go by the map a game starts on, before any walls go up on it.
+    let mut fingerprint = game_state.map.fingerprint();
 
-static MAP: &str = r#"
-### #############
-### #############
-### #############
-###         ###########
-###### #### ###########
-###### #### #########
-######$####$###########
-"#;
+    #[cfg(feature = "bridge")]
+    if bridge {
+        let stdin = io::stdin();
+        return Ok(bridge::run(&mut game_state, stdin.lock(), io::stdout())?);
+    }
 
-fn main() -> Result<(), Box<dyn Error>> {
     // Terminal initialization
     let stdout = io::stdout().into_raw_mode()?;
-    let stdout = MouseTerminal::from(stdout);
+    let stdout = MotionTerminal::new(MouseTerminal::from(stdout))?;
     let stdout = AlternateScreen::from(stdout);
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut game_state = GameState {
-        enemies: vec![Vector2::new(3, 0), Vector2::new(3, 2)],
-        map: Map::new(MAP),
-    };
-
     // Setup event handlers
-    let events = Events::with_config(Config {
+    let tick_rate = Duration::from_millis(1000);
+    let mut events = Events::with_config(Config {
         exit_key: Key::Char('q'),
-        tick_rate: Duration::from_millis(1000),
+        tick_rate,
+        frame_rate: Duration::from_millis(50),
     });
+    // Quitting is up to the prompt, so input has to keep coming after a `q`.
+    events.disable_exit_key();
 
-    loop {
-        terminal.draw(|f| {
-            f.render_widget(&game_state, f.size());
+    let mut map_cache = MapCache::default();
+    let mut on_title = true;
+    let mut pause_menu: Option<PauseMenu> = None;
+    let mut quit = QuitPrompt::default();
+    let mut perf = Perf::new(SystemClock::new());
+    let mut confirm_clear = false;
+    let mut last_tick = Instant::now();
+    let mut speed = Speed::new(slow_motion_lives);
+    let mut current_rate = tick_rate;
+    let mut inputs = InputQueue::new(drain);
+    'game: loop {
+        let rate = speed.tick_rate(tick_rate, speed.slow_motion(&game_state));
+        if rate != current_rate {
+            events.set_tick_rate(rate);
+            current_rate = rate;
+        }
+        map_cache.alpha = last_tick.elapsed().as_secs_f32() / current_rate.as_secs_f32();
+        let overlay = if perf.visible {
+            Some(PerfOverlay {
+                frame: perf.frame.average(),
+                step: perf.step.average(),
+                enemies: game_state.enemies.len(),
+                field_recomputes: field_recomputes(),
+                squares_drawn: map_cache.squares_drawn,
+            })
+        } else {
+            None
+        };
+        perf.time_frame(|| {
+            terminal.draw(|f| {
+                if on_title {
+                    let screen = TitleScreen {
+                        theme: &game_state.theme,
+                        best: scores.get(fingerprint),
+                    };
+                    f.render_widget(screen, f.size());
+                } else if let Some(outcome) =
+                    game_state.outcome().filter(|_| game_state.is_game_over())
+                {
+                    let screen = EndScreen {
+                        state: &game_state,
+                        outcome,
+                        campaign_score: campaign.as_ref().map(|c| c.score + game_state.kills),
+                        best: scores.get(fingerprint),
+                        new_best: recorded == Some(true),
+                    };
+                    f.render_widget(screen, f.size());
+                } else {
+                    f.render_stateful_widget(&game_state, f.size(), &mut map_cache);
+                    if let Some(menu) = pause_menu.as_mut() {
+                        f.render_widget(menu, f.size());
+                    }
+                    f.render_widget(quit, f.size());
+                }
+                if let Some(overlay) = overlay {
+                    f.render_widget(overlay, f.size());
+                }
+            })
         })?;
 
-        match events.next()? {
-            Event::Input(input) => match input {
-                Key::Char('q') => {
-                    break;
+        let batch: Vec<_> = match events.next()? {
+            // Keys and mouse events wait for the next frame, which handles as
+            // many of them as the queue lets through.
+            input @ (Event::Input(_) | Event::Mouse(_) | Event::Hover(..)) => {
+                inputs.push(input);
+                continue;
+            }
+            Event::Frame => inputs.drain_frame(),
+            event => vec![event],
+        };
+        for event in batch {
+            match event {
+                Event::Input(Key::Char('q')) if on_title || game_state.is_game_over() => {
+                    break 'game
+                }
+                Event::Input(Key::Char('q')) if game_state.is_spectating() => {
+                    game_state.stop_spectating();
+                }
+                Event::Input(_) | Event::Mouse(_) | Event::Hover(..)
+                    if game_state.is_spectating() => {}
+                Event::Input(input) if quit.handle(input) => {
+                    if quit.is_confirmed() {
+                        break 'game;
+                    }
+                }
+                Event::Input(_) if on_title => on_title = false,
+                Event::Input(input) if pause_menu.is_some() => {
+                    match pause_menu.as_mut().unwrap().handle(input) {
+                        Some(MenuItem::Resume) => pause_menu = None,
+                        Some(MenuItem::Restart) => {
+                            game_state = new_game(&map);
+                            recorded = None;
+                            pause_menu = None;
+                        }
+                        Some(MenuItem::MainMenu) => {
+                            game_state = new_game(&map);
+                            recorded = None;
+                            pause_menu = None;
+                            on_title = true;
+                        }
+                        Some(MenuItem::Quit) => break 'game,
+                        None => {}
+                    }
+                }
+                Event::Input(input) if confirm_clear => {
+                    confirm_clear = false;
+                    game_state.message = match input {
+                        Key::Char('y') => game_state
+                            .clear_towers()
+                            .map(|refund| format!("towers sold for {}", refund)),
+                        _ => None,
+                    };
+                }
+                Event::Input(Key::Esc) if game_state.placing.is_some() => game_state.placing = None,
+                Event::Input(Key::Esc) if game_state.outcome().is_none() => {
+                    pause_menu = Some(PauseMenu::new());
+                }
+                Event::Mouse(_) | Event::Hover(..) if on_title || pause_menu.is_some() => {}
+                Event::Hover(x, y) => game_state.hover(x, y),
+                Event::Mouse(mouse) => {
+                    let (x, y) = match mouse {
+                        MouseEvent::Press(_, x, y)
+                        | MouseEvent::Release(x, y)
+                        | MouseEvent::Hold(x, y) => (x, y),
+                    };
+                    game_state.hover(x, y);
+                    if let Some(cell) = game_state.screen_to_cell(x, y) {
+                        game_state.set_cursor(cell);
+                    }
+                    match mouse {
+                        MouseEvent::Press(MouseButton::Left, _, _) => game_state.press(),
+                        MouseEvent::Hold(_, _) => game_state.hold(),
+                        MouseEvent::Release(_, _) => {
+                            if let Some((_, err)) = game_state.release() {
+                                game_state.message = err.map(|err| err.to_string());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Input(input) => match input {
+                    Key::Left => game_state.move_cursor(-1, 0),
+                    Key::Right => game_state.move_cursor(1, 0),
+                    Key::Up => game_state.move_cursor(0, -1),
+                    Key::Down => game_state.move_cursor(0, 1),
+                    // Picking a tower enters placement mode; picking it again, or
+                    // pressing enter, builds it on the cursor.
+                    Key::Char('t')
+                    | Key::Char('k')
+                    | Key::Char('h')
+                    | Key::Char('e')
+                    | Key::Char('d')
+                    | Key::Char('\n') => {
+                        let kind = match input {
+                            Key::Char('t') => Some(TowerKind::Basic),
+                            Key::Char('k') => Some(TowerKind::Knockback),
+                            Key::Char('h') => Some(TowerKind::Heavy),
+                            Key::Char('e') => Some(TowerKind::Economy),
+                            Key::Char('d') => Some(TowerKind::Detector),
+                            _ => game_state.placing,
+                        };
+                        if let Some(kind) = kind {
+                            if game_state.placing == Some(kind) {
+                                let cursor = game_state.cursor;
+                                game_state.message = game_state
+                                    .try_place_tower(kind, cursor)
+                                    .err()
+                                    .map(|err| err.to_string());
+                            } else {
+                                game_state.placing = Some(kind);
+                            }
+                        }
+                    }
+                    Key::Char('n') => game_state.skip_to_next_wave(),
+                    Key::Char('p') => perf.visible = !perf.visible,
+                    Key::Char('x') => {
+                        if game_state.phase == Phase::Combat {
+                            game_state.message =
+                                Some("towers can only be sold between waves".into());
+                        } else {
+                            confirm_clear = true;
+                            game_state.message = Some("sell every tower? (y/n)".into());
+                        }
+                    }
+                    Key::Char('l') => {
+                        let cursor = game_state.cursor;
+                        game_state.message = Some(match game_state.instant_reload(cursor) {
+                            Ok(()) => "reloaded".to_string(),
+                            Err(err) => err.to_string(),
+                        });
+                    }
+                    Key::Char('m') => {
+                        let cursor = game_state.cursor;
+                        game_state.message = Some(match game_state.toggle_target_lock(cursor) {
+                            Ok(true) => "target lock on".to_string(),
+                            Ok(false) => "target lock off".to_string(),
+                            Err(err) => err.to_string(),
+                        });
+                    }
+                    Key::Char('c') => game_state.show_chokepoints = !game_state.show_chokepoints,
+                    Key::Char('r') => game_state.show_trails = !game_state.show_trails,
+                    Key::Char('+') | Key::Char('=') | Key::Char('-') => {
+                        if input == Key::Char('-') {
+                            speed.slower();
+                        } else {
+                            speed.faster();
+                        }
+                        game_state.message = Some(format!("speed: {}x", speed.manual_speed()));
+                    }
+                    Key::Char('v') => game_state.show_ranges = !game_state.show_ranges,
+                    Key::Char('o') => game_state.show_coords = !game_state.show_coords,
+                    Key::Char('g') => game_state.show_paths = !game_state.show_paths,
+                    Key::Char('i') => {
+                        let hints: Vec<_> = game_state
+                            .suggest_placements(HINTS)
+                            .into_iter()
+                            .map(|c| coords_label(Some(c)))
+                            .collect();
+                        game_state.message = Some(if hints.is_empty() {
+                            "nowhere left to build".to_string()
+                        } else {
+                            format!("try building at {}", hints.join(", "))
+                        });
+                    }
+                    Key::Char('b') => game_state.border = game_state.border.next(),
+                    Key::Char('a') => {
+                        high_contrast.set(!high_contrast.get());
+                        game_state.theme = current_theme();
+                    }
+                    Key::Char('f') => {
+                        let camera = &mut map_cache.camera;
+                        camera.follow = !camera.follow;
+                        game_state.message = Some(
+                            if camera.follow {
+                                "camera: following the action"
+                            } else {
+                                "camera: fixed"
+                            }
+                            .to_string(),
+                        );
+                    }
+                    Key::Char('s') => {
+                        let spawning = game_state.is_spawning();
+                        game_state.set_spawning(!spawning);
+                    }
+                    Key::Char('w') => {
+                        let cursor = game_state.cursor;
+                        game_state.message = game_state
+                            .try_place_wall(cursor)
+                            .err()
+                            .map(|err| err.to_string());
+                    }
+                    Key::Char('z') => {
+                        let cursor = game_state.cursor;
+                        game_state.message = game_state
+                            .try_place_field(FieldKind::Slow, cursor)
+                            .err()
+                            .map(|err| err.to_string());
+                    }
+                    _ => {}
+                },
+                Event::Frame => {}
+                Event::Tick => {
+                    last_tick = Instant::now();
+                    if !on_title && pause_menu.is_none() && !quit.is_open() {
+                        let report = perf.time_step(|| game_state.advance());
+                        if let Some(summary) = report.summary() {
+                            game_state.message = Some(summary);
+                        }
+                    }
+                    if recorded.is_none() && game_state.is_game_over() {
+                        let best = Best {
+                            score: game_state.kills,
+                            wave: game_state.wave,
+                        };
+                        let new_best = scores.record(fingerprint, best);
+                        if new_best {
+                            if let Some(path) = scores_path.as_ref() {
+                                // Losing a score isn't worth interrupting the game.
+                                let _ = scores.save(path);
+                            }
+                        }
+                        recorded = Some(new_best);
+                    }
+                    if let Some(campaign) = campaign.as_mut() {
+                        if game_state.outcome() == Some(Outcome::Win) && !campaign.is_finished() {
+                            campaign.record(&game_state);
+                            map = campaign.next_level()?;
+                            game_state = new_game(&map);
+                            fingerprint = game_state.map.fingerprint();
+                            recorded = None;
+                        }
+                    }
                 }
-                _ => {}
-            },
-            Event::Tick => {
-                game_state.advance();
             }
         }
     }
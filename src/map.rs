@@ -0,0 +1,971 @@
+use crate::direction::Direction;
+use crate::error::GameError;
+use crate::path::{cheapest_route, distance_field, reaches};
+use crate::theme::Theme;
+use nalgebra::{DMatrix, Vector2};
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use std::cmp::max;
+use std::fmt;
+use std::fs;
+use std::iter;
+use std::ops::{Index, IndexMut};
+use std::path::Path;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::Style;
+use tui::widgets::Widget;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Square {
+    Empty,
+    Wall,
+    SpawnPoint,
+    Destination,
+    /// Walkable, but hurts whoever stands on it.
+    Hazard,
+    /// Quicker going than open ground, so routes bend to follow it.
+    Road,
+}
+
+/// Glyphs a destination pulses between when animated.
+const DESTINATION_FRAMES: [char; 2] = ['$', '%'];
+
+impl Square {
+    /// The glyph for this square; `frame` is the current tick for animated
+    /// rendering, or `None` for the static look that maps are written in.
+    pub fn to_char(self, frame: Option<u64>) -> char {
+        match (self, frame) {
+            (Square::Empty, _) => ' ',
+            (Square::Wall, _) => '#',
+            (Square::SpawnPoint, _) => '^',
+            (Square::Destination, None) => '$',
+            (Square::Destination, Some(f)) => {
+                DESTINATION_FRAMES[f as usize % DESTINATION_FRAMES.len()]
+            }
+            (Square::Hazard, _) => '~',
+            (Square::Road, _) => '=',
+        }
+    }
+    /// What the square is drawn as, which in high contrast sets apart
+    /// squares that might otherwise only differ in color.
+    pub fn glyph(self, frame: Option<u64>, theme: &Theme) -> char {
+        match self {
+            Square::Wall if theme.high_contrast => '█',
+            Square::Hazard if theme.high_contrast => '≈',
+            Square::Empty => theme.empty_glyph,
+            _ => self.to_char(frame),
+        }
+    }
+    pub fn fr_char(c: char) -> Option<Self> {
+        match c {
+            ' ' => Some(Square::Empty),
+            '#' => Some(Square::Wall),
+            '^' => Some(Square::SpawnPoint),
+            '$' => Some(Square::Destination),
+            '~' => Some(Square::Hazard),
+            '=' => Some(Square::Road),
+            _ => None,
+        }
+    }
+    /// The color the square is drawn in when a map is exported as an image.
+    #[cfg(feature = "png")]
+    fn rgb(self) -> [u8; 3] {
+        match self {
+            Square::Empty => [0, 0, 0],
+            Square::Wall => [128, 128, 128],
+            Square::SpawnPoint => [205, 0, 0],
+            Square::Destination => [205, 205, 0],
+            Square::Hazard => [255, 96, 96],
+            Square::Road => [96, 64, 32],
+        }
+    }
+    fn code(self) -> u8 {
+        match self {
+            Square::Empty => 0,
+            Square::Wall => 1,
+            Square::SpawnPoint => 2,
+            Square::Destination => 3,
+            Square::Hazard => 4,
+            Square::Road => 5,
+        }
+    }
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Square::Empty),
+            1 => Some(Square::Wall),
+            2 => Some(Square::SpawnPoint),
+            3 => Some(Square::Destination),
+            4 => Some(Square::Hazard),
+            5 => Some(Square::Road),
+            _ => None,
+        }
+    }
+    pub fn style(self, frame: Option<u64>, theme: &Theme) -> Style {
+        match (self, frame) {
+            (Square::Empty, _) => theme.fg(theme.empty),
+            (Square::Wall, _) => theme.fg(theme.wall),
+            (Square::SpawnPoint, _) => theme.fg(theme.spawn_point),
+            (Square::Destination, Some(f)) if f % 2 == 1 => theme.fg(theme.destination_pulse),
+            (Square::Destination, _) => theme.fg(theme.destination),
+            (Square::Hazard, _) => theme.fg(theme.hazard),
+            (Square::Road, _) => theme.fg(theme.road),
+        }
+    }
+}
+
+/// A line to mirror the grid across.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Axis {
+    /// Through the middle row, swapping top and bottom.
+    Horizontal,
+    /// Through the middle column, swapping left and right.
+    Vertical,
+}
+
+/// Leading bytes of every binary map.
+pub const MAGIC: [u8; 4] = *b"TIVM";
+const VERSION: u8 = 1;
+/// The most cells a binary map may claim to have, far more than any map
+/// that fits on a screen, so a bad header can't ask for a huge allocation.
+const MAX_CELLS: usize = 1 << 24;
+/// Starting value and multiplier of the FNV-1a hash behind `fingerprint`.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+/// Width and height in pixels of one cell in an exported image.
+#[cfg(feature = "png")]
+const PNG_CELL_SIZE: usize = 4;
+
+#[derive(Clone)]
+pub struct Map {
+    pub grid: DMatrix<Square>,
+    /// Cells taken up by towers, which enemies cannot walk through.
+    occupied: DMatrix<bool>,
+    /// Bumped on every mutation so cached views of the grid can tell when
+    /// they are stale.
+    generation: u64,
+    /// Whether stepping off one edge comes back on at the opposite one.
+    pub toroidal: bool,
+}
+
+const NEIGHBOR4: [Vector2<i32>; 4] = [
+    Direction::CARDINAL[0].to_offset(),
+    Direction::CARDINAL[1].to_offset(),
+    Direction::CARDINAL[2].to_offset(),
+    Direction::CARDINAL[3].to_offset(),
+];
+
+const NEIGHBOR8: [Vector2<i32>; 8] = [
+    Direction::ALL[0].to_offset(),
+    Direction::ALL[1].to_offset(),
+    Direction::ALL[2].to_offset(),
+    Direction::ALL[3].to_offset(),
+    Direction::ALL[4].to_offset(),
+    Direction::ALL[5].to_offset(),
+    Direction::ALL[6].to_offset(),
+    Direction::ALL[7].to_offset(),
+];
+
+impl Map {
+    pub fn new(desc: &str) -> Result<Self, GameError> {
+        let rows = desc
+            .split('\n')
+            .enumerate()
+            .filter(|(_, l)| !l.is_empty())
+            .map(|(n, l)| {
+                l.chars()
+                    .enumerate()
+                    .map(|(col, c)| {
+                        Square::fr_char(c).ok_or_else(|| {
+                            GameError::MapParse(format!(
+                                "line {}, column {}: unknown square `{}`",
+                                n + 1,
+                                col + 1,
+                                c
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (w, h) = (rows.iter().map(Vec::len).fold(0, max), rows.len());
+        let squares = rows.into_iter().flat_map(|row| {
+            let pad = w - row.len();
+            row.into_iter().chain(iter::repeat_n(Square::Empty, pad))
+        });
+        Ok(Map::from_grid(
+            DMatrix::from_iterator(w, h, squares).transpose(),
+        ))
+    }
+    fn from_grid(grid: DMatrix<Square>) -> Self {
+        Map {
+            occupied: DMatrix::from_element(grid.nrows(), grid.ncols(), false),
+            grid,
+            generation: 0,
+            toroidal: false,
+        }
+    }
+    /// The same map with its edges wrapped round, so that enemies walking
+    /// off one side come back on at the other.
+    pub fn with_toroidal(mut self) -> Self {
+        self.toroidal = true;
+        self
+    }
+    /// A random maze of roughly `width` by `height` cells, rounded up to odd
+    /// sizes, entered by a spawn point at the top-left corner and left by a
+    /// destination at the bottom-right. A few inner walls are knocked out
+    /// afterwards so there is more than one route and some room to build.
+    pub fn generate<R: Rng>(width: usize, height: usize, rng: &mut R) -> Self {
+        let (w, h) = (max(width, 5) | 1, max(height, 5) | 1);
+        let mut grid = DMatrix::from_element(h, w, Square::Wall);
+        let inner =
+            |t: Vector2<i32>| t.x > 0 && t.y > 0 && t.x < w as i32 - 1 && t.y < h as i32 - 1;
+        grid[(1, 1)] = Square::Empty;
+        let mut stack = vec![Vector2::new(1, 1)];
+        while let Some(&cur) = stack.last() {
+            let next = Direction::CARDINAL
+                .iter()
+                .map(|d| cur + d.to_offset() * 2)
+                .filter(|t| inner(*t) && grid[(t.y as usize, t.x as usize)] == Square::Wall)
+                .choose(rng);
+            match next {
+                Some(t) => {
+                    let between = (cur + t) / 2;
+                    grid[(between.y as usize, between.x as usize)] = Square::Empty;
+                    grid[(t.y as usize, t.x as usize)] = Square::Empty;
+                    stack.push(t);
+                }
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+        for _ in 0..w * h / 20 {
+            let (x, y) = (rng.gen_range(1..w - 1), rng.gen_range(1..h - 1));
+            let across = grid[(y, x - 1)] == Square::Empty && grid[(y, x + 1)] == Square::Empty;
+            let down = grid[(y - 1, x)] == Square::Empty && grid[(y + 1, x)] == Square::Empty;
+            if across || down {
+                grid[(y, x)] = Square::Empty;
+            }
+        }
+        grid[(0, 1)] = Square::SpawnPoint;
+        grid[(h - 1, w - 2)] = Square::Destination;
+        Map::from_grid(grid)
+    }
+    /// A single straight corridor with a spawn point at its west end, a
+    /// destination at its east end and `length` empty cells in between,
+    /// walled in on every side: a way to line up as many enemies as a
+    /// benchmark needs.
+    pub fn stress_corridor(length: usize) -> Self {
+        let length = max(length, 1);
+        let mut grid = DMatrix::from_element(3, length + 2, Square::Wall);
+        for x in 1..=length {
+            grid[(1, x)] = Square::Empty;
+        }
+        grid[(1, 0)] = Square::SpawnPoint;
+        grid[(1, length + 1)] = Square::Destination;
+        Map::from_grid(grid)
+    }
+    /// Loads a map saved either with `to_bytes` or as ASCII art.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, GameError> {
+        let bytes = fs::read(path)?;
+        if bytes.starts_with(&MAGIC) {
+            Map::from_bytes(&bytes)
+        } else {
+            let desc = std::str::from_utf8(&bytes)
+                .map_err(|_| GameError::MapParse("not valid UTF-8".to_string()))?;
+            Map::new(desc)
+        }
+    }
+    /// Writes the grid to `path` as a PNG, `PNG_CELL_SIZE` pixels a side per
+    /// cell, for sharing a map outside the terminal.
+    #[cfg(feature = "png")]
+    pub fn to_image<P: AsRef<Path>>(&self, path: P) -> Result<(), GameError> {
+        let (w, h) = (self.grid.ncols() as u32, self.grid.nrows() as u32);
+        let size = PNG_CELL_SIZE as u32;
+        let image = image::RgbImage::from_fn(w * size, h * size, |x, y| {
+            let s = Vector2::new((x / size) as usize, (y / size) as usize);
+            image::Rgb(self[s].rgb())
+        });
+        image
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|err| match err {
+                image::ImageError::IoError(err) => GameError::Io(err),
+                err => GameError::Io(std::io::Error::other(err)),
+            })
+    }
+    /// A compact encoding of the grid: `MAGIC`, a version byte, the width and
+    /// height as little-endian `u32`s, then the squares in row-major order as
+    /// `(run length, square)` byte pairs. Tower occupancy is not included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.grid.ncols() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.grid.nrows() as u32).to_le_bytes());
+        // The transpose is stored column-major, which is our row-major order.
+        let rows = self.grid.transpose();
+        let mut squares = rows.iter().copied();
+        let mut run = match squares.next() {
+            Some(first) => (first, 1u8),
+            None => return bytes,
+        };
+        for sq in squares {
+            if sq == run.0 && run.1 < u8::MAX {
+                run.1 += 1;
+            } else {
+                bytes.extend_from_slice(&[run.1, run.0.code()]);
+                run = (sq, 1);
+            }
+        }
+        bytes.extend_from_slice(&[run.1, run.0.code()]);
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, GameError> {
+        let bad = |why: String| GameError::MapParse(why);
+        let header_len = MAGIC.len() + 9;
+        if bytes.len() < header_len || !bytes.starts_with(&MAGIC) {
+            return Err(bad("not a binary map".to_string()));
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err(bad(format!(
+                "unsupported map version {}",
+                bytes[MAGIC.len()]
+            )));
+        }
+        let dim = |at: usize| {
+            let mut le = [0; 4];
+            le.copy_from_slice(&bytes[at..at + 4]);
+            u32::from_le_bytes(le) as usize
+        };
+        let (w, h) = (dim(MAGIC.len() + 1), dim(MAGIC.len() + 5));
+        let cells = w
+            .checked_mul(h)
+            .filter(|&cells| cells <= MAX_CELLS)
+            .ok_or_else(|| bad(format!("a {}x{} map is too big", w, h)))?;
+        let runs = &bytes[header_len..];
+        if !runs.len().is_multiple_of(2) {
+            return Err(bad("truncated square run".to_string()));
+        }
+        let found: usize = runs.chunks(2).map(|run| run[0] as usize).sum();
+        if found != cells {
+            return Err(bad(format!(
+                "expected {} squares for a {}x{} map, found {}",
+                cells, w, h, found
+            )));
+        }
+        let mut squares = Vec::with_capacity(cells);
+        for run in runs.chunks(2) {
+            let sq = Square::from_code(run[1])
+                .ok_or_else(|| bad(format!("unknown square code {}", run[1])))?;
+            squares.extend(iter::repeat_n(sq, run[0] as usize));
+        }
+        Ok(Map::from_grid(DMatrix::from_row_slice(h, w, &squares)))
+    }
+    /// A hash of the map's size and squares that comes out the same on every
+    /// run and platform, for telling maps apart in saves and high scores.
+    /// 64-bit FNV-1a over the width, the height and the square codes in
+    /// row-major order.
+    pub fn fingerprint(&self) -> u64 {
+        let w = (self.grid.ncols() as u64).to_le_bytes();
+        let h = (self.grid.nrows() as u64).to_le_bytes();
+        let squares = (0..self.grid.nrows())
+            .flat_map(|y| (0..self.grid.ncols()).map(move |x| self.grid[(y, x)].code()));
+        w.iter()
+            .chain(h.iter())
+            .copied()
+            .chain(squares)
+            .fold(FNV_OFFSET, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            })
+    }
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    pub fn is_occupied(&self, s: Vector2<usize>) -> bool {
+        self.occupied[(s.y, s.x)]
+    }
+    pub fn set_occupied(&mut self, s: Vector2<usize>, occupied: bool) {
+        self.generation += 1;
+        self.occupied[(s.y, s.x)] = occupied;
+    }
+    /// Whether nothing but open ground lies on the straight line between
+    /// `from` and `to`; walls on either end don't count.
+    pub fn in_sight(&self, from: Vector2<usize>, to: Vector2<usize>) -> bool {
+        line(from, to)
+            .into_iter()
+            .all(|c| c == from || c == to || self[c] != Square::Wall)
+    }
+    /// Whether an enemy can step onto `s`.
+    pub fn passable(&self, s: Vector2<usize>) -> bool {
+        matches!(
+            self[s],
+            Square::Empty | Square::Destination | Square::Hazard | Square::Road
+        ) && !self.is_occupied(s)
+    }
+    /// Every cell holding `sq`, in row-major order.
+    pub fn cells_of(&self, sq: Square) -> impl Iterator<Item = Vector2<usize>> + '_ {
+        (0..self.grid.nrows())
+            .flat_map(move |y| (0..self.grid.ncols()).map(move |x| Vector2::new(x, y)))
+            .filter(move |s| self[*s] == sq)
+    }
+    pub fn spawn_points(&self) -> impl Iterator<Item = Vector2<usize>> + '_ {
+        self.cells_of(Square::SpawnPoint)
+    }
+    pub fn destinations(&self) -> impl Iterator<Item = Vector2<usize>> + '_ {
+        self.cells_of(Square::Destination)
+    }
+    /// Walls in the map all the way round: every cell along its border
+    /// becomes a wall, except for spawn points and destinations, which stay
+    /// where they are. The cells inside are left alone.
+    pub fn add_border(&mut self) {
+        let (h, w) = (self.grid.nrows(), self.grid.ncols());
+        for y in 0..h {
+            for x in 0..w {
+                let s = Vector2::new(x, y);
+                let edge = x == 0 || y == 0 || x + 1 == w || y + 1 == h;
+                if edge && matches!(self[s], Square::Empty | Square::Hazard | Square::Road) {
+                    self[s] = Square::Wall;
+                }
+            }
+        }
+    }
+    /// The walkable cells along the border of the map, in row-major order.
+    pub fn edge_cells(&self) -> Vec<Vector2<usize>> {
+        let (h, w) = (self.grid.nrows(), self.grid.ncols());
+        (0..h)
+            .flat_map(|y| (0..w).map(move |x| Vector2::new(x, y)))
+            .filter(|s| s.x == 0 || s.y == 0 || s.x + 1 == w || s.y + 1 == h)
+            .filter(|s| self.passable(*s))
+            .collect()
+    }
+    /// `in_bounds` for a cell given as `usize`s, which can lie further off
+    /// the map than an `i32` reaches.
+    pub fn contains(&self, s: Vector2<usize>) -> bool {
+        s.x < self.grid.ncols() && s.y < self.grid.nrows()
+    }
+    pub fn in_bounds(&self, s: Vector2<i32>) -> bool {
+        s.x >= 0 && s.y >= 0 && s.x < self.grid.ncols() as i32 && s.y < self.grid.nrows() as i32
+    }
+    /// The cell `s` lands on: itself if it is on the map, the cell it wraps
+    /// round to on a toroidal map, and `None` otherwise.
+    pub fn wrap(&self, s: Vector2<i32>) -> Option<Vector2<usize>> {
+        let (w, h) = (self.grid.ncols() as i32, self.grid.nrows() as i32);
+        if self.toroidal && w > 0 && h > 0 {
+            Some(Vector2::new(
+                s.x.rem_euclid(w) as usize,
+                s.y.rem_euclid(h) as usize,
+            ))
+        } else if self.in_bounds(s) {
+            Some(s.map(|x| x as usize))
+        } else {
+            None
+        }
+    }
+    /// How many steps apart `a` and `b` are moving along the grid, going
+    /// across the edges where that is shorter on a toroidal map.
+    pub fn steps_between(&self, a: Vector2<usize>, b: Vector2<usize>) -> usize {
+        let axis = |p: usize, q: usize, size: usize| {
+            let d = p.abs_diff(q);
+            if self.toroidal {
+                d.min(size - d)
+            } else {
+                d
+            }
+        };
+        axis(a.x, b.x, self.grid.ncols()) + axis(a.y, b.y, self.grid.nrows())
+    }
+    fn neighbors_offsets<'a>(
+        &'a self,
+        s: Vector2<usize>,
+        offsets: &'a [Vector2<i32>],
+    ) -> impl Iterator<Item = Vector2<usize>> + 'a {
+        let s = s.map(|x| x as i32);
+        offsets.iter().filter_map(move |t| self.wrap(s + t))
+    }
+    pub fn neighbors_4(&self, s: Vector2<usize>) -> impl Iterator<Item = Vector2<usize>> + '_ {
+        self.neighbors_offsets(s, &NEIGHBOR4)
+    }
+    /// Whether the grid mirrors onto itself across `axis`. With
+    /// `respect_roles` unset, spawn points and destinations count as the
+    /// same, so a map where each side attacks the other still qualifies.
+    pub fn is_symmetric(&self, axis: Axis, respect_roles: bool) -> bool {
+        let (h, w) = (self.grid.nrows(), self.grid.ncols());
+        let role = |sq: Square| match sq {
+            Square::SpawnPoint if !respect_roles => Square::Destination,
+            sq => sq,
+        };
+        (0..h).all(|y| {
+            (0..w).all(|x| {
+                let (my, mx) = match axis {
+                    Axis::Horizontal => (h - 1 - y, x),
+                    Axis::Vertical => (y, w - 1 - x),
+                };
+                role(self.grid[(y, x)]) == role(self.grid[(my, mx)])
+            })
+        })
+    }
+
+    /// The next `k` cells an enemy on `start` would walk through towards
+    /// the nearest destination, found by one search instead of one per step:
+    /// fewer if the destination comes sooner, and none if it can't be
+    /// reached. The first cell is always the step `pf_search` takes.
+    pub fn plan_moves(&self, start: Vector2<usize>, k: usize) -> Vec<Vector2<usize>> {
+        let mut plan = cheapest_route(self, start);
+        plan.truncate(k);
+        plan
+    }
+
+    /// Cells that every path from some spawn point to the destinations has
+    /// to cross, so that blocking any one of them would cut that spawn point
+    /// off. Row-major order.
+    pub fn chokepoints(&self) -> Vec<Vector2<usize>> {
+        let field = distance_field(self);
+        let spawns: Vec<_> = self
+            .spawn_points()
+            .filter(|s| reaches(self, &field, *s))
+            .collect();
+        // A cell on every path is in particular on a shortest one, so only
+        // those need to be tried.
+        let mut candidates = Vec::new();
+        for &s in spawns.iter() {
+            let mut cur = s;
+            while let Some((d, next)) = self
+                .neighbors_4(cur)
+                .filter_map(|t| field[(t.y, t.x)].map(|d| (d, t)))
+                .min_by_key(|(d, _)| *d)
+            {
+                if d == 0 || field[(cur.y, cur.x)].is_some_and(|c| d >= c) {
+                    break;
+                }
+                if !candidates.contains(&next) {
+                    candidates.push(next);
+                }
+                cur = next;
+            }
+        }
+        let mut chokepoints: Vec<_> = candidates
+            .into_iter()
+            .filter(|&c| {
+                let mut blocked = self.clone();
+                blocked.set_occupied(c, true);
+                let after = distance_field(&blocked);
+                spawns.iter().any(|s| !reaches(&blocked, &after, *s))
+            })
+            .collect();
+        chokepoints.sort_by_key(|c| (c.y, c.x));
+        chokepoints
+    }
+    /// The map itself if it can be played, or a `GameError::MapParse`
+    /// listing everything `validate` finds wrong with it.
+    pub fn playable(self) -> Result<Map, GameError> {
+        let problems: Vec<_> = self.validate().iter().map(|p| p.to_string()).collect();
+        if problems.is_empty() {
+            Ok(self)
+        } else {
+            Err(GameError::MapParse(problems.join("; ")))
+        }
+    }
+    /// Everything that makes the map unplayable; empty if nothing does.
+    pub fn validate(&self) -> Vec<MapProblem> {
+        let mut problems = Vec::new();
+        let field = distance_field(self);
+        // Without spawn points enemies come in from the edge instead.
+        if self.spawn_points().next().is_none()
+            && !self
+                .edge_cells()
+                .iter()
+                .any(|s| field[(s.y, s.x)].is_some_and(|d| d > 0))
+        {
+            problems.push(MapProblem::NoSpawnPoints);
+        }
+        if self.destinations().next().is_none() {
+            problems.push(MapProblem::NoDestinations);
+        }
+        problems.extend(
+            self.spawn_points()
+                .filter(|s| !reaches(self, &field, *s))
+                .map(MapProblem::Unreachable),
+        );
+        problems
+    }
+
+    /// The walkable cells, grouped into the areas an enemy can get around
+    /// without crossing a wall. Both the areas and the cells in them are in
+    /// row-major order of their first cell.
+    pub fn connected_regions(&self) -> Vec<Vec<Vector2<usize>>> {
+        let mut seen = DMatrix::from_element(self.grid.nrows(), self.grid.ncols(), false);
+        let mut regions = Vec::new();
+        for start in (0..self.grid.nrows())
+            .flat_map(|y| (0..self.grid.ncols()).map(move |x| Vector2::new(x, y)))
+        {
+            if seen[(start.y, start.x)] || !self.passable(start) {
+                continue;
+            }
+            seen[(start.y, start.x)] = true;
+            let mut region = vec![start];
+            let mut i = 0;
+            while i < region.len() {
+                for t in self.neighbors_4(region[i]) {
+                    if !seen[(t.y, t.x)] && self.passable(t) {
+                        seen[(t.y, t.x)] = true;
+                        region.push(t);
+                    }
+                }
+                i += 1;
+            }
+            region.sort_by_key(|c| (c.y, c.x));
+            regions.push(region);
+        }
+        regions
+    }
+    /// The cells that can be walked to from `center` in at most `steps`
+    /// steps, `center` first, then in the order a breadth-first search finds
+    /// them, going through neighbors as `neighbors_4` lists them. Walls and
+    /// towers are never crossed, so nothing is found on their far side.
+    pub fn reachable_within(&self, center: Vector2<usize>, steps: usize) -> Vec<Vector2<usize>> {
+        let mut cells = vec![center];
+        let mut frontier = vec![center];
+        for _ in 0..steps {
+            let mut next = Vec::new();
+            for &cur in frontier.iter() {
+                for t in self.neighbors_4(cur) {
+                    if self.passable(t) && !cells.contains(&t) {
+                        cells.push(t);
+                        next.push(t);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        cells
+    }
+    pub fn neighbors_8(&self, s: Vector2<usize>) -> impl Iterator<Item = Vector2<usize>> + '_ {
+        self.neighbors_offsets(s, &NEIGHBOR8)
+    }
+}
+
+impl Index<Vector2<usize>> for Map {
+    type Output = Square;
+
+    fn index(&self, i: Vector2<usize>) -> &Square {
+        self.grid.index((i.y, i.x))
+    }
+}
+
+impl IndexMut<Vector2<usize>> for Map {
+    fn index_mut(&mut self, i: Vector2<usize>) -> &mut Square {
+        self.generation += 1;
+        self.grid.index_mut((i.y, i.x))
+    }
+}
+
+impl Map {
+    /// Draws the static grid in the colors of `theme`.
+    pub fn draw(&self, theme: &Theme, buf: &mut Buffer) {
+        for (y, row) in self.grid.row_iter().enumerate() {
+            for (x, sq) in row.iter().enumerate() {
+                let c = buf.get_mut(x as u16, y as u16);
+                c.set_char(sq.glyph(None, theme))
+                    .set_style(sq.style(None, theme));
+            }
+        }
+    }
+}
+
+impl Widget for &Map {
+    fn render(self, _area: Rect, buf: &mut Buffer) {
+        self.draw(&Theme::default(), buf);
+    }
+}
+
+/// Why a map can't be played.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MapProblem {
+    NoSpawnPoints,
+    NoDestinations,
+    /// The spawn point here has no way to any destination.
+    Unreachable(Vector2<usize>),
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapProblem::NoSpawnPoints => {
+                f.write_str("no spawn points, and no edge cell enemies could come in from")
+            }
+            MapProblem::NoDestinations => f.write_str("no destinations"),
+            MapProblem::Unreachable(s) => write!(
+                f,
+                "the spawn point at ({}, {}) can't reach a destination",
+                s.x, s.y
+            ),
+        }
+    }
+}
+
+/// How many steps apart `a` and `b` are, moving along the grid.
+pub fn manhattan(a: Vector2<usize>, b: Vector2<usize>) -> usize {
+    a.x.abs_diff(b.x) + a.y.abs_diff(b.y)
+}
+
+/// The cells of a Bresenham line from `from` to `to`, both ends included.
+pub fn line(from: Vector2<usize>, to: Vector2<usize>) -> Vec<Vector2<usize>> {
+    let (mut x, mut y) = (from.x as i64, from.y as i64);
+    let (x1, y1) = (to.x as i64, to.y as i64);
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+    let mut err = dx + dy;
+    let mut cells = Vec::new();
+    loop {
+        cells.push(Vector2::new(x as usize, y as usize));
+        if x == x1 && y == y1 {
+            return cells;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn sample_map_round_trips_through_bytes() {
+        let map = Map::new(crate::MAP).unwrap();
+        let back = Map::from_bytes(&map.to_bytes()).unwrap();
+        assert_eq!(back.grid, map.grid);
+    }
+
+    #[test]
+    fn large_maze_round_trips_through_bytes() {
+        let map = Map::generate(401, 201, &mut StdRng::seed_from_u64(7));
+        let bytes = map.to_bytes();
+        assert!(bytes.len() < map.grid.len());
+        assert_eq!(Map::from_bytes(&bytes).unwrap().grid, map.grid);
+    }
+
+    #[test]
+    fn from_bytes_rejects_bad_headers() {
+        let mut bytes = Map::new(crate::MAP).unwrap().to_bytes();
+        assert!(Map::from_bytes(b"TIVQ\x01\0\0\0\0\0\0\0\0").is_err());
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert!(Map::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn from_bytes_rejects_oversized_dimensions() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&[0xff; 8]);
+        bytes.extend_from_slice(&[255, Square::Empty.code()]);
+        assert!(matches!(
+            Map::from_bytes(&bytes),
+            Err(GameError::MapParse(_))
+        ));
+    }
+
+    #[test]
+    fn from_bytes_rejects_runs_that_miss_the_size() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&[3, Square::Empty.code()]);
+        assert!(Map::from_bytes(&bytes).is_err());
+        bytes.extend_from_slice(&[2, Square::Wall.code()]);
+        assert!(Map::from_bytes(&bytes).is_err());
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn exported_image_has_a_cell_of_pixels_per_square() {
+        let map = Map::new("^ #\n  $").unwrap();
+        let path = std::env::temp_dir().join(format!("ti-export-{}.png", std::process::id()));
+        map.to_image(&path).unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        fs::remove_file(&path).unwrap();
+        let size = PNG_CELL_SIZE as u32;
+        assert_eq!(image.dimensions(), (3 * size, 2 * size));
+        let corner = |x: u32, y: u32| image.get_pixel(x, y).0;
+        let (right, bottom) = (3 * size - 1, 2 * size - 1);
+        assert_eq!(corner(0, 0), Square::SpawnPoint.rgb());
+        assert_eq!(corner(right, 0), Square::Wall.rgb());
+        assert_eq!(corner(0, bottom), Square::Empty.rgb());
+        assert_eq!(corner(right, bottom), Square::Destination.rgb());
+    }
+
+    #[test]
+    fn reachable_within_goes_outwards_in_neighbor_order() {
+        let map = Map::new("#####\n#   #\n# ^ #\n#   #\n####$").unwrap();
+        let c = Vector2::new(2, 2);
+        let near = map.reachable_within(c, 1);
+        let expected: Vec<_> = std::iter::once(c).chain(map.neighbors_4(c)).collect();
+        assert_eq!(near, expected);
+        assert_eq!(map.reachable_within(c, 2).len(), 9);
+    }
+
+    #[test]
+    fn only_playable_maps_get_through() {
+        assert!(Map::new(crate::MAP).unwrap().playable().is_ok());
+        let walled_in = Map::new("#####\n#^#$#\n#####").unwrap();
+        match walled_in.playable() {
+            Err(GameError::MapParse(why)) => {
+                assert_eq!(why, "the spawn point at (1, 1) can't reach a destination")
+            }
+            other => panic!("expected a map error, got {:?}", other.map(drop)),
+        }
+        let nowhere_to_go = Map::new("####\n#^ #\n####").unwrap();
+        assert!(matches!(
+            nowhere_to_go.playable(),
+            Err(GameError::MapParse(_))
+        ));
+    }
+
+    #[test]
+    fn every_cell_of_a_forced_corridor_is_a_chokepoint() {
+        let map =
+            Map::new("###########\n#  #####  #\n#^       $#\n#  #####  #\n###########").unwrap();
+        let corridor: Vec<_> = (2..=8).map(|x| Vector2::new(x, 2)).collect();
+        assert_eq!(map.chokepoints(), corridor);
+        let open = Map::new("#####\n#^  #\n#   #\n#  $#\n#####").unwrap();
+        assert!(open.chokepoints().is_empty());
+    }
+
+    #[test]
+    fn destinations_pulse_while_walls_hold_still() {
+        let theme = Theme::default();
+        let frames = [Some(0), Some(1)];
+        let [a, b] = frames.map(|f| Square::Destination.to_char(f));
+        assert_ne!(a, b);
+        assert_ne!(
+            Square::Destination.style(Some(0), &theme),
+            Square::Destination.style(Some(1), &theme)
+        );
+        assert_eq!(Square::Destination.to_char(None), '$');
+        for frame in [None, Some(0), Some(1), Some(2)] {
+            assert_eq!(Square::Wall.to_char(frame), '#');
+            assert_eq!(
+                Square::Wall.style(frame, &theme),
+                Square::Wall.style(None, &theme)
+            );
+        }
+    }
+
+    #[test]
+    fn symmetry_is_checked_across_either_axis() {
+        let duel = Map::new("#######\n#^ # $#\n#  #  #\n#^ # $#\n#######").unwrap();
+        assert!(duel.is_symmetric(Axis::Horizontal, true));
+        assert!(duel.is_symmetric(Axis::Vertical, false));
+        assert!(!duel.is_symmetric(Axis::Vertical, true));
+
+        let lopsided = Map::new("#######\n#^ #  #\n#  # $#\n#######").unwrap();
+        assert!(!lopsided.is_symmetric(Axis::Horizontal, false));
+        assert!(!lopsided.is_symmetric(Axis::Vertical, false));
+    }
+
+    #[test]
+    fn a_stress_corridor_is_one_clear_run_of_the_asked_length() {
+        let m = Map::stress_corridor(50);
+        assert_eq!((m.grid.nrows(), m.grid.ncols()), (3, 52));
+        assert!(m.validate().is_empty());
+        let spawns: Vec<_> = m.spawn_points().collect();
+        assert_eq!(spawns, [Vector2::new(0, 1)]);
+        assert_eq!(m.destinations().collect::<Vec<_>>(), [Vector2::new(51, 1)]);
+        let route = cheapest_route(&m, spawns[0]);
+        assert_eq!(route.len(), 51);
+        assert!(route.iter().all(|c| c.y == 1));
+    }
+
+    #[test]
+    fn planned_moves_are_a_walkable_start_of_the_way_to_the_destination() {
+        let m = Map::new(crate::MAP).unwrap();
+        let start = m.spawn_points().next().unwrap();
+        let field = distance_field(&m);
+        let distance = |c: Vector2<usize>| field[(c.y, c.x)].unwrap();
+        for k in [1, 3, 8] {
+            let plan = m.plan_moves(start, k);
+            assert!(!plan.is_empty() && plan.len() <= k);
+            assert_eq!(Some(plan[0]), crate::path::pf_search(&m, start));
+            let mut from = start;
+            for &step in plan.iter() {
+                assert!(m.passable(step));
+                assert_eq!(manhattan(from, step), 1);
+                from = step;
+            }
+            // Every step brings the destination one closer.
+            assert_eq!(distance(from) + plan.len() - 1, distance(plan[0]));
+        }
+        let short = Map::new("#####\n#^ $#\n#####").unwrap();
+        assert_eq!(short.plan_moves(Vector2::new(1, 1), 5).len(), 2);
+        let walled = Map::new("#####\n#^#$#\n#####").unwrap();
+        assert!(walled.plan_moves(Vector2::new(1, 1), 5).is_empty());
+    }
+
+    #[test]
+    fn a_border_walls_the_edge_but_keeps_spawns_destinations_and_the_inside() {
+        let mut m = Map::new("  ~  \n^ = #\n     \n   $ ").unwrap();
+        let before = m.clone();
+        m.add_border();
+        let (h, w) = (m.grid.nrows(), m.grid.ncols());
+        for y in 0..h {
+            for x in 0..w {
+                let s = Vector2::new(x, y);
+                let edge = x == 0 || y == 0 || x + 1 == w || y + 1 == h;
+                let expected = match before[s] {
+                    Square::SpawnPoint | Square::Destination => before[s],
+                    _ if edge => Square::Wall,
+                    sq => sq,
+                };
+                assert_eq!(m[s], expected, "at {:?}", s);
+            }
+        }
+        assert_eq!(m[Vector2::new(2, 1)], Square::Road);
+        assert_eq!(m[Vector2::new(3, 3)], Square::Destination);
+    }
+
+    #[test]
+    fn on_a_toroidal_map_the_edges_join_up() {
+        let bounded = Map::new("#####\n  ^#$\n#####").unwrap();
+        let torus = bounded.clone().with_toroidal();
+        let edge = Vector2::new(0, 1);
+        assert!(torus.neighbors_4(edge).any(|n| n == Vector2::new(4, 1)));
+        assert!(!bounded.neighbors_4(edge).any(|n| n == Vector2::new(4, 1)));
+
+        let spawn = Vector2::new(2, 1);
+        assert!(cheapest_route(&bounded, spawn).is_empty());
+        let route = cheapest_route(&torus, spawn);
+        assert_eq!(
+            route,
+            [(1, 1), (0, 1), (4, 1)].map(|(x, y)| Vector2::new(x, y))
+        );
+        assert_eq!(torus.steps_between(edge, Vector2::new(4, 1)), 1);
+    }
+
+    #[test]
+    fn fingerprints_match_for_the_same_map_and_differ_for_any_change() {
+        let desc = "#####\n#^  #\n#  $#\n#####";
+        let a = Map::new(desc).unwrap();
+        let b = Map::new(desc).unwrap();
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        // Pinned, so a change to the hash that would break saves shows up.
+        assert_eq!(a.fingerprint(), 16282440636405046857);
+
+        let mut changed = Map::new(desc).unwrap();
+        changed[Vector2::new(2, 1)] = Square::Wall;
+        assert_ne!(changed.fingerprint(), a.fingerprint());
+        // The same squares in another shape aren't the same map.
+        let line = Map::new("#^$#").unwrap();
+        let column = Map::new("#\n^\n$\n#").unwrap();
+        assert_ne!(line.fingerprint(), column.fingerprint());
+    }
+}
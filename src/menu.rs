@@ -0,0 +1,214 @@
+use crate::util::StatefulList;
+use termion::event::Key;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Modifier, Style};
+use tui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph, StatefulWidget, Widget};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MenuItem {
+    Resume,
+    Restart,
+    MainMenu,
+    Quit,
+}
+
+impl MenuItem {
+    pub const ALL: [MenuItem; 4] = [
+        MenuItem::Resume,
+        MenuItem::Restart,
+        MenuItem::MainMenu,
+        MenuItem::Quit,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuItem::Resume => "Resume",
+            MenuItem::Restart => "Restart",
+            MenuItem::MainMenu => "Main Menu",
+            MenuItem::Quit => "Quit",
+        }
+    }
+}
+
+/// The menu shown over the game while it is paused.
+pub struct PauseMenu {
+    list: StatefulList<MenuItem>,
+}
+
+impl PauseMenu {
+    pub fn new() -> PauseMenu {
+        let mut list = StatefulList::with_items(MenuItem::ALL.to_vec());
+        list.state.select(Some(0));
+        PauseMenu { list }
+    }
+
+    pub fn next(&mut self) {
+        self.list.next();
+    }
+
+    pub fn previous(&mut self) {
+        self.list.previous();
+    }
+
+    pub fn selected(&self) -> MenuItem {
+        self.list.items[self.list.state.selected().unwrap_or(0)]
+    }
+
+    /// Feeds `key` to the menu: the arrows move the selection and enter
+    /// picks it. Returns the item picked, if one was, with escape counting
+    /// as picking `Resume`.
+    pub fn handle(&mut self, key: Key) -> Option<MenuItem> {
+        match key {
+            Key::Up => self.previous(),
+            Key::Down => self.next(),
+            Key::Esc => return Some(MenuItem::Resume),
+            Key::Char('\n') => return Some(self.selected()),
+            _ => {}
+        }
+        None
+    }
+}
+
+impl Widget for &mut PauseMenu {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let width = MenuItem::ALL
+            .iter()
+            .map(|i| i.label().len())
+            .max()
+            .unwrap_or(0) as u16
+            + 6;
+        let height = MenuItem::ALL.len() as u16 + 2;
+        let rect = Rect::new(
+            area.x + area.width.saturating_sub(width) / 2,
+            area.y + area.height.saturating_sub(height) / 2,
+            width.min(area.width),
+            height.min(area.height),
+        );
+        let items: Vec<_> = self
+            .list
+            .items
+            .iter()
+            .map(|item| ListItem::new(item.label()))
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("Paused"))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        Clear.render(rect, buf);
+        StatefulWidget::render(list, rect, buf, &mut self.list.state);
+    }
+}
+
+/// Asks before quitting, so that a stray `q` doesn't end the game.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum QuitPrompt {
+    #[default]
+    Closed,
+    /// Waiting for a yes or a no; the game is paused meanwhile.
+    Open,
+    Confirmed,
+}
+
+impl QuitPrompt {
+    /// Feeds `key` to the prompt and returns whether it took it. A closed
+    /// prompt only takes `q`, which opens it. An open one takes every key:
+    /// `y` confirms, `n` or escape closes it again, and the rest are
+    /// ignored.
+    pub fn handle(&mut self, key: Key) -> bool {
+        *self = match (*self, key) {
+            (QuitPrompt::Closed, Key::Char('q')) => QuitPrompt::Open,
+            (QuitPrompt::Open, Key::Char('y')) => QuitPrompt::Confirmed,
+            (QuitPrompt::Open, Key::Char('n')) | (QuitPrompt::Open, Key::Esc) => QuitPrompt::Closed,
+            (QuitPrompt::Open, _) => QuitPrompt::Open,
+            _ => return false,
+        };
+        true
+    }
+
+    pub fn is_open(self) -> bool {
+        self == QuitPrompt::Open
+    }
+
+    pub fn is_confirmed(self) -> bool {
+        self == QuitPrompt::Confirmed
+    }
+}
+
+impl Widget for QuitPrompt {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if !self.is_open() {
+            return;
+        }
+        let text = "Quit? (y/n)";
+        let width = text.len() as u16 + 4;
+        let height = 3;
+        let rect = Rect::new(
+            area.x + area.width.saturating_sub(width) / 2,
+            area.y + area.height.saturating_sub(height) / 2,
+            width.min(area.width),
+            height.min(area.height),
+        );
+        Clear.render(rect, buf);
+        Paragraph::new(format!(" {}", text))
+            .block(Block::default().borders(Borders::ALL))
+            .render(rect, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_pause_menu_opens_on_resume_and_wraps_around() {
+        let mut menu = PauseMenu::new();
+        assert_eq!(menu.selected(), MenuItem::Resume);
+        menu.next();
+        menu.next();
+        assert_eq!(menu.selected(), MenuItem::MainMenu);
+        menu.next();
+        menu.next();
+        assert_eq!(menu.selected(), MenuItem::Resume);
+        menu.previous();
+        assert_eq!(menu.selected(), MenuItem::Quit);
+        menu.next();
+        assert_eq!(menu.selected(), MenuItem::Resume);
+    }
+
+    #[test]
+    fn enter_picks_the_selection_and_escape_resumes() {
+        let mut menu = PauseMenu::new();
+        assert_eq!(menu.handle(Key::Char('\n')), Some(MenuItem::Resume));
+        assert_eq!(menu.handle(Key::Down), None);
+        assert_eq!(menu.handle(Key::Char('x')), None);
+        assert_eq!(menu.handle(Key::Char('\n')), Some(MenuItem::Restart));
+        assert_eq!(menu.handle(Key::Esc), Some(MenuItem::Resume));
+        assert_eq!(menu.selected(), MenuItem::Restart);
+    }
+
+    #[test]
+    fn quitting_takes_a_q_and_then_a_yes() {
+        let mut prompt = QuitPrompt::default();
+        assert!(!prompt.handle(Key::Char('y')));
+        assert!(!prompt.handle(Key::Char('p')));
+        assert_eq!(prompt, QuitPrompt::Closed);
+        assert!(prompt.handle(Key::Char('q')));
+        assert!(prompt.is_open());
+        assert!(prompt.handle(Key::Char('q')));
+        assert!(prompt.is_open());
+        assert!(prompt.handle(Key::Char('y')));
+        assert!(prompt.is_confirmed());
+    }
+
+    #[test]
+    fn no_or_escape_call_the_quit_off() {
+        for key in [Key::Char('n'), Key::Esc] {
+            let mut prompt = QuitPrompt::default();
+            prompt.handle(Key::Char('q'));
+            assert!(prompt.handle(key));
+            assert_eq!(prompt, QuitPrompt::Closed);
+            assert!(!prompt.is_confirmed());
+        }
+    }
+}
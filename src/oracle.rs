@@ -0,0 +1,164 @@
+//! A slow but plainly correct reference for the pathfinders, for the tests
+//! to check that they still agree with it on every move.
+
+use crate::map::{Map, Square};
+use crate::path::{FlowFields, Pathfinder};
+use nalgebra::{DMatrix, Vector2};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Seeds the generated maps are made from.
+const SEEDS: u64 = 8;
+/// Maps generated for `check_generated`, as `(width, height)`.
+const SIZES: [(usize, usize); 3] = [(9, 7), (21, 11), (41, 17)];
+
+/// A cell where a pathfinder went another way than the oracle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    pub pathfinder: Pathfinder,
+    pub cell: Vector2<usize>,
+    pub expected: Option<Vector2<usize>>,
+    pub got: Option<Vector2<usize>>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |step: Option<Vector2<usize>>| match step {
+            Some(t) => format!("({}, {})", t.x, t.y),
+            None => "nowhere".to_string(),
+        };
+        write!(
+            f,
+            "{} from ({}, {}): expected {}, got {}",
+            self.pathfinder,
+            self.cell.x,
+            self.cell.y,
+            show(self.expected),
+            show(self.got)
+        )
+    }
+}
+
+/// How many steps it takes from every cell to the nearest destination, by a
+/// breadth-first search of its own outwards from all of them at once. Cells
+/// with no way there are left at `None`.
+fn steps_to_goal(m: &Map) -> DMatrix<Option<usize>> {
+    let mut steps = DMatrix::from_element(m.grid.nrows(), m.grid.ncols(), None);
+    let mut q = VecDeque::new();
+    for d in m.destinations() {
+        steps[(d.y, d.x)] = Some(0);
+        q.push_back((d, 0));
+    }
+    while let Some((cur, d)) = q.pop_front() {
+        for t in m.neighbors_4(cur) {
+            if m.passable(t) && steps[(t.y, t.x)].is_none() {
+                steps[(t.y, t.x)] = Some(d + 1);
+                q.push_back((t, d + 1));
+            }
+        }
+    }
+    steps
+}
+
+/// The first move every pathfinder should make from `s`, given how far
+/// every cell is from a destination: the neighbor closest to one, with ties
+/// going to the one furthest down and then furthest right. A destination
+/// stays put. Only right for maps without hazards or roads, which some
+/// pathfinders steer by.
+fn expected_move(
+    m: &Map,
+    steps: &DMatrix<Option<usize>>,
+    s: Vector2<usize>,
+) -> Option<Vector2<usize>> {
+    if m[s] == Square::Destination {
+        return Some(s);
+    }
+    let mut next: Vec<_> = m.neighbors_4(s).filter(|t| m.passable(*t)).collect();
+    next.sort_by_key(|t| (Reverse(t.y), Reverse(t.x)));
+    next.into_iter()
+        .filter_map(|t| steps[(t.y, t.x)].map(|d| (t, d)))
+        .min_by_key(|&(_, d)| d)
+        .map(|(t, _)| t)
+}
+
+/// Every cell an enemy can stand on where `pathfinder` disagrees with the
+/// oracle, in row-major order.
+fn check(m: &Map, pathfinder: Pathfinder) -> Vec<Mismatch> {
+    let goals: Vec<_> = m.destinations().collect();
+    let steps = steps_to_goal(m);
+    let mut flow = FlowFields::default();
+    (0..m.grid.nrows())
+        .flat_map(|y| (0..m.grid.ncols()).map(move |x| Vector2::new(x, y)))
+        .filter(|&s| m.passable(s) || m[s] == Square::SpawnPoint)
+        .filter_map(|cell| {
+            let expected = expected_move(m, &steps, cell);
+            let got = pathfinder.step_towards(m, cell, &goals, &mut flow);
+            (expected != got).then_some(Mismatch {
+                pathfinder,
+                cell,
+                expected,
+                got,
+            })
+        })
+        .collect()
+}
+
+/// Checks `pathfinder` on mazes generated from seeds `0..SEEDS` in a few
+/// sizes. Returns a line for every mismatch, saying which maze it was found
+/// on.
+fn check_generated(pathfinder: Pathfinder) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    for seed in 0..SEEDS {
+        for &(w, h) in SIZES.iter() {
+            let map = Map::generate(w, h, &mut StdRng::seed_from_u64(seed));
+            mismatches.extend(
+                check(&map, pathfinder)
+                    .iter()
+                    .map(|m| format!("seed {}, {}x{}: {}", seed, w, h, m)),
+            );
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_agrees(pathfinder: Pathfinder) {
+        let mismatches = check_generated(pathfinder);
+        assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+    }
+
+    #[test]
+    fn bfs_agrees_with_oracle() {
+        assert_agrees(Pathfinder::Bfs);
+    }
+
+    #[test]
+    fn dijkstra_agrees_with_oracle() {
+        assert_agrees(Pathfinder::Dijkstra);
+    }
+
+    #[test]
+    fn astar_agrees_with_oracle() {
+        assert_agrees(Pathfinder::AStar);
+    }
+
+    #[test]
+    fn flow_field_agrees_with_oracle() {
+        assert_agrees(Pathfinder::FlowField);
+    }
+
+    #[test]
+    fn expected_move_breaks_ties_downwards() {
+        let map = Map::new("#####\n#^  #\n# # #\n#  $#\n#####").unwrap();
+        let steps = steps_to_goal(&map);
+        let expected = |x, y| expected_move(&map, &steps, Vector2::new(x, y));
+        assert_eq!(expected(1, 1), Some(Vector2::new(1, 2)));
+        assert_eq!(expected(3, 3), Some(Vector2::new(3, 3)));
+    }
+}
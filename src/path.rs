@@ -0,0 +1,630 @@
+use crate::map::{line, Map, Square};
+use nalgebra::{DMatrix, Vector2};
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Wanders to a random empty neighbor, staying put if there is none. Using
+/// the caller's rng keeps seeded games reproducible.
+pub fn pf_random<R: Rng>(m: &Map, s: Vector2<usize>, rng: &mut R) -> Vector2<usize> {
+    m.neighbors_4(s)
+        .filter(|t| m[*t] == Square::Empty && !m.is_occupied(*t))
+        .choose(rng)
+        .unwrap_or(s)
+}
+
+/// What stepping onto an ordinary cell costs a path. Costs are whole
+/// numbers, so this leaves room for a road to come in under it.
+pub const STEP_COST: usize = 2;
+/// What stepping onto a road costs a path: half an ordinary step.
+pub const ROAD_COST: usize = 1;
+/// What stepping onto a hazard costs a path, eight ordinary steps. High
+/// enough that enemies take a fair detour to stay dry.
+pub const HAZARD_COST: usize = 8 * STEP_COST;
+
+// A free step would let searches go round in circles, and the A* estimate
+// counts on no step costing less than a road.
+const _: () = assert!(ROAD_COST > 0 && ROAD_COST <= STEP_COST && ROAD_COST <= HAZARD_COST);
+
+fn step_cost(m: &Map, t: Vector2<usize>) -> usize {
+    match m[t] {
+        Square::Hazard => HAZARD_COST,
+        Square::Road => ROAD_COST,
+        _ => STEP_COST,
+    }
+}
+
+/// What each point of tower threat on a cell adds to the cost of stepping
+/// onto it, in ordinary steps, for enemies that steer clear of towers.
+pub const THREAT_WEIGHT: f32 = 1.0;
+/// What a cell an evasive enemy remembers being hurt on adds to the cost of
+/// stepping onto it, three ordinary steps.
+pub const DANGER_COST: usize = 3 * STEP_COST;
+
+/// Maps smaller than this are searched sequentially even with the `rayon`
+/// feature, since handing a few hundred cells to a thread pool costs more
+/// than it saves.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_CELLS: usize = 128 * 128;
+
+static FIELD_RECOMPUTES: AtomicU64 = AtomicU64::new(0);
+
+/// How many distance fields have been computed so far, for the perf overlay.
+pub fn field_recomputes() -> u64 {
+    FIELD_RECOMPUTES.load(Ordering::Relaxed)
+}
+
+/// How many steps each cell is from the nearest destination, by a
+/// breadth-first search outwards from every destination at once. Cells that
+/// cannot reach a destination are `None`. Indexed by `(y, x)` like the grid.
+pub fn distance_field(m: &Map) -> DMatrix<Option<usize>> {
+    FIELD_RECOMPUTES.fetch_add(1, Ordering::Relaxed);
+    #[cfg(feature = "rayon")]
+    if m.grid.len() >= PARALLEL_MIN_CELLS {
+        return distance_field_par(m);
+    }
+    distance_field_seq(m)
+}
+
+pub fn distance_field_seq(m: &Map) -> DMatrix<Option<usize>> {
+    field_from(m, m.destinations())
+}
+
+/// Like `distance_field`, but measured from `sources` alone.
+fn field_from<I: IntoIterator<Item = Vector2<usize>>>(
+    m: &Map,
+    sources: I,
+) -> DMatrix<Option<usize>> {
+    let mut field = DMatrix::from_element(m.grid.nrows(), m.grid.ncols(), None);
+    let mut q = VecDeque::new();
+    for d in sources {
+        field[(d.y, d.x)] = Some(0);
+        q.push_back(d);
+    }
+    while let Some(cur) = q.pop_front() {
+        let d = field[(cur.y, cur.x)].unwrap();
+        for t in m.neighbors_4(cur) {
+            if m.passable(t) && field[(t.y, t.x)].is_none() {
+                field[(t.y, t.x)] = Some(d + 1);
+                q.push_back(t);
+            }
+        }
+    }
+    field
+}
+
+/// The same search one frontier at a time: the neighbors of a whole frontier
+/// are gathered in parallel, then claimed sequentially. A cell's depth does
+/// not depend on the order its frontier is expanded in, so the result matches
+/// `distance_field_seq` exactly.
+#[cfg(feature = "rayon")]
+pub fn distance_field_par(m: &Map) -> DMatrix<Option<usize>> {
+    use rayon::prelude::*;
+
+    let mut field = DMatrix::from_element(m.grid.nrows(), m.grid.ncols(), None);
+    let mut frontier: Vec<_> = m.destinations().collect();
+    for d in frontier.iter() {
+        field[(d.y, d.x)] = Some(0);
+    }
+    let mut depth = 0;
+    while !frontier.is_empty() {
+        depth += 1;
+        let candidates: Vec<Vector2<usize>> = frontier
+            .par_iter()
+            .flat_map_iter(|&cur| {
+                let field = &field;
+                m.neighbors_4(cur)
+                    .filter(move |t| m.passable(*t) && field[(t.y, t.x)].is_none())
+            })
+            .collect();
+        frontier.clear();
+        for t in candidates {
+            if field[(t.y, t.x)].is_none() {
+                field[(t.y, t.x)] = Some(depth);
+                frontier.push(t);
+            }
+        }
+    }
+    field
+}
+
+/// Whether an enemy standing on `s` has a way to a destination. `s` itself
+/// need not be passable, so that spawn points can be checked too.
+pub fn reaches(m: &Map, field: &DMatrix<Option<usize>>, s: Vector2<usize>) -> bool {
+    field[(s.y, s.x)].is_some() || m.neighbors_4(s).any(|t| field[(t.y, t.x)].is_some())
+}
+
+fn first_move(
+    parents: &HashMap<Vector2<usize>, Option<Vector2<usize>>>,
+    end: Vector2<usize>,
+) -> Vector2<usize> {
+    let mut cur = end;
+    let mut prev = end;
+    while let Some(&Some(parent)) = parents.get(&cur) {
+        prev = cur;
+        cur = parent;
+    }
+    prev
+}
+
+/// Breadth-first search towards the nearest destination, returning the first
+/// step of the path or `None` if no destination is reachable. Ties between
+/// equally short paths go as `preferred_steps` describes.
+pub fn pf_search(m: &Map, s: Vector2<usize>) -> Option<Vector2<usize>> {
+    search(m, s, |t| m[t] == Square::Destination, |t| step_cost(m, t))
+}
+
+/// Like `Pathfinder::step_towards` with Dijkstra's algorithm, but with each
+/// cell costing `THREAT_WEIGHT` more for every point of `threat` on it, so
+/// that a longer way round beats walking through a killing zone. `threat`
+/// is indexed by `(y, x)` like the grid.
+pub fn threat_step(
+    m: &Map,
+    s: Vector2<usize>,
+    goals: &[Vector2<usize>],
+    threat: &DMatrix<f32>,
+) -> Option<Vector2<usize>> {
+    search(
+        m,
+        s,
+        |t| goals.contains(&t),
+        |t| {
+            step_cost(m, t)
+                + (threat[(t.y, t.x)] * THREAT_WEIGHT * STEP_COST as f32).round() as usize
+        },
+    )
+}
+
+/// Like `Pathfinder::step_towards` with Dijkstra's algorithm, but with each
+/// of the `danger` cells costing `DANGER_COST` more, so that of two routes
+/// about as long the one that keeps off them wins.
+pub fn evasive_step(
+    m: &Map,
+    s: Vector2<usize>,
+    goals: &[Vector2<usize>],
+    danger: &[Vector2<usize>],
+) -> Option<Vector2<usize>> {
+    search(
+        m,
+        s,
+        |t| goals.contains(&t),
+        |t| step_cost(m, t) + if danger.contains(&t) { DANGER_COST } else { 0 },
+    )
+}
+
+/// The cells an enemy starting on `s` walks through on its way to the
+/// nearest destination, destination included and `s` left out. Empty if no
+/// destination can be reached.
+pub fn trace_route(m: &Map, s: Vector2<usize>) -> Vec<Vector2<usize>> {
+    let mut route = Vec::new();
+    let mut cur = s;
+    while m[cur] != Square::Destination && route.len() < m.grid.len() {
+        match pf_search(m, cur) {
+            Some(next) => {
+                route.push(next);
+                cur = next;
+            }
+            None => return Vec::new(),
+        }
+    }
+    route
+}
+
+/// Straightens `route`, the cells walked on the way from `start`, into the
+/// corners of a path that cuts across open ground wherever it can: from
+/// each corner, the next is the furthest cell of the route still in a
+/// straight, walkable line of it. Walking the lines between the corners
+/// never leaves passable ground. Ends on the route's last cell.
+pub fn string_pull(
+    m: &Map,
+    start: Vector2<usize>,
+    route: &[Vector2<usize>],
+) -> Vec<Vector2<usize>> {
+    let mut corners = Vec::new();
+    let mut from = start;
+    let mut i = 0;
+    while i < route.len() {
+        while i + 1 < route.len() && walkable(m, from, route[i + 1]) {
+            i += 1;
+        }
+        from = route[i];
+        corners.push(from);
+        i += 1;
+    }
+    corners
+}
+
+/// Whether the straight line from `from` to `to` can be walked: every cell
+/// after `from` passable, and no diagonal step cutting the corner of a
+/// cell that isn't.
+pub fn walkable(m: &Map, from: Vector2<usize>, to: Vector2<usize>) -> bool {
+    line(from, to).windows(2).all(|step| {
+        let (a, b) = (step[0], step[1]);
+        let straight = a.x == b.x || a.y == b.y;
+        m.passable(b)
+            && (straight
+                || m.passable(Vector2::new(a.x, b.y)) && m.passable(Vector2::new(b.x, a.y)))
+    })
+}
+
+/// A cheapest route from `s` to the nearest destination, found by a single
+/// search: destination included, `s` left out, and empty if none can be
+/// reached. Cheaper than `trace_route`, but where several routes cost the
+/// same it need not be the one enemies end up walking.
+pub fn cheapest_route(m: &Map, s: Vector2<usize>) -> Vec<Vector2<usize>> {
+    search_route(m, s, |t| m[t] == Square::Destination, |t| step_cost(m, t)).unwrap_or_default()
+}
+
+/// The neighbors of `cur` in the order paths prefer to step onto them when
+/// several are equally good: the one furthest down first, then of two on
+/// the same row the one furthest right. Searches expand cells in this order
+/// and settle ties first come first served, so among equally short paths
+/// the first move is always the first of these that lies on one.
+fn preferred_steps(m: &Map, cur: Vector2<usize>) -> Vec<Vector2<usize>> {
+    let mut steps: Vec<_> = m.neighbors_4(cur).collect();
+    steps.sort_by_key(|t| (Reverse(t.y), Reverse(t.x)));
+    steps
+}
+
+fn search<F: Fn(Vector2<usize>) -> bool, C: Fn(Vector2<usize>) -> usize>(
+    m: &Map,
+    s: Vector2<usize>,
+    is_goal: F,
+    cost_of: C,
+) -> Option<Vector2<usize>> {
+    search_route(m, s, is_goal, cost_of).map(|route| route.first().copied().unwrap_or(s))
+}
+
+/// The cells of the path `search` picks, after `s` and up to the goal.
+fn search_route<F: Fn(Vector2<usize>) -> bool, C: Fn(Vector2<usize>) -> usize>(
+    m: &Map,
+    s: Vector2<usize>,
+    is_goal: F,
+    cost_of: C,
+) -> Option<Vec<Vector2<usize>>> {
+    // Dijkstra's algorithm, with ties broken first come first served so that
+    // on a map without hazards or roads it settles cells in breadth-first
+    // order, and in the order of `preferred_steps` among neighbors.
+    let mut parents = HashMap::new();
+    // Queued steps, as (cost, sequence number); the cells and the cells they
+    // were reached from live in `steps` since vectors aren't `Ord`.
+    let mut q = BinaryHeap::new();
+    let mut steps = vec![(s, None)];
+    q.push((Reverse(0), Reverse(0)));
+    loop {
+        let (Reverse(cost), Reverse(i)) = q.pop()?;
+        let (cur, parent) = steps[i];
+        if parents.contains_key(&cur) {
+            continue;
+        }
+        parents.insert(cur, parent);
+        if is_goal(cur) {
+            let mut route = vec![cur];
+            while let Some(&Some(parent)) = parents.get(route.last().unwrap()) {
+                route.push(parent);
+            }
+            route.pop();
+            route.reverse();
+            return Some(route);
+        }
+        for t in preferred_steps(m, cur) {
+            if m.passable(t) && !parents.contains_key(&t) {
+                q.push((Reverse(cost + cost_of(t)), Reverse(steps.len())));
+                steps.push((t, Some(cur)));
+            }
+        }
+    }
+}
+
+/// How enemies find their way. Every algorithm takes the first step of a
+/// cheapest path, preferring neighbors in the order of `preferred_steps` when
+/// several are equally good, so on a map without hazards or roads they all
+/// move enemies the same way and differ only in how much work they do.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Pathfinder {
+    /// Breadth-first search, which counts steps and ignores hazards and
+    /// roads.
+    Bfs,
+    #[default]
+    Dijkstra,
+    /// Searches back from the destinations towards the enemy, guided by the
+    /// Manhattan distance to it, measured across the edges on a toroidal map.
+    AStar,
+    /// Walks down a distance field of the whole map, which like `Bfs`
+    /// counts steps and ignores hazards and roads.
+    FlowField,
+}
+
+/// The distance fields `Pathfinder::FlowField` walks down, one for every
+/// set of goals asked about, kept until the map changes so that each is
+/// worked out once rather than for every step of every enemy.
+#[derive(Clone, Debug, Default)]
+pub struct FlowFields {
+    generation: Option<u64>,
+    fields: HashMap<Vec<Vector2<usize>>, DMatrix<Option<usize>>>,
+}
+
+impl FlowFields {
+    /// The field measured from `goals` on `m`, worked out afresh only if
+    /// the map has changed since it was last asked for.
+    pub fn get(&mut self, m: &Map, goals: &[Vector2<usize>]) -> &DMatrix<Option<usize>> {
+        if self.generation != Some(m.generation()) {
+            self.fields.clear();
+            self.generation = Some(m.generation());
+        }
+        self.fields.entry(goals.to_vec()).or_insert_with(|| {
+            FIELD_RECOMPUTES.fetch_add(1, Ordering::Relaxed);
+            field_from(m, goals.iter().copied())
+        })
+    }
+}
+
+impl Pathfinder {
+    /// The first step from `s` towards `dest`, or towards the nearest
+    /// destination if `dest` is `None` or can't be reached. `None` if no
+    /// destination can be reached at all.
+    pub fn next_step(
+        self,
+        m: &Map,
+        s: Vector2<usize>,
+        dest: Option<Vector2<usize>>,
+        flow: &mut FlowFields,
+    ) -> Option<Vector2<usize>> {
+        let step = match dest {
+            Some(dest) => self.step_towards(m, s, &[dest], flow),
+            None => None,
+        };
+        step.or_else(|| {
+            let destinations: Vec<_> = m.destinations().collect();
+            self.step_towards(m, s, &destinations, flow)
+        })
+    }
+
+    /// The first step from `s` towards whichever of `goals` is nearest, or
+    /// `None` if none can be reached. Flow fields are looked up in `flow`,
+    /// which the other algorithms leave alone.
+    pub fn step_towards(
+        self,
+        m: &Map,
+        s: Vector2<usize>,
+        goals: &[Vector2<usize>],
+        flow: &mut FlowFields,
+    ) -> Option<Vector2<usize>> {
+        match self {
+            Pathfinder::Bfs => bfs(m, s, |t| goals.contains(&t)),
+            Pathfinder::Dijkstra => search(m, s, |t| goals.contains(&t), |t| step_cost(m, t)),
+            Pathfinder::AStar => a_star(m, s, goals),
+            Pathfinder::FlowField => {
+                if goals.contains(&s) {
+                    return Some(s);
+                }
+                let field = flow.get(m, goals);
+                preferred_steps(m, s)
+                    .into_iter()
+                    .filter(|t| m.passable(*t))
+                    .filter_map(|t| field[(t.y, t.x)].map(|d| (t, d)))
+                    .min_by_key(|&(_, d)| d)
+                    .map(|(t, _)| t)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pathfinder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Pathfinder::Bfs => "bfs",
+            Pathfinder::Dijkstra => "dijkstra",
+            Pathfinder::AStar => "astar",
+            Pathfinder::FlowField => "flowfield",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Pathfinder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "bfs" => Ok(Pathfinder::Bfs),
+            "dijkstra" => Ok(Pathfinder::Dijkstra),
+            "astar" => Ok(Pathfinder::AStar),
+            "flowfield" => Ok(Pathfinder::FlowField),
+            _ => Err(format!("unknown pathfinder `{}`", s)),
+        }
+    }
+}
+
+fn bfs<F: Fn(Vector2<usize>) -> bool>(
+    m: &Map,
+    s: Vector2<usize>,
+    is_goal: F,
+) -> Option<Vector2<usize>> {
+    let mut parents = HashMap::new();
+    parents.insert(s, None);
+    let mut q = VecDeque::new();
+    q.push_back(s);
+    while let Some(cur) = q.pop_front() {
+        if is_goal(cur) {
+            return Some(first_move(&parents, cur));
+        }
+        for t in preferred_steps(m, cur) {
+            if m.passable(t) && !parents.contains_key(&t) {
+                parents.insert(t, Some(cur));
+                q.push_back(t);
+            }
+        }
+    }
+    None
+}
+
+fn a_star(m: &Map, s: Vector2<usize>, goals: &[Vector2<usize>]) -> Option<Vector2<usize>> {
+    if goals.contains(&s) {
+        return Some(s);
+    }
+    let h = |t: Vector2<usize>| m.steps_between(t, s) * ROAD_COST;
+    // Costs are of the way from a cell to the nearest goal, so that once `s`
+    // is settled each neighbor on a cheapest path is too: ties on the
+    // estimate go to the lower cost, and every such neighbor costs less.
+    let mut costs = HashMap::new();
+    let mut q = BinaryHeap::new();
+    let mut cells = Vec::new();
+    for &g in goals {
+        q.push((Reverse(h(g)), Reverse(0), Reverse(cells.len())));
+        cells.push(g);
+    }
+    let mut settled = HashSet::new();
+    loop {
+        let (_, Reverse(cost), Reverse(i)) = q.pop()?;
+        let cur = cells[i];
+        if !settled.insert(cur) {
+            continue;
+        }
+        costs.insert(cur, cost);
+        if cur == s {
+            return preferred_steps(m, s)
+                .into_iter()
+                .find(|t| costs.get(t).is_some_and(|c| c + step_cost(m, *t) == cost));
+        }
+        // Stepping from `t` onto `cur` costs what `cur` does.
+        let step = step_cost(m, cur);
+        for t in m.neighbors_4(cur) {
+            if (t == s || m.passable(t)) && !settled.contains(&t) {
+                q.push((
+                    Reverse(cost + step + h(t)),
+                    Reverse(cost + step),
+                    Reverse(cells.len()),
+                ));
+                cells.push(t);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Where `pf_random` wanders from the sample map's spawn point in
+    /// `steps` steps, with the rng seeded by `seed`.
+    fn wander(seed: u64, steps: usize) -> Vec<Vector2<usize>> {
+        let m = Map::new(crate::MAP).unwrap();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut s = m.spawn_points().next().unwrap();
+        (0..steps)
+            .map(|_| {
+                s = pf_random(&m, s, &mut rng);
+                s
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_distance_fields_match_sequential_ones() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let maze = Map::generate(301, 301, &mut rng);
+        assert!(maze.grid.len() >= PARALLEL_MIN_CELLS);
+        assert_eq!(distance_field_par(&maze), distance_field_seq(&maze));
+        // Wide open, so that the frontiers are wide too.
+        let mut open = maze.clone();
+        for wall in maze.cells_of(Square::Wall).filter(|_| rng.gen_bool(0.7)) {
+            open[wall] = Square::Empty;
+        }
+        assert_eq!(distance_field_par(&open), distance_field_seq(&open));
+    }
+
+    #[test]
+    fn random_wandering_repeats_for_the_same_seed() {
+        let walk = wander(7, 30);
+        assert_eq!(walk, wander(7, 30));
+        assert_ne!(walk, wander(8, 30));
+        let m = Map::new(crate::MAP).unwrap();
+        let mut from = m.spawn_points().next().unwrap();
+        for &to in walk.iter() {
+            assert_eq!(m[to], Square::Empty);
+            assert!(m.neighbors_4(from).any(|n| n == to));
+            from = to;
+        }
+    }
+
+    #[test]
+    fn flow_fields_are_kept_until_the_map_changes() {
+        let mut m = Map::new(crate::MAP).unwrap();
+        let goals: Vec<_> = m.destinations().collect();
+        let mut flow = FlowFields::default();
+        let first = flow.get(&m, &goals).clone();
+        flow.get(&m, &goals);
+        flow.get(&m, &goals[..1]);
+        assert_eq!(flow.fields.len(), 2);
+        let spawn = m.spawn_points().next().unwrap();
+        m[spawn + Vector2::new(0, 1)] = Square::Wall;
+        assert_ne!(flow.get(&m, &goals), &first);
+        assert_eq!(flow.fields.len(), 1);
+    }
+
+    #[test]
+    fn routes_keep_off_hazards_while_a_dry_way_is_cheaper() {
+        let m = Map::new("#######\n#^ ~ $#\n# ### #\n#     #\n#######").unwrap();
+        let route = cheapest_route(&m, Vector2::new(1, 1));
+        assert_eq!(route.last(), Some(&Vector2::new(5, 1)));
+        assert!(route.iter().all(|&t| m[t] != Square::Hazard));
+    }
+
+    #[test]
+    fn equally_short_paths_are_settled_down_first_then_right() {
+        let fork = Map::new("#######\n#$ ^ $#\n#######").unwrap();
+        assert_eq!(
+            pf_search(&fork, Vector2::new(3, 1)),
+            Some(Vector2::new(4, 1))
+        );
+        let open = Map::new("#####\n#^  #\n#   #\n#  $#\n#####").unwrap();
+        assert_eq!(
+            pf_search(&open, Vector2::new(1, 1)),
+            Some(Vector2::new(1, 2))
+        );
+        assert_eq!(
+            preferred_steps(&open, Vector2::new(2, 2)),
+            [(2, 3), (3, 2), (1, 2), (2, 1)].map(|(x, y)| Vector2::new(x, y))
+        );
+    }
+
+    #[test]
+    fn string_pulling_cuts_corners_without_crossing_walls() {
+        let m = Map::new("########\n#^     #\n#  ##  #\n#  ##  #\n#     $#\n########").unwrap();
+        let start = Vector2::new(1, 1);
+        let route = cheapest_route(&m, start);
+        let corners = string_pull(&m, start, &route);
+        assert!(corners.len() < route.len());
+        assert_eq!(corners.last(), route.last());
+        assert!(corners.iter().all(|c| route.contains(c)));
+        let mut from = start;
+        for &corner in corners.iter() {
+            assert!(walkable(&m, from, corner));
+            assert!(line(from, corner).iter().all(|&c| m[c] != Square::Wall));
+            from = corner;
+        }
+        // Straight across the block in the middle is no way to go.
+        assert!(!walkable(&m, start, Vector2::new(6, 4)));
+    }
+
+    #[test]
+    fn a_longer_road_beats_a_shorter_rough_way_when_it_costs_less() {
+        let m = Map::new("#######\n#^   $#\n#=====#\n#######").unwrap();
+        let start = Vector2::new(1, 1);
+        let route = cheapest_route(&m, start);
+        let road = [(1, 2), (2, 2), (3, 2), (4, 2), (5, 2), (5, 1)];
+        assert_eq!(route, road.map(|(x, y)| Vector2::new(x, y)));
+        assert_eq!(pf_search(&m, start), Some(Vector2::new(1, 2)));
+        // Two road cells fewer and the rough way is cheaper again.
+        let m = Map::new("#######\n#^   $#\n# === #\n#######").unwrap();
+        assert_eq!(cheapest_route(&m, start).len(), 4);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::widgets::{Block, Borders, Clear, Paragraph, Widget};
+
+/// Samples averaged over by the overlay.
+const WINDOW: usize = 30;
+
+/// Where timings come from, so they can be faked.
+pub trait Clock {
+    /// Time since some fixed point.
+    fn now(&self) -> Duration;
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// The mean of the last `window` samples.
+#[derive(Debug, Clone)]
+pub struct MovingAverage {
+    window: usize,
+    samples: VecDeque<Duration>,
+    total: Duration,
+}
+
+impl MovingAverage {
+    pub fn new(window: usize) -> MovingAverage {
+        MovingAverage {
+            window: window.max(1),
+            samples: VecDeque::new(),
+            total: Duration::ZERO,
+        }
+    }
+
+    pub fn push(&mut self, sample: Duration) {
+        if self.samples.len() == self.window {
+            self.total -= self.samples.pop_front().unwrap();
+        }
+        self.samples.push_back(sample);
+        self.total += sample;
+    }
+
+    /// Zero before the first sample.
+    pub fn average(&self) -> Duration {
+        match self.samples.len() {
+            0 => Duration::ZERO,
+            n => self.total / n as u32,
+        }
+    }
+}
+
+/// Timings for the debug overlay.
+pub struct Perf<C: Clock> {
+    clock: C,
+    pub frame: MovingAverage,
+    pub step: MovingAverage,
+    pub visible: bool,
+}
+
+impl<C: Clock> Perf<C> {
+    pub fn new(clock: C) -> Perf<C> {
+        Perf {
+            clock,
+            frame: MovingAverage::new(WINDOW),
+            step: MovingAverage::new(WINDOW),
+            visible: false,
+        }
+    }
+
+    /// Runs `f`, adding how long it took to `frame`.
+    pub fn time_frame<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        let start = self.clock.now();
+        let result = f();
+        self.frame.push(self.clock.now() - start);
+        result
+    }
+
+    /// Runs `f`, adding how long it took to `step`.
+    pub fn time_step<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        let start = self.clock.now();
+        let result = f();
+        self.step.push(self.clock.now() - start);
+        result
+    }
+}
+
+/// The overlay itself, drawn in the top right corner.
+pub struct PerfOverlay {
+    pub frame: Duration,
+    pub step: Duration,
+    pub enemies: usize,
+    pub field_recomputes: u64,
+    pub squares_drawn: u64,
+}
+
+impl Widget for PerfOverlay {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let lines = [
+            format!("frame: {:.2}ms", self.frame.as_secs_f64() * 1000.0),
+            format!("step:  {:.2}ms", self.step.as_secs_f64() * 1000.0),
+            format!("enemies: {}", self.enemies),
+            format!("fields: {}", self.field_recomputes),
+            format!("squares: {}", self.squares_drawn),
+        ];
+        let width = lines.iter().map(|l| l.len()).max().unwrap_or(0) as u16 + 2;
+        let height = lines.len() as u16 + 2;
+        let rect = Rect::new(
+            area.right().saturating_sub(width),
+            area.y,
+            width.min(area.width),
+            height.min(area.height),
+        );
+        let text = lines.join("\n");
+        Clear.render(rect, buf);
+        Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL).title("perf"))
+            .render(rect, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// A clock that moves on by `tick` every time it is read.
+    struct FakeClock {
+        now: Cell<Duration>,
+        tick: Duration,
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            let now = self.now.get();
+            self.now.set(now + self.tick);
+            now
+        }
+    }
+
+    #[test]
+    fn the_average_only_counts_the_last_window_of_samples() {
+        let mut average = MovingAverage::new(3);
+        assert_eq!(average.average(), Duration::ZERO);
+        average.push(Duration::from_millis(10));
+        assert_eq!(average.average(), Duration::from_millis(10));
+        for ms in [20, 30, 40] {
+            average.push(Duration::from_millis(ms));
+        }
+        assert_eq!(average.average(), Duration::from_millis(30));
+    }
+
+    #[test]
+    fn timings_come_from_the_clock() {
+        let clock = FakeClock {
+            now: Cell::new(Duration::ZERO),
+            tick: Duration::from_millis(4),
+        };
+        let mut perf = Perf::new(clock);
+        assert_eq!(perf.time_step(|| 7), 7);
+        perf.time_frame(|| ());
+        assert_eq!(perf.step.average(), Duration::from_millis(4));
+        assert_eq!(perf.frame.average(), Duration::from_millis(4));
+    }
+}
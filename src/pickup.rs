@@ -0,0 +1,128 @@
+use crate::enemy::EnemyKind;
+use nalgebra::Vector2;
+use rand::Rng;
+
+/// Ticks a pick-up lies around before it disappears.
+pub const PICKUP_TTL: u32 = 10;
+/// Money a `PickUpKind::Money` is worth.
+pub const PICKUP_MONEY: u32 = 15;
+/// Ticks a `PickUpKind::DamageBoost` lasts once collected.
+pub const BOOST_TICKS: u32 = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PickUpKind {
+    Money,
+    /// Doubles the damage of every tower for a while.
+    DamageBoost,
+}
+
+impl PickUpKind {
+    pub const ALL: [PickUpKind; 2] = [PickUpKind::Money, PickUpKind::DamageBoost];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            PickUpKind::Money => "+",
+            PickUpKind::DamageBoost => "!",
+        }
+    }
+}
+
+/// One entry of a loot table: what drops, `None` for nothing at all, and
+/// how much weight it carries against the other entries.
+pub type Loot = (Option<PickUpKind>, u32);
+
+/// What each kind of enemy can leave behind when killed. Splitters drop
+/// little since their children get a roll of their own.
+pub fn loot_table(kind: EnemyKind) -> &'static [Loot] {
+    use PickUpKind::*;
+    match kind {
+        EnemyKind::Basic | EnemyKind::Glider => {
+            &[(None, 18), (Some(Money), 1), (Some(DamageBoost), 1)]
+        }
+        EnemyKind::Breaker => &[(None, 8), (Some(Money), 1), (Some(DamageBoost), 1)],
+        EnemyKind::Regen | EnemyKind::Evasive => {
+            &[(None, 17), (Some(Money), 2), (Some(DamageBoost), 1)]
+        }
+        EnemyKind::Splitter => &[(None, 38), (Some(Money), 1), (Some(DamageBoost), 1)],
+        EnemyKind::Cloaked => &[(None, 16), (Some(Money), 2), (Some(DamageBoost), 2)],
+        EnemyKind::Mason => &[(None, 8), (Some(Money), 2)],
+        EnemyKind::Shield => &[(None, 7), (Some(Money), 1), (Some(DamageBoost), 2)],
+        EnemyKind::Rusher => &[(None, 7), (Some(Money), 2), (Some(DamageBoost), 1)],
+        EnemyKind::Boss => &[(Some(Money), 1), (Some(DamageBoost), 1)],
+    }
+}
+
+/// Picks what a killed `kind` drops, each entry of its loot table coming up
+/// in proportion to its weight.
+pub fn roll_loot<R: Rng>(kind: EnemyKind, rng: &mut R) -> Option<PickUpKind> {
+    let table = loot_table(kind);
+    let mut pick = rng.gen_range(0..table.iter().map(|&(_, weight)| weight).sum::<u32>());
+    for &(drop, weight) in table {
+        if pick < weight {
+            return drop;
+        }
+        pick -= weight;
+    }
+    None
+}
+
+/// Something a killed enemy left behind, collected by moving the cursor
+/// onto it before `ttl` runs out.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PickUp {
+    pub pos: Vector2<usize>,
+    pub kind: PickUpKind,
+    /// Ticks left before it disappears.
+    pub ttl: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn rolls(kind: EnemyKind, seed: u64, n: usize) -> Vec<Option<PickUpKind>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| roll_loot(kind, &mut rng)).collect()
+    }
+
+    #[test]
+    fn the_same_seed_rolls_the_same_drops() {
+        use PickUpKind::*;
+        let drops = rolls(EnemyKind::Cloaked, 11, 12);
+        let expected = [
+            None,
+            None,
+            None,
+            None,
+            Some(Money),
+            None,
+            Some(DamageBoost),
+            Some(DamageBoost),
+            None,
+            None,
+            None,
+            None,
+        ];
+        assert_eq!(drops, expected);
+        assert_eq!(drops, rolls(EnemyKind::Cloaked, 11, 12));
+    }
+
+    #[test]
+    fn drops_come_up_in_proportion_to_their_weights() {
+        let n = 20_000;
+        for &kind in EnemyKind::ALL.iter() {
+            let table = loot_table(kind);
+            let total: u32 = table.iter().map(|&(_, weight)| weight).sum();
+            let drops = rolls(kind, 5, n);
+            for &(drop, weight) in table {
+                let share = drops.iter().filter(|&&d| d == drop).count() as f64 / n as f64;
+                let expected = weight as f64 / total as f64;
+                assert!((share - expected).abs() < 0.02, "{:?} {:?}", kind, drop);
+            }
+            // Whatever isn't in the table never drops.
+            assert!(drops.iter().all(|d| table.iter().any(|&(t, _)| t == *d)));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::game::{GameState, Outcome};
+use crate::highscores::Best;
+use crate::theme::Theme;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Modifier, Style};
+use tui::widgets::Widget;
+
+const TITLE: &str = r#"
+ _____  ___  ___  __  __  ___  _  _    _    _
+|_   _|| __|| _ \|  \/  ||_ _|| \| |  /_\  | |
+  | |  | _| |   /| |\/| | | | | .` | / _ \ | |__
+  |_|  |___||_|_\|_|  |_||___||_|\_|/_/ \_\|____|
+
+   ___  _  _ __   __   _    ___   ___  ___  ___
+  |_ _|| \| |\ \ / /  /_\  |   \ | __|| _ \/ __|
+   | | | .` | \ V /  / _ \ | |) || _| |   /\__ \
+  |___||_|\_|  \_/  /_/ \_\|___/ |___||_|_\|___/
+"#;
+
+const WIN: &str = r#"
+__   __  ___   _   _    __      __ ___  _  _  _
+\ \ / / / _ \ | | | |   \ \    / /|_ _|| \| || |
+ \ V / | (_) || |_| |    \ \/\/ /  | | | .` ||_|
+  |_|   \___/  \___/      \_/\_/  |___||_|\_|(_)
+"#;
+
+const LOSE: &str = r#"
+  ___    _    __  __  ___      ___  __   __ ___  ___
+ / __|  /_\  |  \/  || __|    / _ \ \ \ / /| __|| _ \
+| (_ | / _ \ | |\/| || _|    | (_) | \ V / | _| |   /
+ \___|/_/ \_\|_|  |_||___|    \___/   \_/  |___||_|_\
+"#;
+
+/// Draws `lines` as one block centered horizontally in `area`, starting
+/// `top` rows down. Returns the row after the block.
+fn draw_centered(lines: &[String], top: u16, style: Style, area: Rect, buf: &mut Buffer) -> u16 {
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u16;
+    let x = area.x + area.width.saturating_sub(width) / 2;
+    let mut y = area.y + top;
+    for line in lines {
+        if y >= area.bottom() {
+            break;
+        }
+        buf.set_stringn(x, y, line, area.right().saturating_sub(x) as usize, style);
+        y += 1;
+    }
+    y - area.y
+}
+
+fn art(s: &str) -> Vec<String> {
+    s.trim_matches('\n').lines().map(String::from).collect()
+}
+
+pub struct TitleScreen<'a> {
+    pub theme: &'a Theme,
+    /// The best game so far on the map about to be played.
+    pub best: Option<Best>,
+}
+
+fn describe(best: Best) -> String {
+    format!("score {}, {} waves cleared", best.score, best.wave)
+}
+
+impl Widget for TitleScreen<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let style = self.theme.fg(self.theme.title).add_modifier(Modifier::BOLD);
+        let next = draw_centered(&art(TITLE), 1, style, area, buf);
+        let mut help = vec!["press any key to start, q to quit".to_string()];
+        if let Some(best) = self.best {
+            help.push(String::new());
+            help.push(format!("best on this map: {}", describe(best)));
+        }
+        draw_centered(&help, next + 2, self.theme.fg(self.theme.hud), area, buf);
+    }
+}
+
+/// The win or lose screen, with the final stats of `state`.
+pub struct EndScreen<'a> {
+    pub state: &'a GameState,
+    pub outcome: Outcome,
+    /// The score over every level so far, when playing a campaign.
+    pub campaign_score: Option<u32>,
+    /// The best game on this map, this one included.
+    pub best: Option<Best>,
+    /// Whether this game is the new best.
+    pub new_best: bool,
+}
+
+impl Widget for EndScreen<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (banner, color) = match self.outcome {
+            Outcome::Win => (WIN, self.state.theme.win),
+            Outcome::Lose => (LOSE, self.state.theme.lose),
+        };
+        let theme = &self.state.theme;
+        let style = theme.fg(color).add_modifier(Modifier::BOLD);
+        let next = draw_centered(&art(banner), 1, style, area, buf);
+        let state = self.state;
+        let mut stats = vec![
+            format!("waves cleared: {}/{}", state.wave, state.total_waves()),
+            format!("enemies killed: {}", state.kills),
+            format!("lives left: {}", state.lives),
+            format!("money: {}", state.money),
+        ];
+        if let Some(score) = self.campaign_score {
+            stats.push(format!("campaign score: {}", score));
+        }
+        if self.new_best {
+            stats.push("a new best on this map!".to_string());
+        } else if let Some(best) = self.best {
+            stats.push(format!("best on this map: {}", describe(best)));
+        }
+        stats.push(String::new());
+        stats.push("press q to quit".to_string());
+        draw_centered(&stats, next + 2, theme.fg(theme.hud), area, buf);
+    }
+}
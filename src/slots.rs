@@ -0,0 +1,184 @@
+/// Refers to a value in `Slots`. A handle stays valid until its value is
+/// removed; after that it refers to nothing, even once the slot is reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Clone, Debug)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// A generational slot map: values keep their slot, and so their handle,
+/// when others are removed, and freed slots are reused for new values.
+/// Iteration goes by slot, not by insertion order.
+#[derive(Clone, Debug)]
+pub struct Slots<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    len: usize,
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Slots<T> {
+        Slots {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> Slots<T> {
+    pub fn new() -> Slots<T> {
+        Slots::default()
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.value = Some(value);
+                Handle {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                Handle {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let slot = self.slots.get_mut(handle.index)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation += 1;
+        self.free.push(handle.index);
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        match self.slots.get(handle.index) {
+            Some(slot) if slot.generation == handle.generation => slot.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        match self.slots.get_mut(handle.index) {
+            Some(slot) if slot.generation == handle.generation => slot.value.as_mut(),
+            _ => None,
+        }
+    }
+
+    /// Removes every value for which `keep` returns false, in a single pass
+    /// over the slots. Survivors stay in their slots, so their handles stay
+    /// valid and they keep their order relative to each other.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        for index in 0..self.slots.len() {
+            let slot = &self.slots[index];
+            if let Some(value) = slot.value.as_ref() {
+                if !keep(value) {
+                    let handle = Handle {
+                        index,
+                        generation: slot.generation,
+                    };
+                    self.remove(handle);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.retain(|_| false);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Every value together with its handle.
+    pub fn entries(&self) -> impl Iterator<Item = (Handle, &T)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let handle = Handle {
+                index,
+                generation: slot.generation,
+            };
+            slot.value.as_ref().map(|value| (handle, value))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retain_drops_in_one_pass_and_keeps_order_and_handles() {
+        let mut slots = Slots::new();
+        let handles: Vec<_> = (0..10).map(|i| slots.insert(i)).collect();
+        slots.retain(|&v| v % 2 == 0);
+        assert_eq!(slots.len(), 5);
+        assert_eq!(
+            slots.iter().copied().collect::<Vec<_>>(),
+            vec![0, 2, 4, 6, 8]
+        );
+        for (i, &h) in handles.iter().enumerate() {
+            assert_eq!(slots.get(h).copied(), Some(i).filter(|i| i % 2 == 0));
+        }
+    }
+
+    #[test]
+    fn removed_handles_stay_dead_once_their_slot_is_reused() {
+        let mut slots = Slots::new();
+        let old = slots.insert("old");
+        slots.remove(old);
+        let new = slots.insert("new");
+        assert_eq!(slots.get(old), None);
+        assert_eq!(slots.get(new), Some(&"new"));
+    }
+
+    #[test]
+    fn handles_outlive_the_removal_of_other_values() {
+        let mut slots = Slots::new();
+        let a = slots.insert('a');
+        let b = slots.insert('b');
+        let c = slots.insert('c');
+        assert_eq!(slots.remove(a), Some('a'));
+        assert_eq!(slots.remove(a), None);
+        assert_eq!((slots.get(b), slots.get(c)), (Some(&'b'), Some(&'c')));
+        let d = slots.insert('d');
+        *slots.get_mut(c).unwrap() = 'C';
+        // `d` took over the slot `a` left free, so it comes first.
+        let entries: Vec<_> = slots.entries().collect();
+        assert_eq!(entries, vec![(d, &'d'), (b, &'b'), (c, &'C')]);
+    }
+}
@@ -0,0 +1,89 @@
+//! Renders of the game as plain text, so that a change to how the game is
+//! drawn shows up in the tests as a diff against a render saved earlier in
+//! `snapshots/`. Only the characters are kept, not their colors.
+
+use crate::difficulty::Difficulty;
+use crate::enemy::EnemyKind;
+use crate::game::{GameState, MapCache};
+use crate::map::Map;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::widgets::StatefulWidget;
+
+/// Size, as `(width, height)`, the sample scene is rendered at.
+pub const SAMPLE_SIZE: (u16, u16) = (80, 30);
+
+/// `state` drawn onto a `width` by `height` screen, one line per row with
+/// the spaces at the end of each left off.
+pub fn render_to_string(state: &GameState, width: u16, height: u16) -> String {
+    let area = Rect::new(0, 0, width, height);
+    let mut buf = Buffer::empty(area);
+    state.render(area, &mut buf, &mut MapCache::default());
+    (0..height)
+        .map(|y| {
+            let row: String = (0..width).map(|x| buf.get(x, y).symbol.as_str()).collect();
+            row.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The lines where `got` differs from `expected`, saying what each should
+/// have been. Blank lines at the end don't count.
+pub fn diff(expected: &str, got: &str) -> Vec<String> {
+    let lines = |s: &str| s.trim_end().lines().map(str::to_string).collect::<Vec<_>>();
+    let (expected, got) = (lines(expected), lines(got));
+    (0..expected.len().max(got.len()))
+        .filter_map(|i| {
+            let (want, have) = (expected.get(i), got.get(i));
+            (want != have).then(|| {
+                format!(
+                    "row {}: expected `{}`, got `{}`",
+                    i,
+                    want.map_or("", String::as_str),
+                    have.map_or("", String::as_str)
+                )
+            })
+        })
+        .collect()
+}
+
+/// The game on `map` before it has run a single tick, with two basic
+/// enemies just spawned at its first spawn point.
+pub fn sample_scene(map: Map) -> GameState {
+    let mut state = GameState::new(map, Difficulty::Normal).with_seed(0);
+    let spawn = state.map.spawn_points().next();
+    if let Some(spawn) = spawn {
+        for _ in 0..2 {
+            let pos = state.free_spawn_cell(spawn);
+            state.spawn(EnemyKind::Basic, pos);
+        }
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_map_matches_snapshot() {
+        let (width, height) = SAMPLE_SIZE;
+        let state = sample_scene(Map::new(crate::MAP).unwrap());
+        let got = render_to_string(&state, width, height);
+        let diff = diff(include_str!("../snapshots/sample.txt"), &got);
+        assert!(diff.is_empty(), "{}\n\n{}", diff.join("\n"), got);
+    }
+
+    #[test]
+    fn diff_names_the_rows_that_changed() {
+        assert!(diff("ab\ncd\n\n", "ab\ncd").is_empty());
+        assert_eq!(
+            diff("ab\ncd\nef", "ab\nxd"),
+            vec![
+                "row 1: expected `cd`, got `xd`".to_string(),
+                "row 2: expected `ef`, got ``".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::game::GameState;
+use crate::wave::Phase;
+use std::time::Duration;
+
+/// Speeds the player can pick between, as multiples of the normal speed.
+pub const SPEEDS: [f64; 4] = [0.5, 1.0, 2.0, 4.0];
+/// Lives left at or below which the game slows down, unless told otherwise.
+pub const SLOW_MOTION_LIVES: u32 = 1;
+
+/// How fast the game runs. The player picks a speed; on top of that the game
+/// drops to half of it while a wave is being fought with only a few lives
+/// left, and back once the wave is over. Slow motion never touches the
+/// player's pick, so the two can't undo each other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Speed {
+    /// Index into `SPEEDS` of the player's pick.
+    pub manual: usize,
+    /// Lives left at or below which slow motion kicks in; zero turns it off.
+    pub slow_motion_lives: u32,
+}
+
+impl Speed {
+    pub fn new(slow_motion_lives: u32) -> Speed {
+        Speed {
+            manual: 1,
+            slow_motion_lives,
+        }
+    }
+
+    pub fn faster(&mut self) {
+        self.manual = (self.manual + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.manual = self.manual.saturating_sub(1);
+    }
+
+    pub fn manual_speed(&self) -> f64 {
+        SPEEDS[self.manual]
+    }
+
+    pub fn slow_motion(&self, state: &GameState) -> bool {
+        self.slow_motion_lives > 0
+            && state.lives <= self.slow_motion_lives
+            && state.phase == Phase::Combat
+            && state.outcome().is_none()
+    }
+
+    /// The speed the game actually runs at, given whether slow motion is on.
+    pub fn effective(&self, slow_motion: bool) -> f64 {
+        if slow_motion {
+            self.manual_speed() / 2.0
+        } else {
+            self.manual_speed()
+        }
+    }
+
+    /// The time between ticks, for a game whose normal tick rate is `base`.
+    pub fn tick_rate(&self, base: Duration, slow_motion: bool) -> Duration {
+        base.div_f64(self.effective(slow_motion))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+    use crate::map::Map;
+
+    #[test]
+    fn slow_motion_halves_whatever_speed_the_player_picked() {
+        let mut speed = Speed::new(SLOW_MOTION_LIVES);
+        assert_eq!(speed.effective(false), 1.0);
+        assert_eq!(speed.effective(true), 0.5);
+        speed.faster();
+        speed.faster();
+        assert_eq!(speed.effective(true), 2.0);
+        assert_eq!(speed.manual_speed(), 4.0);
+        speed.faster();
+        assert_eq!(speed.manual_speed(), 4.0);
+        let base = Duration::from_millis(100);
+        assert_eq!(speed.tick_rate(base, true), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn slow_motion_kicks_in_with_few_lives_during_combat_only() {
+        let mut state = GameState::new(Map::new(crate::MAP).unwrap(), Difficulty::Normal);
+        let speed = Speed::new(2);
+        state.phase = Phase::Combat;
+        state.lives = 3;
+        assert!(!speed.slow_motion(&state));
+        state.lives = 2;
+        assert!(speed.slow_motion(&state));
+        state.phase = Phase::Build { ticks_left: 1 };
+        assert!(!speed.slow_motion(&state));
+        state.phase = Phase::Combat;
+        assert!(!Speed::new(0).slow_motion(&state));
+    }
+}
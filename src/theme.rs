@@ -0,0 +1,285 @@
+use crate::error::GameError;
+use std::fs;
+use std::path::Path;
+use tui::style::{Color, Modifier, Style};
+
+/// The colors the game is drawn in, by what they are used for.
+///
+/// A theme file is a list of `role = color` lines in the same format as an
+/// economy file. A color is either a name like `red` or `lightblue`, `reset`
+/// for the terminal's own color, or `#rrggbb`. `empty_glyph` takes a single
+/// character instead of a color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Empty ground, when it is drawn as anything but blank.
+    pub empty: Color,
+    /// What empty ground is drawn as.
+    pub empty_glyph: char,
+    pub wall: Color,
+    pub spawn_point: Color,
+    pub destination: Color,
+    /// The color destinations pulse to when animated.
+    pub destination_pulse: Color,
+    pub hazard: Color,
+    pub road: Color,
+    pub tower: Color,
+    /// A tower that has just fired.
+    pub muzzle_flash: Color,
+    pub enemy: Color,
+    pub projectile: Color,
+    pub pickup: Color,
+    /// Fields the player laid down.
+    pub field: Color,
+    /// The cells enemies have just left.
+    pub trail: Color,
+    /// Background of cells one tower can shoot at, when ranges are shown.
+    pub range_low: Color,
+    /// Background of cells two towers can shoot at.
+    pub range_mid: Color,
+    /// Background of cells three or more towers can shoot at.
+    pub range_high: Color,
+    /// Background of cells one enemy is headed through, in god view.
+    pub traffic_low: Color,
+    /// Background of cells two enemies are headed through.
+    pub traffic_mid: Color,
+    /// Background of cells three or more enemies are headed through.
+    pub traffic_high: Color,
+    /// Background of the wall line being dragged out.
+    pub drag: Color,
+    /// Background of the cell under the mouse while placing a tower.
+    pub hover: Color,
+    pub chokepoint: Color,
+    /// The tower preview while placing, when it can be built.
+    pub affordable: Color,
+    /// The tower preview while placing, when it can't be built.
+    pub unaffordable: Color,
+    pub progress: Color,
+    pub hud: Color,
+    pub title: Color,
+    pub win: Color,
+    pub lose: Color,
+    /// Whether everything is drawn bold on black, with glyphs that tell
+    /// squares apart without relying on color.
+    pub high_contrast: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            empty: Color::DarkGray,
+            empty_glyph: ' ',
+            wall: Color::Gray,
+            spawn_point: Color::Red,
+            destination: Color::Yellow,
+            destination_pulse: Color::LightYellow,
+            hazard: Color::LightRed,
+            road: Color::DarkGray,
+            tower: Color::Cyan,
+            muzzle_flash: Color::LightYellow,
+            enemy: Color::Reset,
+            projectile: Color::Reset,
+            pickup: Color::LightGreen,
+            field: Color::LightBlue,
+            trail: Color::DarkGray,
+            range_low: Color::Blue,
+            range_mid: Color::LightBlue,
+            range_high: Color::LightCyan,
+            traffic_low: Color::DarkGray,
+            traffic_mid: Color::Magenta,
+            traffic_high: Color::LightMagenta,
+            drag: Color::DarkGray,
+            hover: Color::DarkGray,
+            chokepoint: Color::Magenta,
+            affordable: Color::Green,
+            unaffordable: Color::Red,
+            progress: Color::Green,
+            hud: Color::Reset,
+            title: Color::Green,
+            win: Color::Yellow,
+            lose: Color::Red,
+            high_contrast: false,
+        }
+    }
+}
+
+impl Theme {
+    /// Bold white on black for everything drawn in the foreground, for
+    /// players who struggle to make the usual colors out. Backgrounds keep
+    /// their colors, since they never carry text of their own.
+    pub fn high_contrast() -> Theme {
+        let white = Color::White;
+        Theme {
+            empty: white,
+            wall: white,
+            spawn_point: white,
+            destination: white,
+            destination_pulse: white,
+            hazard: white,
+            road: white,
+            tower: white,
+            muzzle_flash: white,
+            enemy: white,
+            projectile: white,
+            pickup: white,
+            field: white,
+            trail: white,
+            chokepoint: white,
+            affordable: white,
+            unaffordable: white,
+            progress: white,
+            hud: white,
+            title: white,
+            win: white,
+            lose: white,
+            high_contrast: true,
+            ..Theme::default()
+        }
+    }
+
+    /// The style to draw something in `color` with. Everything drawn in a
+    /// role's color should go through here so that high contrast applies.
+    pub fn fg(&self, color: Color) -> Style {
+        let style = Style::default().fg(color);
+        if self.high_contrast {
+            style.bg(Color::Black).add_modifier(Modifier::BOLD)
+        } else {
+            style
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Theme, GameError> {
+        Theme::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(desc: &str) -> Result<Theme, GameError> {
+        let bad = GameError::Config;
+        let mut theme = Theme::default();
+        for (n, line) in desc.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(bad(format!("line {}: expected `role = color`", n + 1))),
+            };
+            if key == "empty_glyph" {
+                let mut chars = value.chars();
+                theme.empty_glyph = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => {
+                        return Err(bad(format!(
+                            "line {}: `{}` is not a single character",
+                            n + 1,
+                            value
+                        )))
+                    }
+                };
+                continue;
+            }
+            let color = parse_color(value)
+                .ok_or_else(|| bad(format!("line {}: `{}` is not a color", n + 1, value)))?;
+            let role = match key {
+                "empty" => &mut theme.empty,
+                "wall" => &mut theme.wall,
+                "spawn_point" => &mut theme.spawn_point,
+                "destination" => &mut theme.destination,
+                "destination_pulse" => &mut theme.destination_pulse,
+                "hazard" => &mut theme.hazard,
+                "road" => &mut theme.road,
+                "tower" => &mut theme.tower,
+                "muzzle_flash" => &mut theme.muzzle_flash,
+                "enemy" => &mut theme.enemy,
+                "projectile" => &mut theme.projectile,
+                "pickup" => &mut theme.pickup,
+                "field" => &mut theme.field,
+                "trail" => &mut theme.trail,
+                "range_low" => &mut theme.range_low,
+                "range_mid" => &mut theme.range_mid,
+                "range_high" => &mut theme.range_high,
+                "traffic_low" => &mut theme.traffic_low,
+                "traffic_mid" => &mut theme.traffic_mid,
+                "traffic_high" => &mut theme.traffic_high,
+                "drag" => &mut theme.drag,
+                "hover" => &mut theme.hover,
+                "chokepoint" => &mut theme.chokepoint,
+                "affordable" => &mut theme.affordable,
+                "unaffordable" => &mut theme.unaffordable,
+                "progress" => &mut theme.progress,
+                "hud" => &mut theme.hud,
+                "title" => &mut theme.title,
+                "win" => &mut theme.win,
+                "lose" => &mut theme.lose,
+                _ => return Err(bad(format!("line {}: unknown role `{}`", n + 1, key))),
+            };
+            *role = color;
+        }
+        Ok(theme)
+    }
+}
+
+fn parse_color(s: &str) -> Option<Color> {
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    let color = match s.to_ascii_lowercase().as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "darkgray" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Square;
+
+    #[test]
+    fn a_custom_theme_recolors_the_walls() {
+        let theme = Theme::parse("# brick\nwall = #b22222\nenemy = LightRed").unwrap();
+        assert_eq!(
+            Square::Wall.style(None, &theme).fg,
+            Some(Color::Rgb(0xb2, 0x22, 0x22))
+        );
+        assert_eq!(theme.enemy, Color::LightRed);
+        assert_eq!(theme.tower, Theme::default().tower);
+        assert_ne!(
+            Square::Wall.style(None, &Theme::default()),
+            Square::Wall.style(None, &theme)
+        );
+    }
+
+    #[test]
+    fn high_contrast_draws_walls_and_enemies_bold_white_on_black() {
+        let theme = Theme::high_contrast();
+        let expected = Style::default()
+            .fg(Color::White)
+            .bg(Color::Black)
+            .add_modifier(Modifier::BOLD);
+        assert_eq!(Square::Wall.style(None, &theme), expected);
+        assert_eq!(theme.fg(theme.enemy), expected);
+        let usual = Theme::default();
+        assert!(!usual.fg(usual.enemy).add_modifier.contains(Modifier::BOLD));
+        assert_eq!(usual.fg(usual.wall).bg, None);
+    }
+}
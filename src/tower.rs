@@ -0,0 +1,421 @@
+use crate::direction::Direction;
+use crate::slots::Handle;
+use nalgebra::Vector2;
+use std::fmt;
+use std::str::FromStr;
+
+/// Cells a projectile covers per tick.
+pub const PROJECTILE_SPEED: usize = 2;
+/// Ticks a tower is drawn flashing for after it fires.
+pub const FIRE_ANIM_TICKS: u8 = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TowerKind {
+    Basic,
+    /// Weak shots that shove the enemy they hit back along its path.
+    Knockback,
+    /// A slow, hard-hitting gun taking up two by two cells.
+    Heavy,
+    /// Never fires, but earns a little money every tick.
+    Economy,
+    /// Never fires, but lets the other towers see cloaked enemies in its
+    /// range.
+    Detector,
+}
+
+impl TowerKind {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TowerKind::Basic => "T",
+            TowerKind::Knockback => "K",
+            TowerKind::Heavy => "H",
+            TowerKind::Economy => "E",
+            TowerKind::Detector => "D",
+        }
+    }
+
+    /// Width and height of the cells the tower covers, with `Tower::pos` in
+    /// the top left corner.
+    pub fn footprint(self) -> (usize, usize) {
+        match self {
+            TowerKind::Heavy => (2, 2),
+            _ => (1, 1),
+        }
+    }
+
+    pub fn cost(self) -> u32 {
+        match self {
+            TowerKind::Basic => 20,
+            TowerKind::Knockback => 35,
+            TowerKind::Heavy => 60,
+            TowerKind::Economy => 80,
+            TowerKind::Detector => 30,
+        }
+    }
+
+    pub fn range(self) -> usize {
+        match self {
+            TowerKind::Basic => 3,
+            TowerKind::Knockback => 2,
+            TowerKind::Heavy => 4,
+            TowerKind::Economy => 0,
+            TowerKind::Detector => 3,
+        }
+    }
+
+    /// How the damage of this kind's shots drops off with distance.
+    pub fn falloff(self) -> Falloff {
+        match self {
+            TowerKind::Knockback => Falloff::None,
+            _ => Falloff::Linear { edge: 0.5 },
+        }
+    }
+
+    /// Damage of a shot at point blank range.
+    pub fn damage(self) -> u32 {
+        match self {
+            TowerKind::Basic => 3,
+            TowerKind::Knockback => 1,
+            TowerKind::Heavy => 10,
+            TowerKind::Economy | TowerKind::Detector => 0,
+        }
+    }
+
+    /// Ticks spent idle after each shot.
+    pub fn reload_ticks(self) -> u32 {
+        match self {
+            TowerKind::Basic => 0,
+            TowerKind::Knockback => 2,
+            TowerKind::Heavy => 3,
+            TowerKind::Economy | TowerKind::Detector => 0,
+        }
+    }
+
+    /// Damage dealt per tick at point blank range by a tower firing without
+    /// a break, averaged over its reloads.
+    pub fn dps(self) -> f32 {
+        let shot_ticks = self.reload_ticks() + 1;
+        let (shots, ticks) = match self.magazine() {
+            Some(n) => (n, n * shot_ticks + self.magazine_reload_ticks()),
+            None => (1, shot_ticks),
+        };
+        (self.damage() * shots) as f32 / ticks as f32
+    }
+
+    /// Shots the tower fires before it has to stop and reload, or `None`
+    /// if it never runs out.
+    pub fn magazine(self) -> Option<u32> {
+        match self {
+            TowerKind::Heavy => Some(4),
+            _ => None,
+        }
+    }
+
+    /// Ticks it takes to refill an empty magazine.
+    pub fn magazine_reload_ticks(self) -> u32 {
+        match self {
+            TowerKind::Heavy => 8,
+            _ => 0,
+        }
+    }
+
+    pub fn max_hp(self) -> u32 {
+        match self {
+            TowerKind::Heavy => 60,
+            _ => 30,
+        }
+    }
+
+    /// Money earned per tick.
+    pub fn income(self) -> u32 {
+        match self {
+            TowerKind::Economy => 1,
+            _ => 0,
+        }
+    }
+
+    pub fn attacks(self) -> bool {
+        self.damage() > 0
+    }
+
+    /// Whether cloaked enemies in this kind's range can be shot at.
+    pub fn reveals(self) -> bool {
+        self == TowerKind::Detector
+    }
+
+    /// Cells an enemy hit by this tower is pushed back.
+    pub fn knockback(self) -> usize {
+        match self {
+            TowerKind::Knockback => 2,
+            _ => 0,
+        }
+    }
+}
+
+impl FromStr for TowerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "basic" => Ok(TowerKind::Basic),
+            "knockback" => Ok(TowerKind::Knockback),
+            "heavy" => Ok(TowerKind::Heavy),
+            "economy" => Ok(TowerKind::Economy),
+            "detector" => Ok(TowerKind::Detector),
+            _ => Err(format!("unknown tower `{}`", s)),
+        }
+    }
+}
+
+/// How a tower's damage drops off between point blank and the edge of its
+/// range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Falloff {
+    /// Full damage all the way out.
+    None,
+    /// Dropping in a straight line from full damage on adjacent cells to
+    /// `edge` times that at the edge of the range.
+    Linear { edge: f64 },
+}
+
+impl Falloff {
+    /// What damage is multiplied by at `distance` from a tower with `range`.
+    pub fn factor(self, distance: f64, range: f64) -> f64 {
+        match self {
+            Falloff::None => 1.0,
+            Falloff::Linear { edge } => {
+                if range <= 1.0 {
+                    return 1.0;
+                }
+                let t = ((distance - 1.0) / (range - 1.0)).clamp(0.0, 1.0);
+                1.0 - t * (1.0 - edge)
+            }
+        }
+    }
+}
+
+/// The cells covered by a footprint of `(w, h)` with its top left corner on
+/// `pos`, row by row. Some may lie off the map, and any that would lie
+/// past `usize::MAX` are left out.
+pub fn footprint_cells(pos: Vector2<usize>, (w, h): (usize, usize)) -> Vec<Vector2<usize>> {
+    (0..h)
+        .flat_map(|dy| {
+            (0..w).filter_map(move |dx| {
+                Some(Vector2::new(pos.x.checked_add(dx)?, pos.y.checked_add(dy)?))
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tower {
+    pub pos: Vector2<usize>,
+    pub kind: TowerKind,
+    pub cooldown: u32,
+    /// Worn down by rushers; the tower falls at zero.
+    pub hp: u32,
+    pub falloff: Falloff,
+    /// Shots left in the magazine, for kinds that have one.
+    pub ammo: Option<u32>,
+    /// Ticks left before an emptied magazine is full again.
+    pub reloading: u32,
+    /// Ticks left of the flash drawn when the tower fires.
+    pub fire_anim: u8,
+    /// The way the tower last turned to shoot.
+    pub facing: Direction,
+    /// Whether the tower keeps shooting at the enemy it locked onto until
+    /// that enemy dies or gets out of its reach, instead of always going
+    /// for the nearest.
+    pub target_lock: bool,
+    /// The enemy the tower is locked onto, if any.
+    pub locked: Option<Handle>,
+}
+
+impl Tower {
+    pub fn new(kind: TowerKind, pos: Vector2<usize>) -> Self {
+        Tower {
+            pos,
+            kind,
+            cooldown: 0,
+            hp: kind.max_hp(),
+            falloff: kind.falloff(),
+            ammo: kind.magazine(),
+            reloading: 0,
+            fire_anim: 0,
+            facing: Direction::North,
+            target_lock: false,
+            locked: None,
+        }
+    }
+
+    pub fn cells(&self) -> Vec<Vector2<usize>> {
+        footprint_cells(self.pos, self.kind.footprint())
+    }
+
+    /// The square of the distance from `p` to the nearest cell the tower
+    /// covers, so that a big tower reaches as far on every side.
+    pub fn distance_squared(&self, p: Vector2<usize>) -> usize {
+        let (w, h) = self.kind.footprint();
+        let gap = |p: usize, lo: usize, len: usize| {
+            let hi = lo + len - 1;
+            p.saturating_sub(hi).max(lo.saturating_sub(p))
+        };
+        let (dx, dy) = (gap(p.x, self.pos.x, w), gap(p.y, self.pos.y, h));
+        dx * dx + dy * dy
+    }
+
+    pub fn in_range(&self, p: Vector2<usize>) -> bool {
+        self.distance_squared(p) <= self.kind.range().pow(2)
+    }
+
+    /// Damage a shot at `p` does, never less than one.
+    pub fn damage_at(&self, p: Vector2<usize>) -> u32 {
+        let distance = (self.distance_squared(p) as f64).sqrt();
+        let factor = self.falloff.factor(distance, self.kind.range() as f64);
+        ((self.kind.damage() as f64 * factor).round() as u32).max(1)
+    }
+
+    /// The cells of a `width` by `height` map the tower can shoot at.
+    pub fn cells_in_range(&self, width: usize, height: usize) -> Vec<Vector2<usize>> {
+        let r = self.kind.range();
+        let (w, h) = self.kind.footprint();
+        let (x0, y0) = (self.pos.x.saturating_sub(r), self.pos.y.saturating_sub(r));
+        let (x1, y1) = (
+            (self.pos.x + w - 1 + r).min(width - 1),
+            (self.pos.y + h - 1 + r).min(height - 1),
+        );
+        (y0..=y1)
+            .flat_map(|y| (x0..=x1).map(move |x| Vector2::new(x, y)))
+            .filter(|&p| self.in_range(p))
+            .collect()
+    }
+}
+
+/// Why a tower or wall could not be built.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaceError {
+    NotEmpty,
+    Occupied,
+    TooExpensive,
+    BlocksPath,
+    /// Only under the no-build-on-path rule.
+    OnPath,
+    OffMap,
+    /// The game has been lost and is only being watched.
+    GameOver,
+    /// As many towers stand as the game allows.
+    TowerCap,
+}
+
+impl fmt::Display for PlaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            PlaceError::NotEmpty => "you can only build on empty ground",
+            PlaceError::Occupied => "that cell is already taken",
+            PlaceError::TooExpensive => "not enough money",
+            PlaceError::BlocksPath => "that would cut the enemies off from every destination",
+            PlaceError::OnPath => "towers can't be built on the enemies' path",
+            PlaceError::OffMap => "that doesn't fit on the map",
+            PlaceError::GameOver => "the game is over",
+            PlaceError::TowerCap => "as many towers are up as this game allows",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// A shot homing in on the enemy `target`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Projectile {
+    pub pos: Vector2<usize>,
+    /// Where the projectile was before the last tick moved it.
+    pub prev_pos: Vector2<usize>,
+    pub target: Handle,
+    pub damage: u32,
+    pub knockback: usize,
+}
+
+impl Projectile {
+    /// Moves one cell towards `goal`, diagonals included. Projectiles fly over
+    /// walls, so no map is needed.
+    pub fn step_towards(&mut self, goal: Vector2<usize>) {
+        self.pos.x = step(self.pos.x, goal.x);
+        self.pos.y = step(self.pos.y, goal.y);
+    }
+
+    /// The cell to draw the projectile on when a frame falls `alpha` of the
+    /// way, from 0 to 1, between the last tick and the next.
+    pub fn render_pos(&self, alpha: f32) -> Vector2<usize> {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let lerp = |from: usize, to: usize| {
+            (from as f32 + (to as f32 - from as f32) * alpha).round() as usize
+        };
+        Vector2::new(
+            lerp(self.prev_pos.x, self.pos.x),
+            lerp(self.prev_pos.y, self.pos.y),
+        )
+    }
+}
+
+fn step(from: usize, to: usize) -> usize {
+    match from.cmp(&to) {
+        std::cmp::Ordering::Less => from + 1,
+        std::cmp::Ordering::Equal => from,
+        std::cmp::Ordering::Greater => from - 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slots::Slots;
+
+    #[test]
+    fn projectiles_are_drawn_between_their_last_cell_and_this_one() {
+        let projectile = Projectile {
+            pos: Vector2::new(5, 2),
+            prev_pos: Vector2::new(1, 2),
+            target: Slots::new().insert(()),
+            damage: 1,
+            knockback: 0,
+        };
+        assert_eq!(projectile.render_pos(0.0), projectile.prev_pos);
+        assert_eq!(projectile.render_pos(1.0), projectile.pos);
+        assert_eq!(projectile.render_pos(0.5), Vector2::new(3, 2));
+        assert_eq!(projectile.render_pos(2.0), projectile.pos);
+    }
+
+    #[test]
+    fn damage_falls_off_towards_the_edge_of_range_per_the_curve() {
+        let mut tower = Tower::new(TowerKind::Heavy, Vector2::new(0, 0));
+        tower.falloff = Falloff::Linear { edge: 0.5 };
+        let range = TowerKind::Heavy.range();
+        let full = TowerKind::Heavy.damage();
+        assert_eq!(tower.damage_at(Vector2::new(1, 0)), full);
+        // Measured from the right-hand column of the footprint.
+        assert_eq!(tower.damage_at(Vector2::new(range + 1, 0)), full / 2);
+        tower.falloff = Falloff::None;
+        assert_eq!(tower.damage_at(Vector2::new(range + 1, 0)), full);
+        assert_eq!(Falloff::Linear { edge: 0.25 }.factor(2.5, 4.0), 0.625);
+    }
+
+    #[test]
+    fn a_big_tower_reaches_as_far_on_every_side_of_its_footprint() {
+        let tower = Tower::new(TowerKind::Heavy, Vector2::new(5, 5));
+        assert_eq!(tower.kind.footprint(), (2, 2));
+        let r = tower.kind.range();
+        // Reflecting through the middle of the footprint.
+        let mirror = |p: Vector2<usize>| Vector2::new(11 - p.x, 11 - p.y);
+        let covered = tower.cells_in_range(20, 20);
+        assert!(!covered.is_empty());
+        for &p in &covered {
+            assert!(covered.contains(&mirror(p)), "{:?}", p);
+            assert_eq!(tower.damage_at(p), tower.damage_at(mirror(p)));
+        }
+        for &(x, y) in &[(5 - r, 5), (6 + r, 6), (5, 5 - r), (6, 6 + r)] {
+            assert!(tower.in_range(Vector2::new(x, y)), "{:?}", (x, y));
+        }
+        for &(x, y) in &[(4 - r, 5), (7 + r, 6), (5, 4 - r), (6, 7 + r)] {
+            assert!(!tower.in_range(Vector2::new(x, y)), "{:?}", (x, y));
+        }
+    }
+}
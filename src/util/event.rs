@@ -1,26 +1,35 @@
-use std::io;
+use std::io::{self, Write};
 use std::sync::mpsc;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
     Arc,
 };
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use termion::event::Key;
+use termion::event::{self as term, Key, MouseEvent};
 use termion::input::TermRead;
 
 pub enum Event<I> {
     Input(I),
+    Mouse(MouseEvent),
     Tick,
+    /// Time to redraw; fires every `Config::frame_rate`, independently of ticks.
+    Frame,
+    /// The mouse moved to `(x, y)` with no button held, counted from 1.
+    /// Only sent to terminals wrapped in a `MotionTerminal`.
+    Hover(u16, u16),
 }
 
-/// A small event handler that wrap termion input and tick events. Each event
+/// A small event handler that wrap termion input, mouse and tick events. Each event
 /// type is handled in its own thread and returned to a common `Receiver`
 pub struct Events {
     rx: mpsc::Receiver<Event<Key>>,
     input_handle: thread::JoinHandle<()>,
     ignore_exit_key: Arc<AtomicBool>,
+    /// The tick rate in microseconds, read by the timer thread before every
+    /// pulse so it can be changed while running.
+    tick_rate: Arc<AtomicU64>,
     tick_handle: thread::JoinHandle<()>,
 }
 
@@ -28,6 +37,7 @@ pub struct Events {
 pub struct Config {
     pub exit_key: Key,
     pub tick_rate: Duration,
+    pub frame_rate: Duration,
 }
 
 impl Default for Config {
@@ -35,6 +45,7 @@ impl Default for Config {
         Config {
             exit_key: Key::Char('q'),
             tick_rate: Duration::from_millis(250),
+            frame_rate: Duration::from_millis(50),
         }
     }
 }
@@ -52,30 +63,56 @@ impl Events {
             let ignore_exit_key = ignore_exit_key.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if let Err(err) = tx.send(Event::Input(key)) {
-                            eprintln!("{}", err);
-                            return;
-                        }
-                        if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
-                            return;
-                        }
+                for evt in stdin.events().flatten() {
+                    let is_exit_key = evt == term::Event::Key(config.exit_key);
+                    let event = match evt {
+                        term::Event::Key(key) => Event::Input(key),
+                        term::Event::Mouse(mouse) => Event::Mouse(mouse),
+                        term::Event::Unsupported(bytes) => match hover(&bytes) {
+                            Some((x, y)) => Event::Hover(x, y),
+                            None => continue,
+                        },
+                    };
+                    if let Err(err) = tx.send(event) {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                    if !ignore_exit_key.load(Ordering::Relaxed) && is_exit_key {
+                        return;
                     }
                 }
             })
         };
+        let tick_rate = Arc::new(AtomicU64::new(config.tick_rate.as_micros() as u64));
         let tick_handle = {
-            thread::spawn(move || loop {
-                if tx.send(Event::Tick).is_err() {
-                    break;
+            let tick_rate = tick_rate.clone();
+            thread::spawn(move || {
+                let start = Instant::now();
+                let mut schedule = Schedule::new(config.tick_rate, config.frame_rate);
+                loop {
+                    let rate = Duration::from_micros(tick_rate.load(Ordering::Relaxed));
+                    schedule.set_tick_rate(rate);
+                    let (at, pulse) = match schedule.next() {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    if let Some(wait) = at.checked_sub(start.elapsed()) {
+                        thread::sleep(wait);
+                    }
+                    let event = match pulse {
+                        Pulse::Tick => Event::Tick,
+                        Pulse::Frame => Event::Frame,
+                    };
+                    if tx.send(event).is_err() {
+                        break;
+                    }
                 }
-                thread::sleep(config.tick_rate);
             })
         };
         Events {
             rx,
             ignore_exit_key,
+            tick_rate,
             input_handle,
             tick_handle,
         }
@@ -85,6 +122,13 @@ impl Events {
         self.rx.recv()
     }
 
+    /// Changes the time between ticks from the next tick on. Must be
+    /// non-zero.
+    pub fn set_tick_rate(&self, rate: Duration) {
+        self.tick_rate
+            .store(rate.as_micros() as u64, Ordering::Relaxed);
+    }
+
     pub fn disable_exit_key(&mut self) {
         self.ignore_exit_key.store(true, Ordering::Relaxed);
     }
@@ -92,4 +136,154 @@ impl Events {
     pub fn enable_exit_key(&mut self) {
         self.ignore_exit_key.store(false, Ordering::Relaxed);
     }
-}
\ No newline at end of file
+}
+
+/// The position of a mouse move with no button held, which termion doesn't
+/// parse itself: `ESC [ < 35 ; x ; y M` in SGR encoding, or `ESC [ 67 ; x ;
+/// y M` in rxvt's.
+fn hover(bytes: &[u8]) -> Option<(u16, u16)> {
+    let seq = std::str::from_utf8(bytes).ok()?.strip_prefix("\x1b[")?;
+    let seq = seq.strip_suffix('M')?;
+    let (code, seq) = match seq.strip_prefix('<') {
+        Some(sgr) => (35, sgr),
+        None => (67, seq),
+    };
+    let nums: Vec<u16> = seq
+        .split(';')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    match nums.as_slice() {
+        [cb, x, y] if *cb == code => Some((*x, *y)),
+        _ => None,
+    }
+}
+
+/// Asks the terminal to report every mouse move, not just drags, for as
+/// long as it lives. Goes around a `MouseTerminal`, since turning on its
+/// mouse reporting afterwards would switch this back off.
+pub struct MotionTerminal<W: Write> {
+    term: W,
+}
+
+impl<W: Write> MotionTerminal<W> {
+    pub fn new(mut term: W) -> io::Result<MotionTerminal<W>> {
+        term.write_all(b"\x1b[?1003h")?;
+        Ok(MotionTerminal { term })
+    }
+}
+
+impl<W: Write> Drop for MotionTerminal<W> {
+    fn drop(&mut self) {
+        let _ = self.term.write_all(b"\x1b[?1003l");
+        let _ = self.term.flush();
+    }
+}
+
+impl<W: Write> Write for MotionTerminal<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.term.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.term.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pulse {
+    Tick,
+    Frame,
+}
+
+/// Interleaves ticks and frames on one clock. Yields each pulse together with
+/// the time, counted from the start, at which it is due; both fire right away
+/// and a tick goes first when they are due together. Both rates must be
+/// non-zero.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    tick_rate: Duration,
+    frame_rate: Duration,
+    next_tick: Duration,
+    next_frame: Duration,
+}
+
+impl Schedule {
+    pub fn new(tick_rate: Duration, frame_rate: Duration) -> Schedule {
+        Schedule {
+            tick_rate,
+            frame_rate,
+            next_tick: Duration::ZERO,
+            next_frame: Duration::ZERO,
+        }
+    }
+}
+
+impl Schedule {
+    /// Spaces ticks `tick_rate` apart from now on, the next one included:
+    /// it becomes due `tick_rate` after the last.
+    pub fn set_tick_rate(&mut self, tick_rate: Duration) {
+        if tick_rate == self.tick_rate {
+            return;
+        }
+        self.next_tick = self.next_tick.saturating_sub(self.tick_rate) + tick_rate;
+        self.tick_rate = tick_rate;
+    }
+}
+
+impl Iterator for Schedule {
+    type Item = (Duration, Pulse);
+
+    fn next(&mut self) -> Option<(Duration, Pulse)> {
+        if self.next_tick <= self.next_frame {
+            let at = self.next_tick;
+            self.next_tick += self.tick_rate;
+            Some((at, Pulse::Tick))
+        } else {
+            let at = self.next_frame;
+            self.next_frame += self.frame_rate;
+            Some((at, Pulse::Frame))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How many ticks and frames `schedule` has due before `window` is up.
+    fn count(schedule: Schedule, window: Duration) -> (usize, usize) {
+        let pulses: Vec<_> = schedule
+            .take_while(|&(at, _)| at < window)
+            .map(|(_, pulse)| pulse)
+            .collect();
+        let ticks = pulses.iter().filter(|&&p| p == Pulse::Tick).count();
+        (ticks, pulses.len() - ticks)
+    }
+
+    #[test]
+    fn frames_and_ticks_are_scheduled_at_their_own_rates() {
+        let ms = Duration::from_millis;
+        assert_eq!(count(Schedule::new(ms(250), ms(50)), ms(1000)), (4, 20));
+        assert_eq!(count(Schedule::new(ms(100), ms(300)), ms(1000)), (10, 4));
+        let first: Vec<_> = Schedule::new(ms(100), ms(100)).take(3).collect();
+        assert_eq!(
+            first,
+            vec![
+                (ms(0), Pulse::Tick),
+                (ms(0), Pulse::Frame),
+                (ms(100), Pulse::Tick)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_new_tick_rate_spaces_the_next_tick_from_the_last() {
+        let ms = Duration::from_millis;
+        let mut schedule = Schedule::new(ms(250), ms(1000));
+        assert_eq!(schedule.next(), Some((ms(0), Pulse::Tick)));
+        schedule.set_tick_rate(ms(100));
+        assert_eq!(schedule.next(), Some((ms(0), Pulse::Frame)));
+        assert_eq!(schedule.next(), Some((ms(100), Pulse::Tick)));
+        assert_eq!(schedule.next(), Some((ms(200), Pulse::Tick)));
+    }
+}
@@ -0,0 +1,353 @@
+use crate::difficulty::Difficulty;
+use crate::enemy::EnemyKind;
+use crate::map::Map;
+use nalgebra::Vector2;
+use std::collections::HashMap;
+
+/// Ticks the player gets to build before each wave.
+pub const BUILD_TICKS: u32 = 15;
+/// Ticks between spawns in a generated wave.
+pub const SPAWN_INTERVAL: u32 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Waiting for the next wave, which starts once `ticks_left` runs out.
+    Build {
+        ticks_left: u32,
+    },
+    Combat,
+}
+
+/// The enemies of one wave, in the order they spawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wave {
+    pub enemies: Vec<EnemyKind>,
+    /// Ticks between one enemy spawning at each spawn point and the next,
+    /// or zero to send the whole wave in at once.
+    pub spawn_interval: u32,
+    /// Whether the wave comes in from random cells along the edge of the
+    /// map instead of from its spawn points. Maps without spawn points
+    /// always spawn from the edge.
+    pub edge_spawns: bool,
+    pub formation: Formation,
+    /// Which of the map's spawn points the wave comes in from, as indices in
+    /// row-major order; empty for all of them.
+    pub active_spawns: Vec<usize>,
+    /// How much tougher each enemy is than the one spawned before it, as a
+    /// share of its usual hp; see `ramped_hp`.
+    pub hp_ramp: f64,
+    /// Whether this is the sudden-death wave closing out the game; see
+    /// `Wave::sudden_death`.
+    pub sudden_death: bool,
+}
+
+/// Tuning for spawn pressure, where the quicker a wave is cleared the
+/// bigger the next one comes in, and the slower the smaller.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pressure {
+    /// Ticks a wave is expected to take to clear for every enemy in it.
+    pub par_ticks: f64,
+    /// The least the next wave's size is multiplied by.
+    pub min: f64,
+    /// The most the next wave's size is multiplied by.
+    pub max: f64,
+}
+
+impl Default for Pressure {
+    fn default() -> Pressure {
+        Pressure {
+            par_ticks: 6.0,
+            min: 0.75,
+            max: 1.5,
+        }
+    }
+}
+
+impl Pressure {
+    /// What the size of the wave after one of `size` enemies, cleared in
+    /// `clear_ticks`, is multiplied by: how many times over par the clear
+    /// was, kept between `min` and `max`.
+    pub fn scale(&self, clear_ticks: u64, size: usize) -> f64 {
+        let par = self.par_ticks * size as f64;
+        (par / clear_ticks.max(1) as f64).clamp(self.min, self.max)
+    }
+}
+
+/// The shape a group of enemies comes in at a spawn point in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Formation {
+    /// One enemy at a time, on the spawn point.
+    #[default]
+    Single,
+    /// Four in single file, heading in.
+    Line,
+    /// Six in a triangle with its tip two cells in from the spawn point.
+    Wedge,
+    /// Four in a two by two block.
+    Square,
+}
+
+impl Formation {
+    /// Where the members of the formation go relative to its spawn point,
+    /// as steps `(ahead, to the right)`; the spawn point itself is `(0, 0)`.
+    fn shape(self) -> &'static [(i32, i32)] {
+        match self {
+            Formation::Single => &[(0, 0)],
+            Formation::Line => &[(0, 0), (1, 0), (2, 0), (3, 0)],
+            Formation::Wedge => &[(2, 0), (1, -1), (1, 1), (0, -2), (0, 0), (0, 2)],
+            Formation::Square => &[(0, 0), (1, 0), (0, 1), (1, 1)],
+        }
+    }
+
+    /// How many enemies the formation holds with nothing in the way.
+    pub fn size(self) -> usize {
+        self.shape().len()
+    }
+
+    /// The offsets from the spawn point of each member of the formation,
+    /// for one heading onto the map in the direction `forward`.
+    pub fn offsets(self, forward: Vector2<i32>) -> Vec<Vector2<i32>> {
+        let right = Vector2::new(-forward.y, forward.x);
+        self.shape()
+            .iter()
+            .map(|&(ahead, side)| forward * ahead + right * side)
+            .collect()
+    }
+
+    /// The cells the formation fills around the spawn point `s` on `m`,
+    /// heading in the direction `forward`, leaving out any that enemies
+    /// couldn't stand on. The spawn point itself is always kept.
+    pub fn cells(self, m: &Map, s: Vector2<usize>, forward: Vector2<i32>) -> Vec<Vector2<usize>> {
+        let origin = s.map(|c| c as i32);
+        self.offsets(forward)
+            .into_iter()
+            .map(|offset| origin + offset)
+            .filter(|&t| {
+                let cell = t.map(|c| c as usize);
+                t == origin || m.in_bounds(t) && m.passable(cell)
+            })
+            .map(|t| t.map(|c| c as usize))
+            .collect()
+    }
+}
+
+impl Wave {
+    /// The `index`th wave of a game, counting from zero. Waves grow steadily,
+    /// and from the third wave on they take turns coming in as lines, wedges
+    /// and squares. On normal difficulty the other kinds join the basic
+    /// enemies from these waves on, counting from one:
+    ///
+    /// | wave | kinds joining            |
+    /// |------|--------------------------|
+    /// | 2    | breakers                 |
+    /// | 4    | regenerating, gliders    |
+    /// | 5    | splitters, rushers       |
+    /// | 6    | cloaked, evasive         |
+    /// | 7    | masons                   |
+    /// | 8    | shields                  |
+    pub fn generate(index: usize, difficulty: Difficulty) -> Wave {
+        let scale = |n: usize| (n as f64 * difficulty.wave_scale()).round() as usize;
+        let mut enemies = vec![EnemyKind::Basic; scale(4 + 2 * index)];
+        spread(&mut enemies, EnemyKind::Breaker, scale(index.div_ceil(2)));
+        spread(&mut enemies, EnemyKind::Regen, scale(index / 3));
+        spread(&mut enemies, EnemyKind::Splitter, scale(index / 4));
+        spread(&mut enemies, EnemyKind::Cloaked, scale(index / 5));
+        spread(&mut enemies, EnemyKind::Mason, scale(index / 6));
+        spread(&mut enemies, EnemyKind::Shield, scale(index / 7));
+        spread(&mut enemies, EnemyKind::Rusher, scale(index / 4));
+        spread(&mut enemies, EnemyKind::Glider, scale(index / 3));
+        spread(&mut enemies, EnemyKind::Evasive, scale(index / 5));
+        Wave {
+            enemies,
+            spawn_interval: SPAWN_INTERVAL,
+            edge_spawns: false,
+            formation: match index {
+                0 | 1 => Formation::Single,
+                _ => [Formation::Line, Formation::Wedge, Formation::Square][index % 3],
+            },
+            active_spawns: Vec::new(),
+            hp_ramp: 0.0,
+            sudden_death: false,
+        }
+    }
+
+    /// A last, climactic wave to follow `index` ordinary ones: the wave that
+    /// would come next, with a couple more of every kind mixed in, all sent
+    /// in at once, and a boss bringing up the rear, or two on hard.
+    pub fn sudden_death(index: usize, difficulty: Difficulty) -> Wave {
+        let scale = |n: usize| (n as f64 * difficulty.wave_scale()).round() as usize;
+        let mut wave = Wave::generate(index, difficulty);
+        for &kind in EnemyKind::ALL.iter() {
+            if kind != EnemyKind::Basic && kind != EnemyKind::Boss {
+                spread(&mut wave.enemies, kind, scale(2));
+            }
+        }
+        let bosses = scale(1).max(1);
+        let len = wave.enemies.len();
+        wave.enemies.resize(len + bosses, EnemyKind::Boss);
+        wave.spawn_interval = 0;
+        wave.sudden_death = true;
+        wave
+    }
+
+    /// Grows or shrinks the wave to `factor` times its size, rounded and
+    /// never below one enemy, keeping its mix of kinds and their order.
+    /// Bosses are left out of it: however the rest of the wave is scaled,
+    /// exactly as many come as before, at the back.
+    pub fn rescale(&mut self, factor: f64) {
+        let (bosses, rest): (Vec<_>, Vec<_>) = self
+            .enemies
+            .iter()
+            .partition(|&&kind| kind == EnemyKind::Boss);
+        let len = rest.len();
+        if len == 0 {
+            return;
+        }
+        let new_len = ((len as f64 * factor).round() as usize).max(1);
+        self.enemies = (0..new_len).map(|i| rest[i * len / new_len]).collect();
+        self.enemies.extend(bosses);
+    }
+
+    /// How many enemies of each kind the wave holds.
+    pub fn summary(&self) -> HashMap<EnemyKind, usize> {
+        let mut counts = HashMap::new();
+        for kind in self.enemies.iter() {
+            *counts.entry(*kind).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// The hp of the `index`th enemy to spawn in a wave, counting from zero, for
+/// an enemy with `base` hp and a wave whose hp ramps up by `ramp`: the first
+/// gets `base`, and every later one `ramp` times `base` more than the last.
+pub fn ramped_hp(base: u32, ramp: f64, index: usize) -> u32 {
+    (base as f64 * (1.0 + ramp * index as f64)).round() as u32
+}
+
+/// Inserts `count` enemies of `kind` evenly through `enemies` rather than
+/// bunching them up at one end.
+fn spread(enemies: &mut Vec<EnemyKind>, kind: EnemyKind, count: usize) {
+    let total = enemies.len() + count;
+    for i in 0..count {
+        enemies.insert(i * total / count, kind);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressure_grows_fast_clears_and_shrinks_slow_ones_within_bounds() {
+        let pressure = Pressure::default();
+        let fast = pressure.scale(1, 10);
+        let slow = pressure.scale(10_000, 10);
+        assert!(fast > slow);
+        assert_eq!(fast, pressure.max);
+        assert_eq!(slow, pressure.min);
+        assert_eq!(pressure.scale(60, 10), 1.0);
+    }
+
+    #[test]
+    fn rescale_keeps_the_mix_and_never_drops_a_boss() {
+        let mut wave = Wave::sudden_death(9, Difficulty::Normal);
+        let bosses = wave.summary()[&EnemyKind::Boss];
+        let len = wave.enemies.len();
+        wave.rescale(0.5);
+        assert_eq!(wave.summary()[&EnemyKind::Boss], bosses);
+        assert_eq!(wave.enemies.len(), (len - bosses).div_ceil(2) + bosses);
+        assert_eq!(wave.enemies.last(), Some(&EnemyKind::Boss));
+        wave.rescale(3.0);
+        assert_eq!(wave.summary()[&EnemyKind::Boss], bosses);
+    }
+
+    #[test]
+    fn summaries_count_every_kind_in_the_wave() {
+        use EnemyKind::*;
+        let summary = Wave::generate(6, Difficulty::Normal).summary();
+        let expected: HashMap<_, _> = [
+            (Basic, 16),
+            (Breaker, 3),
+            (Regen, 2),
+            (Splitter, 1),
+            (Cloaked, 1),
+            (Mason, 1),
+            (Rusher, 1),
+            (Glider, 2),
+            (Evasive, 1),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        assert_eq!(summary, expected);
+        let hard = Wave::generate(6, Difficulty::Hard).summary();
+        assert_eq!((hard[&Basic], hard[&Breaker]), (24, 5));
+        assert!(!hard.contains_key(&Shield));
+    }
+
+    #[test]
+    fn a_wedge_points_ahead_and_loses_the_cells_it_cannot_fill() {
+        let east = Vector2::new(1, 0);
+        let offsets: Vec<_> = Formation::Wedge
+            .offsets(east)
+            .iter()
+            .map(|o| (o.x, o.y))
+            .collect();
+        assert_eq!(offsets, [(2, 0), (1, -1), (1, 1), (0, -2), (0, 0), (0, 2)]);
+        let south = Formation::Wedge.offsets(Vector2::new(0, 1));
+        assert_eq!((south[0].x, south[0].y), (0, 2));
+        assert_eq!((south[1].x, south[1].y), (1, 1));
+
+        let m = Map::new("#######\n#     #\n#     #\n^     #\n#     #\n#######").unwrap();
+        let cells: Vec<_> = Formation::Wedge
+            .cells(&m, Vector2::new(0, 3), east)
+            .iter()
+            .map(|c| (c.x, c.y))
+            .collect();
+        assert_eq!(cells, [(2, 3), (1, 2), (1, 4), (0, 3)]);
+    }
+
+    #[test]
+    fn the_sudden_death_wave_outdoes_every_wave_before_it() {
+        let hp = |wave: &Wave| wave.enemies.iter().map(|k| k.max_hp()).sum::<u32>();
+        let last = Wave::sudden_death(5, Difficulty::Normal);
+        assert!(last.sudden_death);
+        assert_eq!(last.spawn_interval, 0);
+        assert_eq!(last.enemies.last(), Some(&EnemyKind::Boss));
+        for index in 0..5 {
+            let wave = Wave::generate(index, Difficulty::Normal);
+            assert!(!wave.sudden_death);
+            assert!(last.enemies.len() > wave.enemies.len());
+            assert!(hp(&last) > hp(&wave));
+        }
+        let hard = Wave::sudden_death(5, Difficulty::Hard);
+        assert!(hard.enemies.len() > last.enemies.len());
+        assert!(hard.summary()[&EnemyKind::Boss] >= 2);
+    }
+
+    #[test]
+    fn kinds_join_the_waves_from_when_the_schedule_says() {
+        use EnemyKind::*;
+        let schedule = [
+            (Breaker, 2),
+            (Regen, 4),
+            (Glider, 4),
+            (Splitter, 5),
+            (Rusher, 5),
+            (Cloaked, 6),
+            (Evasive, 6),
+            (Mason, 7),
+            (Shield, 8),
+        ];
+        let has = |wave: usize, kind| {
+            Wave::generate(wave - 1, Difficulty::Normal)
+                .enemies
+                .contains(&kind)
+        };
+        for &(kind, first) in schedule.iter() {
+            assert!(!has(first - 1, kind), "{:?}", kind);
+            assert!(has(first, kind), "{:?}", kind);
+        }
+    }
+}